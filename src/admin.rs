@@ -0,0 +1,164 @@
+//! Admin REST endpoints for the supervised processes, mounted by `cdd-ctl` and `cdd-ctl-wasm`.
+//!
+//! - `GET /daemons`: [`DaemonStatus`] of every configured server.
+//! - `GET /daemons/{name}`: status of one server.
+//! - `POST /daemons/{name}/restart`, `/stop`, `/start`: control one server; the request is
+//!   applied asynchronously and answered with `202 Accepted` and the current status.
+//! - `GET /daemons/{name}/logs?tail=N`: the last `N` (default [`DEFAULT_TAIL`]) captured
+//!   [`LogLine`]s of one server; with `&follow=true`, a `text/event-stream` of them as `log`
+//!   events that stays open for new lines.
+//!
+//! Every route requires a bearer token whose caller is an administrator (see
+//! [`auth::require_admin`]). Unknown servers are `404 Not Found`; external ones cannot be
+//! controlled and are `409 Conflict`.
+
+use std::convert::Infallible;
+
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{middleware, web, HttpRequest, HttpResponse};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use futures_util::stream::{self, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth;
+use crate::logs::LogLine;
+use crate::mcp_http::{sse_event, KEEPALIVE};
+use crate::supervisor::{DaemonEvent, DaemonEventKind, DaemonStatus, Supervisor, SupervisorError};
+
+/// Lines returned by the log-tail route when `tail` is not given.
+pub const DEFAULT_TAIL: usize = 100;
+
+/// Mounts the `/daemons` routes. Requires `web::Data<Supervisor>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/daemons")
+            .wrap(middleware::from_fn(auth::resolve_caller))
+            .wrap(HttpAuthentication::bearer(auth::validator))
+            .route("", web::get().to(list_daemons))
+            .route("/{name}", web::get().to(get_daemon))
+            .route("/{name}/restart", web::post().to(restart_daemon))
+            .route("/{name}/stop", web::post().to(stop_daemon))
+            .route("/{name}/start", web::post().to(start_daemon))
+            .route("/{name}/logs", web::get().to(daemon_logs)),
+    );
+}
+
+/// `GET /daemons`
+async fn list_daemons(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    auth::require_admin(&req)?;
+    Ok(HttpResponse::Ok().json(supervisor.list()))
+}
+
+/// `GET /daemons/{name}`
+async fn get_daemon(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    auth::require_admin(&req)?;
+    Ok(HttpResponse::Ok().json(status(&supervisor, &name)?))
+}
+
+/// `POST /daemons/{name}/restart`
+async fn restart_daemon(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    control(&supervisor, &req, &name, Supervisor::restart)
+}
+
+/// `POST /daemons/{name}/stop`
+async fn stop_daemon(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    control(&supervisor, &req, &name, Supervisor::stop)
+}
+
+/// `POST /daemons/{name}/start`
+async fn start_daemon(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    control(&supervisor, &req, &name, Supervisor::start)
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    tail: Option<usize>,
+    #[serde(default)]
+    follow: bool,
+}
+
+/// `GET /daemons/{name}/logs`
+async fn daemon_logs(
+    supervisor: web::Data<Supervisor>,
+    req: HttpRequest,
+    name: web::Path<String>,
+    query: web::Query<LogsQuery>,
+) -> actix_web::Result<HttpResponse> {
+    auth::require_admin(&req)?;
+    let tail = query.tail.unwrap_or(DEFAULT_TAIL);
+    if !query.follow {
+        return Ok(HttpResponse::Ok().json(supervisor.logs(&name, tail)?));
+    }
+
+    let (backlog, events) = supervisor.follow_logs(&name, tail)?;
+    let backlog = stream::iter(backlog).map(|line| Ok::<_, Infallible>(log_event(&line)));
+    let state = (name.into_inner(), events, tokio::time::interval(KEEPALIVE));
+    let live = stream::unfold(state, |(name, mut events, mut keepalive)| async move {
+        let chunk = loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(DaemonEvent {
+                        name: source,
+                        kind: DaemonEventKind::Log { timestamp_ms, stream, line },
+                    }) if source == name => {
+                        break log_event(&LogLine { timestamp_ms, stream, line });
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        break Bytes::from(format!(": {} events missed\n\n", missed));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = keepalive.tick() => break Bytes::from_static(b": keepalive\n\n"),
+            }
+        };
+        Some((Ok(chunk), (name, events, keepalive)))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(backlog.chain(live)))
+}
+
+fn log_event(line: &LogLine) -> Bytes {
+    sse_event("log", &serde_json::to_string(line).unwrap_or_default())
+}
+
+fn control(
+    supervisor: &Supervisor,
+    req: &HttpRequest,
+    name: &str,
+    action: fn(&Supervisor, &str) -> Result<(), SupervisorError>,
+) -> actix_web::Result<HttpResponse> {
+    let caller = auth::require_admin(req)?;
+    action(supervisor, name)?;
+    log::info!("{} requested by {}", req.path(), caller.subject());
+    Ok(HttpResponse::Accepted().json(status(supervisor, name)?))
+}
+
+fn status(supervisor: &Supervisor, name: &str) -> Result<DaemonStatus, SupervisorError> {
+    supervisor
+        .status(name)
+        .ok_or_else(|| SupervisorError::UnknownDaemon(name.to_string()))
+}
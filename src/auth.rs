@@ -0,0 +1,158 @@
+//! Authentication for the endpoints `cdd-ctl` mounts next to the gateway's API.
+//!
+//! Bearer tokens are checked by the gateway's own middleware ([`validator`]), so one login
+//! grants access to both and tokens are verified in a single place. [`resolve_caller`] then
+//! applies the gateway's role model: a caller is an administrator when they hold the
+//! [`OWNER_ROLE`] in the organization configured as `admin_organization` (see [`Admins`]).
+//! The result is stored in the request extensions as a [`Caller`].
+//!
+//! Every authenticated scope is wrapped the same way, innermost first:
+//!
+//! ```ignore
+//! web::scope("/daemons")
+//!     .wrap(middleware::from_fn(auth::resolve_caller))
+//!     .wrap(HttpAuthentication::bearer(auth::validator))
+//! ```
+
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest};
+
+pub use cdd_gateway::api::auth_middleware::{validator, Claims};
+
+use crate::CddRepository;
+
+/// Query parameter accepted in place of the `Authorization` header, for clients such as
+/// browser WebSockets that cannot set headers (see [`token_from_query`]).
+pub const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+/// Organization role of the gateway granting control over the supervised processes.
+pub const OWNER_ROLE: &str = "owner";
+
+/// The authenticated caller of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    subject: String,
+    is_admin: bool,
+}
+
+impl Caller {
+    /// A caller identified by the token subject `subject`.
+    pub fn new(subject: impl Into<String>, is_admin: bool) -> Self {
+        Self {
+            subject: subject.into(),
+            is_admin,
+        }
+    }
+
+    /// The token's subject (the user).
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Whether the caller owns the configured `admin_organization`.
+    pub fn is_admin(&self) -> bool {
+        self.is_admin
+    }
+}
+
+/// Looks up administrators in the gateway's organizations. Registered as `web::Data<Admins>`.
+pub struct Admins {
+    repo: Arc<dyn CddRepository>,
+    organization: Option<String>,
+}
+
+impl Admins {
+    /// Owners of `organization` in `repo` are administrators; nobody is without one.
+    pub fn new(repo: Arc<dyn CddRepository>, organization: Option<String>) -> Self {
+        Self { repo, organization }
+    }
+
+    /// Whether `subject` holds the [`OWNER_ROLE`] in the admin organization.
+    pub async fn is_admin(&self, subject: &str) -> bool {
+        let Some(organization) = &self.organization else {
+            return false;
+        };
+        match self.repo.get_organization_role(organization, subject).await {
+            Ok(role) => role.as_deref() == Some(OWNER_ROLE),
+            Err(e) => {
+                log::warn!("Failed to look up the role of {}: {}", subject, e);
+                false
+            }
+        }
+    }
+}
+
+/// Middleware storing the [`Caller`] of a request authenticated by [`validator`], which must
+/// wrap it. Without `web::Data<Admins>` in the app data, nobody is an administrator.
+pub async fn resolve_caller(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let subject = req
+        .extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing bearer token"))?;
+    let is_admin = match req.app_data::<web::Data<Admins>>() {
+        Some(admins) => admins.is_admin(&subject).await,
+        None => false,
+    };
+    req.extensions_mut().insert(Caller::new(subject, is_admin));
+    next.call(req).await
+}
+
+/// Middleware copying the [`ACCESS_TOKEN_PARAM`] query parameter into the `Authorization`
+/// header when the request has none, so that [`validator`] sees it. Register it outside
+/// [`validator`].
+pub async fn token_from_query(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if !req.headers().contains_key(header::AUTHORIZATION) {
+        let token = query_token(req.query_string())
+            .and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok());
+        if let Some(value) = token {
+            req.headers_mut().insert(header::AUTHORIZATION, value);
+        }
+    }
+    next.call(req).await
+}
+
+/// The caller of `req` if they are an administrator, or `403 Forbidden`.
+///
+/// Must run behind [`resolve_caller`], which stores the caller.
+pub fn require_admin(req: &HttpRequest) -> Result<Caller, actix_web::Error> {
+    req.extensions()
+        .get::<Caller>()
+        .filter(|caller| caller.is_admin())
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorForbidden("Administrator role required"))
+}
+
+fn query_token(query: &str) -> Option<&str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == ACCESS_TOKEN_PARAM)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_token_is_read_from_the_query() {
+        assert_eq!(query_token("access_token=abc"), Some("abc"));
+        assert_eq!(query_token("a=1&access_token=abc&b=2"), Some("abc"));
+        assert_eq!(query_token("access_token="), None);
+        assert_eq!(query_token("token=abc"), None);
+        assert_eq!(query_token(""), None);
+    }
+}
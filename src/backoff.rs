@@ -0,0 +1,201 @@
+//! Restart backoff and circuit breaking for supervised servers.
+//!
+//! `ProcessConfig` waits a fixed `restart_delay_ms` between restarts and gives up after
+//! `max_retries`. A [`Backoff`] can instead grow the delay exponentially up to a cap and
+//! randomise it, so that servers brought down together by a host hiccup do not all restart in
+//! lockstep. A [`CircuitBreaker`] keeps a server that exhausted its retries from staying dead
+//! until the next full restart: the circuit opens, and after `cool_down_ms` it goes half-open
+//! and allows one more attempt. A process that then stays up for
+//! [`STABLE_UPTIME`](crate::supervisor::STABLE_UPTIME) closes it; one that exits reopens it.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How the delay before a restart grows with consecutive failed starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackoffStrategy {
+    /// Always wait `restart_delay_ms`.
+    #[default]
+    Fixed,
+    /// Wait `restart_delay_ms`, then multiply the delay by `multiplier` after every further
+    /// failed start, up to `max_delay_ms`.
+    Exponential,
+}
+
+/// Delay between restarts, configured as `servers.<name>.backoff`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Backoff {
+    /// How the delay grows.
+    pub strategy: BackoffStrategy,
+    /// Factor applied per failed start by [`BackoffStrategy::Exponential`].
+    pub multiplier: u32,
+    /// Upper bound of an exponential delay, in milliseconds.
+    pub max_delay_ms: u64,
+    /// Up to this percentage of each delay is shaved off at random; `100` is "full jitter".
+    pub jitter_percent: u8,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            strategy: BackoffStrategy::Fixed,
+            multiplier: 2,
+            max_delay_ms: 300_000,
+            jitter_percent: 0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Checks that the settings describe a usable delay.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.multiplier == 0 {
+            return Err("`multiplier` must be positive".to_string());
+        }
+        if self.max_delay_ms == 0 {
+            return Err("`max_delay_ms` must be positive".to_string());
+        }
+        if self.jitter_percent > 100 {
+            return Err(format!(
+                "`jitter_percent` must be at most 100, got {}",
+                self.jitter_percent
+            ));
+        }
+        Ok(())
+    }
+
+    /// The delay before the restart following the `retries`-th consecutive failed start, given
+    /// the server's `restart_delay_ms`.
+    pub fn delay(&self, restart_delay_ms: u64, retries: u32) -> Duration {
+        let delay_ms = match self.strategy {
+            BackoffStrategy::Fixed => restart_delay_ms,
+            BackoffStrategy::Exponential => {
+                let factor = u64::from(self.multiplier)
+                    .checked_pow(retries.saturating_sub(1))
+                    .unwrap_or(u64::MAX);
+                restart_delay_ms
+                    .saturating_mul(factor)
+                    .min(self.max_delay_ms)
+            }
+        };
+        let jitter = delay_ms as f64 * f64::from(self.jitter_percent) / 100.0 * random_fraction();
+        Duration::from_millis(delay_ms.saturating_sub(jitter as u64))
+    }
+}
+
+/// Retrying servers that exhausted `max_retries`, configured as
+/// `servers.<name>.circuit_breaker`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreaker {
+    /// Milliseconds the circuit stays open before a half-open attempt.
+    pub cool_down_ms: u64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            cool_down_ms: 300_000,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Checks that the cool-down is usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cool_down_ms == 0 {
+            return Err("`cool_down_ms` must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// State of a server's circuit, as shown in daemon status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Restarts follow the backoff.
+    #[default]
+    Closed,
+    /// `max_retries` was exhausted; waiting out the cool-down.
+    Open,
+    /// Making one attempt after the cool-down.
+    HalfOpen,
+}
+
+/// A pseudo-random fraction in `[0, 1)`; good enough to spread restarts apart.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().hash_one(Instant::now());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exponential(multiplier: u32, max_delay_ms: u64) -> Backoff {
+        Backoff {
+            strategy: BackoffStrategy::Exponential,
+            multiplier,
+            max_delay_ms,
+            jitter_percent: 0,
+        }
+    }
+
+    #[test]
+    fn fixed_delay_ignores_retries() {
+        let backoff = Backoff::default();
+        for retries in [0, 1, 10] {
+            assert_eq!(backoff.delay(2000, retries), Duration::from_millis(2000));
+        }
+    }
+
+    #[test]
+    fn exponential_delay_grows_up_to_the_cap() {
+        let backoff = exponential(3, 10_000);
+        let delays: Vec<u64> = (1..=5)
+            .map(|retries| backoff.delay(500, retries).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1500, 4500, 10_000, 10_000]);
+        assert_eq!(backoff.delay(500, 0), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn exponential_delay_does_not_overflow() {
+        let backoff = exponential(u32::MAX, u64::MAX);
+        assert_eq!(
+            backoff.delay(u64::MAX, u32::MAX),
+            Duration::from_millis(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let backoff = Backoff {
+            jitter_percent: 50,
+            ..Backoff::default()
+        };
+        for _ in 0..100 {
+            let delay = backoff.delay(1000, 1);
+            assert!((Duration::from_millis(500)..=Duration::from_millis(1000)).contains(&delay));
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(Backoff::default().validate().is_ok());
+        assert!(exponential(0, 1).validate().is_err());
+        assert!(exponential(2, 0).validate().is_err());
+        let backoff = Backoff {
+            jitter_percent: 101,
+            ..Backoff::default()
+        };
+        assert!(backoff.validate().is_err());
+        assert!(CircuitBreaker { cool_down_ms: 0 }.validate().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Concurrent `from_openapi` generation across several languages.
+//!
+//! Used by `cdd-ctl from_openapi --languages go,rust,ts <target> ...` (or `--all`) to regenerate
+//! many SDKs in one invocation. Each language writes into its own `<output>/<lang>/` directory
+//! and a failing generator never aborts the others.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::executor::Executor;
+use crate::toolchain::executable_name;
+
+/// Outcome of generating one language.
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageResult {
+    /// Language identifier, e.g. `go`.
+    pub language: String,
+    /// Directory the generator was asked to write into.
+    pub output_dir: PathBuf,
+    /// Whether the generator exited successfully.
+    pub success: bool,
+    /// Exit code of the generator, if it ran to completion.
+    pub exit_code: Option<i32>,
+    /// Captured stderr, or the spawn error.
+    pub stderr: String,
+    /// Wall-clock time spent on this language.
+    pub duration: Duration,
+}
+
+/// Splits an `-o <dir>` / `--output <dir>` / `--output=<dir>` argument out of `args`.
+///
+/// Returns the remaining arguments and the output directory, if one was given.
+pub fn take_output_dir(args: &[String]) -> (Vec<String>, Option<PathBuf>) {
+    let mut rest = Vec::with_capacity(args.len());
+    let mut output = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-o" || arg == "--output" {
+            output = iter.next().map(PathBuf::from);
+        } else if let Some(dir) = arg.strip_prefix("--output=") {
+            output = Some(PathBuf::from(dir));
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    (rest, output)
+}
+
+/// Runs `cdd-<lang> from_openapi <target> <args> -o <output>/<lang>` for every language under
+/// `executor`'s runtime, with at most `jobs` generators running at once.
+///
+/// Results are returned in the order of `languages`.
+pub async fn from_openapi_many(
+    executor: &Executor,
+    languages: &[String],
+    target: &str,
+    args: &[String],
+    output: &Path,
+    jobs: usize,
+) -> Vec<LanguageResult> {
+    let permits = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut set = JoinSet::new();
+
+    for (idx, language) in languages.iter().enumerate() {
+        let executable = executable_name(language);
+        let language = executable
+            .strip_prefix("cdd-")
+            .unwrap_or(&executable)
+            .to_string();
+        let output_dir = output.join(&language);
+        let target = target.to_string();
+        let args = args.to_vec();
+        let permits = permits.clone();
+        let executor = executor.clone();
+
+        set.spawn(async move {
+            // The semaphore is never closed, so acquiring only fails if it is dropped.
+            let _permit = permits.acquire_owned().await.ok();
+            let started = Instant::now();
+            let mut result = LanguageResult {
+                language,
+                output_dir: output_dir.clone(),
+                success: false,
+                exit_code: None,
+                stderr: String::new(),
+                duration: Duration::ZERO,
+            };
+
+            if let Err(e) = tokio::fs::create_dir_all(&output_dir).await {
+                result.stderr = format!("Failed to create {}: {}", output_dir.display(), e);
+                return (idx, result);
+            }
+
+            let mut full_args = vec!["from_openapi".to_string(), target];
+            full_args.extend(args);
+            full_args.push("-o".to_string());
+            full_args.push(output_dir.to_string_lossy().into_owned());
+
+            match executor.output(&executable, &full_args).await {
+                Ok(output) => {
+                    result.success = output.success();
+                    result.exit_code = output.code;
+                    result.stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+                }
+                Err(e) => result.stderr = format!("Failed to execute {}: {}", executable, e),
+            }
+            result.duration = started.elapsed();
+            (idx, result)
+        });
+    }
+
+    let mut results: Vec<Option<LanguageResult>> = vec![None; languages.len()];
+    while let Some(joined) = set.join_next().await {
+        if let Ok((idx, result)) = joined {
+            results[idx] = Some(result);
+        }
+    }
+    results
+        .into_iter()
+        .zip(languages)
+        .map(|(result, language)| {
+            result.unwrap_or_else(|| LanguageResult {
+                language: language.clone(),
+                output_dir: output.join(language),
+                success: false,
+                exit_code: None,
+                stderr: "generator task panicked".to_string(),
+                duration: Duration::ZERO,
+            })
+        })
+        .collect()
+}
+
+/// Renders a one-line-per-language summary of `results`.
+pub fn summary(results: &[LanguageResult]) -> String {
+    let mut out = String::new();
+    for r in results {
+        if r.success {
+            out.push_str(&format!(
+                "ok    {:<12} {:>7.1}s  {}\n",
+                r.language,
+                r.duration.as_secs_f64(),
+                r.output_dir.display()
+            ));
+        } else {
+            let reason = r.stderr.lines().last().unwrap_or_default().trim();
+            let code = r
+                .exit_code
+                .map_or_else(|| "-".to_string(), |c| c.to_string());
+            out.push_str(&format!(
+                "FAIL  {:<12} {:>7.1}s  exit {}: {}\n",
+                r.language,
+                r.duration.as_secs_f64(),
+                code,
+                reason
+            ));
+        }
+    }
+    let failed = results.iter().filter(|r| !r.success).count();
+    out.push_str(&format!(
+        "{} succeeded, {} failed\n",
+        results.len() - failed,
+        failed
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn output_dir_is_taken_out_of_the_arguments() {
+        for output in [
+            args(&["-o", "out"]),
+            args(&["--output", "out"]),
+            args(&["--output=out"]),
+        ] {
+            let mut all = args(&["-i", "spec.json"]);
+            all.extend(output);
+            all.push("--verbose".to_string());
+            assert_eq!(
+                take_output_dir(&all),
+                (
+                    args(&["-i", "spec.json", "--verbose"]),
+                    Some(PathBuf::from("out"))
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn output_dir_is_optional() {
+        assert_eq!(
+            take_output_dir(&args(&["-i", "spec.json"])),
+            (args(&["-i", "spec.json"]), None)
+        );
+        assert_eq!(take_output_dir(&args(&["-o"])), (Vec::new(), None));
+        assert_eq!(
+            take_output_dir(&args(&["-o", "a", "--output=b"])),
+            (Vec::new(), Some(PathBuf::from("b")))
+        );
+    }
+}
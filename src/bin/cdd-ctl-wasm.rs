@@ -6,17 +6,33 @@
 //! cdd-ctl: Daemon manage >13 processes and act as API gateway and authentication layer.
 #![allow(unused_imports)]
 
-use actix_web::{web, App, HttpServer};
+use actix_web::{middleware, web, App, HttpServer};
 use cdd_ctl::{api, db};
-use clap::{Parser, Subcommand};
+use clap::Parser;
 use log::{error, info};
-use std::process::Command;
+use std::process::ExitCode;
 use std::sync::Arc;
 
+use cdd_ctl::admin;
+use cdd_ctl::auth;
+use cdd_ctl::cli::{self, Commands};
+use cdd_ctl::config::validate_servers;
+use cdd_ctl::logging::{self, LogFormat};
+use cdd_ctl::mcp::McpAggregator;
+use cdd_ctl::mcp_http::{self, McpState};
+use cdd_ctl::metrics;
+use cdd_ctl::readiness;
+use cdd_ctl::reload;
+use cdd_ctl::rpc::RpcContext;
+use cdd_ctl::rpc_ws;
+use cdd_ctl::sandbox;
+use cdd_ctl::shutdown;
+use cdd_ctl::telemetry;
 use cdd_ctl::AppConfig;
+use cdd_ctl::Supervisor;
 use cdd_ctl::{CddRepository, PgRepository};
+use cdd_ctl::{Executor, RuntimeSelector, ServeMode};
 use cdd_ctl::{GitHubClient, ReqwestGitHubClient};
-use cdd_ctl::{ProcessConfig, ProcessManager};
 
 #[derive(Parser, Debug)]
 #[command(name = "cdd-ctl-wasm", author, version, about, long_about = None)]
@@ -26,7 +42,7 @@ struct Args {
     /// Optional subcommands
     command: Option<Commands>,
 
-    /// Path to configuration file (JSON/YAML/TOML) (JSON/YAML/TOML)
+    /// Path to configuration file (JSON/YAML/TOML)
     #[arg(short, long)]
     /// Path to configuration file (JSON/YAML/TOML)
     config: Option<String>,
@@ -35,180 +51,35 @@ struct Args {
     #[arg(short, long)]
     /// Override the bind address
     bind: Option<String>,
-}
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    /// Generate JSON documentation with code snippets for an OpenAPI specification.
-    #[command(name = "to_docs_json")]
-    ToDocsJson {
-        /// Target language
-        target_language: String,
-
-        /// Additional arguments to pass to the tool
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
-
-    /// Generate code from an OpenAPI specification.
-    #[command(name = "from_openapi")]
-    FromOpenApi {
-        /// Target language
-        target_language: String,
-
-        /// The generation target (e.g., to_sdk, to_server, to_orm)
-        target: String,
-
-        /// Remaining arguments to pass to the target language CLI
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
-
-    /// Generate an OpenAPI specification from source code.
-    #[command(name = "to_openapi")]
-    ToOpenApi {
-        /// Target language
-        target_language: String,
-
-        /// Remaining arguments to pass to the target language CLI
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
+    /// Runtime used to execute the `cdd-*` toolchains
+    #[arg(long, value_enum, default_value_t = RuntimeSelector::Wasm)]
+    runtime: RuntimeSelector,
+
+    /// Override the configured WASM resource limits
+    #[command(flatten)]
+    wasm_limits: cli::WasmLimitArgs,
+
+    /// Format of the log records
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> std::io::Result<ExitCode> {
     dotenvy::dotenv().ok();
     std::env::set_var("WASM_EXECUTION_MODE", "1");
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let args = Args::parse();
+    cli::init_logging(args.config.as_deref(), args.log_format);
 
-    match args.command {
-        Some(Commands::ToDocsJson {
-            target_language,
-            args: extra_args,
-        }) => {
-            let target = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-
-            let mut cmd = Command::new("wasmtime");
-
-            // WebAssembly GC for Kotlin
-            if target == "cdd-kotlin" {
-                cmd.arg("--wasm-features=gc");
-            }
-
-            let wasm_file = format!("cdd-ctl-wasm-sdk/assets/wasm/{}.wasm", target);
-
-            cmd.arg("--dir=."); // Mount current dir
-
-            cmd.arg(&wasm_file);
-
-            // Pass arguments to WASM binary
-            cmd.arg("--");
-            cmd.arg("to_docs_json");
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute wasmtime: {}", e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        Some(Commands::FromOpenApi {
-            target_language,
-            target,
-            args: extra_args,
-        }) => {
-            let executable = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-
-            let mut cmd = Command::new("wasmtime");
-
-            // WebAssembly GC for Kotlin
-            if executable == "cdd-kotlin" {
-                cmd.arg("--wasm-features=gc");
-            }
-
-            let wasm_file = format!("cdd-ctl-wasm-sdk/assets/wasm/{}.wasm", executable);
-            cmd.arg("--dir=."); // Mount current dir
-            cmd.arg(&wasm_file);
-            cmd.arg("--");
-
-            cmd.arg("from_openapi");
-            cmd.arg(&target);
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute wasmtime for {}: {}", executable, e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        Some(Commands::ToOpenApi {
-            target_language,
-            args: extra_args,
-        }) => {
-            let executable = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-
-            let mut cmd = Command::new("wasmtime");
-
-            // WebAssembly GC for Kotlin
-            if executable == "cdd-kotlin" {
-                cmd.arg("--wasm-features=gc");
-            }
-
-            let wasm_file = format!("cdd-ctl-wasm-sdk/assets/wasm/{}.wasm", executable);
-            cmd.arg("--dir=."); // Mount current dir
-            cmd.arg(&wasm_file);
-            cmd.arg("--");
-
-            cmd.arg("to_openapi");
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute wasmtime for {}: {}", executable, e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        None => {}
+    let executor = Executor::new(args.runtime)
+        .with_wasm_limits(cli::wasm_limits(args.config.as_deref(), &args.wasm_limits));
+
+    if let Some(command) = args.command {
+        return cli::run(command, args.config.as_deref(), executor)
+            .await
+            .map(|()| ExitCode::SUCCESS);
     }
 
     let mut app_config = match AppConfig::load(args.config.as_deref()) {
@@ -223,77 +94,106 @@ async fn main() -> std::io::Result<()> {
         app_config.server_bind = bind;
     }
 
+    let default_servers = executor.process_configs(ServeMode::Mcp);
     if app_config.servers.is_empty() {
         info!("No servers configured, populating with default WASM execution engines.");
-        let wasm_tools = [
-            "cdd-c",
-            "cdd-cpp",
-            "cdd-csharp",
-            "cdd-go",
-            "cdd-java",
-            "cdd-kotlin",
-            "cdd-php",
-            "cdd-python",
-            "cdd-python-all",
-            "cdd-ruby",
-            "cdd-rust",
-            "cdd-swift",
-            "cdd-ts",
-        ];
-        for tool in wasm_tools {
-            app_config.servers.insert(
-                tool.to_string(),
-                cdd_engine::daemon::ProcessConfig {
-                    command: Some("wasmtime".to_string()),
-                    args: Some(vec![
-                        format!("cdd-ctl-wasm-sdk/assets/wasm/{}.wasm", tool),
-                        "serve_json_rpc".to_string(),
-                    ]),
-                    external_address: None,
-                    max_retries: 5,
-                    restart_delay_ms: 2000,
-                },
-            );
-        }
+        app_config.servers.extend(default_servers.clone());
     }
 
+    let ctl_config = cli::ctl_config(args.config.as_deref());
+    if let Err(e) = validate_servers(&app_config.servers, &ctl_config.servers) {
+        error!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    }
+    let _tracing = match telemetry::init(&ctl_config.tracing, env!("CARGO_BIN_NAME")) {
+        Ok(guard) => guard,
+        Err(e) => {
+            error!("Invalid tracing configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     info!("Starting cdd-ctl-wasm server on {}", app_config.server_bind);
 
-    let process_manager = Arc::new(ProcessManager::new(app_config.servers.clone()));
+    let supervisor =
+        Arc::new(Supervisor::new(app_config.servers.clone()).with_options(&ctl_config.servers));
 
-    let pm_clone = process_manager.clone();
-    if let Err(e) = pm_clone.start_all().await {
+    if let Err(e) = supervisor.start_all().await {
         error!("Error starting processes: {}", e);
     }
 
+    // Applies changes to `servers` on SIGHUP or when the config file is modified.
+    let watcher = reload::watch(args.config.clone(), supervisor.clone(), default_servers);
+
     // Connect to PG Database
     let pool = db::establish_connection_pool(&app_config.database_url);
     let repo = Arc::new(PgRepository { pool });
 
+    // Administrators are the owners of `admin_organization`.
+    let admins = web::Data::new(auth::Admins::new(
+        repo.clone(),
+        ctl_config.admin_organization.clone(),
+    ));
+
     // Configure GitHub Client
     let github_client = Arc::new(ReqwestGitHubClient::new(
         std::env::var("GITHUB_CLIENT_ID").unwrap_or_default(),
         std::env::var("GITHUB_CLIENT_SECRET").unwrap_or_default(),
     ));
 
+    // `/mcp` speaks MCP to the supervised `mcp` servers, initialized on the first request.
+    let aggregator = Arc::new(McpAggregator::new(supervisor.clone()));
+    let mcp_state = web::Data::new(McpState::new(aggregator.clone()));
+
+    // Web UIs follow the daemons (and can call the RPC methods) over `/ws`.
+    let rpc_context = web::Data::new(
+        RpcContext::new(supervisor.clone())
+            .with_mcp(aggregator)
+            .with_executor(executor.clone()),
+    );
+    let supervisor_data = web::Data::from(supervisor.clone());
+
     let bind_addr = app_config.server_bind.clone();
 
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(middleware::from_fn(shutdown::track_jobs))
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .wrap(middleware::from_fn(telemetry::trace_requests))
+            .wrap(middleware::from_fn(logging::assign_request_id))
             .app_data(web::Data::new(repo.clone() as Arc<dyn CddRepository>))
             .app_data(web::Data::new(
                 github_client.clone() as Arc<dyn GitHubClient>
             ))
+            .app_data(mcp_state.clone())
+            .app_data(rpc_context.clone())
+            .app_data(supervisor_data.clone())
+            .app_data(admins.clone())
+            .configure(readiness::configure)
+            .configure(metrics::configure)
             .configure(api::configure)
+            .configure(mcp_http::configure)
+            .configure(rpc_ws::configure)
+            .configure(admin::configure)
             .service(api::swagger_ui())
     })
     .bind(&bind_addr)?
+    .disable_signals()
+    .shutdown_timeout(ctl_config.shutdown.server_timeout_secs())
     .run();
 
-    let result = server.await;
+    // Returns on SIGTERM or SIGINT, once in-flight work has drained.
+    let drained = shutdown::serve(server, &ctl_config.shutdown).await;
+
+    watcher.abort();
 
     // Shutdown processes
-    process_manager.stop_all().await;
+    let stopped = supervisor.stop_all().await;
+
+    // Sandboxes of jobs cut short by the shutdown.
+    if let Err(e) = sandbox::remove_jobs_dir() {
+        error!("Failed to remove the jobs directory: {}", e);
+    }
 
-    result
+    Ok(shutdown::exit_code(drained? && stopped))
 }
@@ -6,19 +6,33 @@
 //! cdd-ctl: Daemon manage >13 processes and act as API gateway and authentication layer.
 #![allow(unused_imports)]
 
-use actix_web::{web, App, HttpServer};
+use actix_web::{middleware, web, App, HttpServer};
 use cdd_ctl::{api, db};
-use clap::{Parser, Subcommand};
+use clap::Parser;
 use log::{error, info};
-use std::process::Command;
+use std::process::ExitCode;
 use std::sync::Arc;
-use tokio::io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader};
 
+use cdd_ctl::admin;
+use cdd_ctl::auth;
+use cdd_ctl::cli::{self, Commands};
+use cdd_ctl::config::validate_servers;
+use cdd_ctl::logging::{self, LogFormat};
+use cdd_ctl::mcp::McpAggregator;
+use cdd_ctl::mcp_http::{self, McpState};
+use cdd_ctl::metrics;
+use cdd_ctl::readiness;
+use cdd_ctl::reload;
+use cdd_ctl::rpc::RpcContext;
+use cdd_ctl::rpc_ws;
+use cdd_ctl::sandbox;
+use cdd_ctl::shutdown;
+use cdd_ctl::telemetry;
 use cdd_ctl::AppConfig;
+use cdd_ctl::Supervisor;
 use cdd_ctl::{CddRepository, PgRepository};
+use cdd_ctl::{Executor, RuntimeSelector, ServeMode};
 use cdd_ctl::{GitHubClient, ReqwestGitHubClient};
-use cdd_engine::daemon::{ProcessConfig, ProcessManager};
-use cdd_engine::mcp::{McpOrchestrator, McpRequest, McpResponse};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -31,209 +45,34 @@ struct Args {
 
     #[arg(short, long)]
     bind: Option<String>,
-}
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    #[command(name = "to_docs_json")]
-    ToDocsJson {
-        target_language: String,
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
-    #[command(name = "from_openapi")]
-    FromOpenApi {
-        target_language: String,
-        target: String,
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
-    #[command(name = "to_openapi")]
-    ToOpenApi {
-        target_language: String,
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
-        args: Vec<String>,
-    },
-    #[command(name = "mcp")]
-    Mcp { target_language: Option<String> },
+    /// Runtime used to execute the `cdd-*` toolchains
+    #[arg(long, value_enum, default_value_t = RuntimeSelector::Native)]
+    runtime: RuntimeSelector,
+
+    /// Override the configured WASM resource limits
+    #[command(flatten)]
+    wasm_limits: cli::WasmLimitArgs,
+
+    /// Format of the log records
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> std::io::Result<ExitCode> {
     dotenvy::dotenv().ok();
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let args = Args::parse();
+    cli::init_logging(args.config.as_deref(), args.log_format);
 
-    match args.command {
-        Some(Commands::ToDocsJson {
-            target_language,
-            args: extra_args,
-        }) => {
-            let target = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-            let mut cmd = Command::new(&target);
-            cmd.arg("to_docs_json");
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute {}: {}", target, e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        Some(Commands::FromOpenApi {
-            target_language,
-            target,
-            args: extra_args,
-        }) => {
-            let executable = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-            let mut cmd = Command::new(&executable);
-            cmd.arg("from_openapi").arg(&target);
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute {}: {}", executable, e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        Some(Commands::ToOpenApi {
-            target_language,
-            args: extra_args,
-        }) => {
-            let executable = if target_language.starts_with("cdd-") {
-                target_language.clone()
-            } else {
-                format!("cdd-{}", target_language)
-            };
-            let mut cmd = Command::new(&executable);
-            cmd.arg("to_openapi");
-            for arg in extra_args {
-                cmd.arg(arg);
-            }
-
-            let output = cmd.output().unwrap_or_else(|e| {
-                eprintln!("Failed to execute {}: {}", executable, e);
-                std::process::exit(1);
-            });
-
-            if !output.status.success() {
-                std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
-                std::process::exit(output.status.code().unwrap_or(1));
-            }
-            std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
-            return Ok(());
-        }
-        Some(Commands::Mcp { target_language }) => {
-            if let Some(lang) = target_language {
-                let executable = if lang.starts_with("cdd-") {
-                    lang
-                } else {
-                    format!("cdd-{}", lang)
-                };
-                let mut cmd = Command::new(&executable);
-                cmd.arg("mcp");
-
-                let mut child = cmd.spawn().unwrap_or_else(|e| {
-                    eprintln!("Failed to spawn {}: {}", executable, e);
-                    std::process::exit(1);
-                });
-                let status = child.wait()?;
-                std::process::exit(status.code().unwrap_or(1));
-            } else {
-                let mut app_config = match AppConfig::load(args.config.as_deref()) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        error!("Failed to load configuration: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-
-                if app_config.servers.is_empty() {
-                    let native_tools = [
-                        "cdd-c",
-                        "cdd-cpp",
-                        "cdd-csharp",
-                        "cdd-go",
-                        "cdd-java",
-                        "cdd-kotlin",
-                        "cdd-php",
-                        "cdd-python",
-                        "cdd-python-all",
-                        "cdd-ruby",
-                        "cdd-rust",
-                        "cdd-sh",
-                        "cdd-swift",
-                        "cdd-ts",
-                    ];
-                    for tool in native_tools {
-                        app_config.servers.insert(
-                            tool.to_string(),
-                            ProcessConfig {
-                                command: Some(tool.to_string()),
-                                args: Some(vec!["mcp".to_string()]),
-                                external_address: None,
-                                max_retries: 5,
-                                restart_delay_ms: 2000,
-                            },
-                        );
-                    }
-                }
-
-                let process_manager = Arc::new(ProcessManager::new(app_config.servers.clone()));
-                if let Err(e) = process_manager.start_all().await {
-                    error!("Error starting MCP processes: {}", e);
-                    std::process::exit(1);
-                }
-
-                let mut stdin_reader = BufReader::new(stdin()).lines();
-                let mut stdout_writer = stdout();
-
-                while let Ok(Some(line)) = stdin_reader.next_line().await {
-                    if let Ok(req) = serde_json::from_str::<McpRequest>(&line) {
-                        let response = process_manager.handle_request(req).await;
-                        let res_json = match response {
-                            Ok(res) => serde_json::to_string(&res).unwrap_or_default(),
-                            Err(e) => serde_json::to_string(&serde_json::json!({
-                                "jsonrpc": "2.0",
-                                "error": { "message": e.to_string() }
-                            }))
-                            .unwrap_or_default(),
-                        };
-                        let msg = format!("{}\n", res_json);
-                        let _ = stdout_writer.write_all(msg.as_bytes()).await;
-                    }
-                }
-
-                process_manager.stop_all().await;
-                return Ok(());
-            }
-        }
-        None => {}
+    let executor = Executor::new(args.runtime)
+        .with_wasm_limits(cli::wasm_limits(args.config.as_deref(), &args.wasm_limits));
+
+    if let Some(command) = args.command {
+        return cli::run(command, args.config.as_deref(), executor)
+            .await
+            .map(|()| ExitCode::SUCCESS);
     }
 
     let mut app_config = match AppConfig::load(args.config.as_deref()) {
@@ -248,74 +87,108 @@ async fn main() -> std::io::Result<()> {
         app_config.server_bind = bind;
     }
 
+    let default_servers = executor.process_configs(ServeMode::Mcp);
     if app_config.servers.is_empty() {
-        info!("No servers configured, populating with default native dependencies.");
-        let native_tools = [
-            "cdd-c",
-            "cdd-cpp",
-            "cdd-csharp",
-            "cdd-go",
-            "cdd-java",
-            "cdd-kotlin",
-            "cdd-php",
-            "cdd-python",
-            "cdd-python-all",
-            "cdd-ruby",
-            "cdd-rust",
-            "cdd-sh",
-            "cdd-swift",
-            "cdd-ts",
-        ];
-        for tool in native_tools {
-            app_config.servers.insert(
-                tool.to_string(),
-                ProcessConfig {
-                    command: Some(tool.to_string()),
-                    args: Some(vec!["mcp".to_string()]),
-                    external_address: None,
-                    max_retries: 5,
-                    restart_delay_ms: 2000,
-                },
-            );
-        }
+        info!(
+            "No servers configured, populating with default dependencies for the {:?} runtime.",
+            args.runtime
+        );
+        app_config.servers.extend(default_servers.clone());
     }
 
+    let ctl_config = cli::ctl_config(args.config.as_deref());
+    if let Err(e) = validate_servers(&app_config.servers, &ctl_config.servers) {
+        error!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    }
+    let _tracing = match telemetry::init(&ctl_config.tracing, env!("CARGO_BIN_NAME")) {
+        Ok(guard) => guard,
+        Err(e) => {
+            error!("Invalid tracing configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     info!("Starting cdd-ctl server on {}", app_config.server_bind);
 
-    let process_manager = Arc::new(ProcessManager::new(app_config.servers.clone()));
+    let supervisor =
+        Arc::new(Supervisor::new(app_config.servers.clone()).with_options(&ctl_config.servers));
 
-    let pm_clone = process_manager.clone();
-    if let Err(e) = pm_clone.start_all().await {
+    if let Err(e) = supervisor.start_all().await {
         error!("Error starting processes: {}", e);
     }
 
+    // Applies changes to `servers` on SIGHUP or when the config file is modified.
+    let watcher = reload::watch(args.config.clone(), supervisor.clone(), default_servers);
+
     // Connect to PG Database
     let pool = db::establish_connection_pool(&app_config.database_url);
     let repo = Arc::new(PgRepository { pool });
 
+    // Administrators are the owners of `admin_organization`.
+    let admins = web::Data::new(auth::Admins::new(
+        repo.clone(),
+        ctl_config.admin_organization.clone(),
+    ));
+
     // Configure GitHub Client
     let github_client = Arc::new(ReqwestGitHubClient::new(
         std::env::var("GITHUB_CLIENT_ID").unwrap_or_default(),
         std::env::var("GITHUB_CLIENT_SECRET").unwrap_or_default(),
     ));
 
+    // `/mcp` speaks MCP to the supervised `mcp` servers, initialized on the first request.
+    let aggregator = Arc::new(McpAggregator::new(supervisor.clone()));
+    let mcp_state = web::Data::new(McpState::new(aggregator.clone()));
+
+    // Web UIs follow the daemons (and can call the RPC methods) over `/ws`.
+    let rpc_context = web::Data::new(
+        RpcContext::new(supervisor.clone())
+            .with_mcp(aggregator)
+            .with_executor(executor.clone()),
+    );
+    let supervisor_data = web::Data::from(supervisor.clone());
+
     let bind_addr = app_config.server_bind.clone();
 
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(middleware::from_fn(shutdown::track_jobs))
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .wrap(middleware::from_fn(telemetry::trace_requests))
+            .wrap(middleware::from_fn(logging::assign_request_id))
             .app_data(web::Data::new(repo.clone() as Arc<dyn CddRepository>))
             .app_data(web::Data::new(
                 github_client.clone() as Arc<dyn GitHubClient>
             ))
+            .app_data(mcp_state.clone())
+            .app_data(rpc_context.clone())
+            .app_data(supervisor_data.clone())
+            .app_data(admins.clone())
+            .configure(readiness::configure)
+            .configure(metrics::configure)
             .configure(api::configure)
+            .configure(mcp_http::configure)
+            .configure(rpc_ws::configure)
+            .configure(admin::configure)
     })
     .bind(&bind_addr)?
+    .disable_signals()
+    .shutdown_timeout(ctl_config.shutdown.server_timeout_secs())
     .run();
 
-    let result = server.await;
+    // Returns on SIGTERM or SIGINT, once in-flight work has drained.
+    let drained = shutdown::serve(server, &ctl_config.shutdown).await;
+
+    watcher.abort();
 
     // Shutdown processes
-    process_manager.stop_all().await;
+    let stopped = supervisor.stop_all().await;
+
+    // Sandboxes of jobs cut short by the shutdown.
+    if let Err(e) = sandbox::remove_jobs_dir() {
+        error!("Failed to remove the jobs directory: {}", e);
+    }
 
-    result
+    Ok(shutdown::exit_code(drained? && stopped))
 }
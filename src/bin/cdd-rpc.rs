@@ -6,16 +6,31 @@
 //! cdd-ctl: Daemon manage >13 processes and act as API gateway and authentication layer.
 #![allow(unused_imports)]
 
-use actix_web::{web, App, HttpServer};
-use cdd_ctl::{api, db};
+use actix_web::{middleware, web, App, HttpServer};
 use clap::Parser;
 use log::{error, info};
+use std::process::ExitCode;
 use std::sync::Arc;
 
+use cdd_ctl::auth;
+use cdd_ctl::cli;
+
+use cdd_ctl::config::validate_servers;
+use cdd_ctl::db;
+use cdd_ctl::logging::{self, LogFormat};
+use cdd_ctl::metrics;
+use cdd_ctl::readiness;
+use cdd_ctl::reload;
+use cdd_ctl::rpc::RpcContext;
+use cdd_ctl::sandbox;
+use cdd_ctl::shutdown;
+use cdd_ctl::telemetry;
+use cdd_ctl::wasm::WasmLimitsConfig;
 use cdd_ctl::AppConfig;
+use cdd_ctl::Supervisor;
+use cdd_ctl::{rpc_http, rpc_ws};
 use cdd_ctl::{CddRepository, PgRepository};
-use cdd_ctl::{GitHubClient, ReqwestGitHubClient};
-use cdd_ctl::{ProcessConfig, ProcessManager};
+use cdd_ctl::{Runtime, ServeMode, ToolchainRegistry};
 
 #[derive(Parser, Debug)]
 #[command(name = "cdd-rpc", author, version, about, long_about = None)]
@@ -30,14 +45,18 @@ struct Args {
     #[arg(short, long)]
     /// Override the bind address
     bind: Option<String>,
+
+    /// Format of the log records
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> std::io::Result<ExitCode> {
     dotenvy::dotenv().ok();
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let args = Args::parse();
+    cli::init_logging(args.config.as_deref(), args.log_format);
 
     let mut app_config = match AppConfig::load(args.config.as_deref()) {
         Ok(c) => c,
@@ -51,75 +70,90 @@ async fn main() -> std::io::Result<()> {
         app_config.server_bind = bind;
     }
 
+    let default_servers = ToolchainRegistry::builtin().process_configs(
+        Runtime::Native,
+        ServeMode::JsonRpc,
+        &WasmLimitsConfig::default(),
+    );
     if app_config.servers.is_empty() {
         info!("No servers configured, populating with default native dependencies.");
-        let native_tools = [
-            "cdd-c",
-            "cdd-cpp",
-            "cdd-csharp",
-            "cdd-go",
-            "cdd-java",
-            "cdd-kotlin",
-            "cdd-php",
-            "cdd-python",
-            "cdd-python-all",
-            "cdd-ruby",
-            "cdd-rust",
-            "cdd-sh",
-            "cdd-swift",
-            "cdd-ts",
-        ];
-        for tool in native_tools {
-            app_config.servers.insert(
-                tool.to_string(),
-                cdd_engine::daemon::ProcessConfig {
-                    command: Some(tool.to_string()),
-                    args: Some(vec!["serve_json_rpc".to_string()]),
-                    external_address: None,
-                    max_retries: 5,
-                    restart_delay_ms: 2000,
-                },
-            );
-        }
+        app_config.servers.extend(default_servers.clone());
+    }
+
+    let ctl_config = cli::ctl_config(args.config.as_deref());
+    if let Err(e) = validate_servers(&app_config.servers, &ctl_config.servers) {
+        error!("Invalid configuration: {}", e);
+        std::process::exit(1);
     }
+    let _tracing = match telemetry::init(&ctl_config.tracing, env!("CARGO_BIN_NAME")) {
+        Ok(guard) => guard,
+        Err(e) => {
+            error!("Invalid tracing configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     info!("Starting cdd-rpc server on {}", app_config.server_bind);
 
-    let process_manager = Arc::new(ProcessManager::new(app_config.servers.clone()));
+    let supervisor =
+        Arc::new(Supervisor::new(app_config.servers.clone()).with_options(&ctl_config.servers));
 
-    let pm_clone = process_manager.clone();
-    if let Err(e) = pm_clone.start_all().await {
+    if let Err(e) = supervisor.start_all().await {
         error!("Error starting processes: {}", e);
     }
 
-    // Connect to PG Database
-    let pool = db::establish_connection_pool(&app_config.database_url);
-    let repo = Arc::new(PgRepository { pool });
+    // Applies changes to `servers` on SIGHUP or when the config file is modified.
+    let watcher = reload::watch(args.config.clone(), supervisor.clone(), default_servers);
+
+    // `cdd.*` jobs are handed to the supervised servers, each in its own sandbox.
+    let rpc_context = web::Data::new(RpcContext::new(supervisor.clone()));
+    let supervisor_data = web::Data::from(supervisor.clone());
 
-    // Configure GitHub Client
-    let github_client = Arc::new(ReqwestGitHubClient::new(
-        std::env::var("GITHUB_CLIENT_ID").unwrap_or_default(),
-        std::env::var("GITHUB_CLIENT_SECRET").unwrap_or_default(),
-    ));
+    // Administrators are the owners of `admin_organization` in the gateway's database, which is
+    // only connected to when one is configured.
+    let admins = ctl_config.admin_organization.clone().map(|organization| {
+        let pool = db::establish_connection_pool(&app_config.database_url);
+        let repo: Arc<dyn CddRepository> = Arc::new(PgRepository { pool });
+        web::Data::new(auth::Admins::new(repo, Some(organization)))
+    });
 
     let bind_addr = app_config.server_bind.clone();
 
     let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(repo.clone() as Arc<dyn CddRepository>))
-            .app_data(web::Data::new(
-                github_client.clone() as Arc<dyn GitHubClient>
-            ))
-            .configure(api::configure)
-            .service(api::swagger_ui())
+            .wrap(middleware::from_fn(shutdown::track_jobs))
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .wrap(middleware::from_fn(telemetry::trace_requests))
+            .wrap(middleware::from_fn(logging::assign_request_id))
+            .app_data(rpc_context.clone())
+            .app_data(supervisor_data.clone())
+            .configure(|cfg| {
+                if let Some(admins) = &admins {
+                    cfg.app_data(admins.clone());
+                }
+            })
+            .configure(readiness::configure)
+            .configure(metrics::configure)
+            .configure(rpc_http::configure)
+            .configure(rpc_ws::configure)
     })
     .bind(&bind_addr)?
+    .disable_signals()
+    .shutdown_timeout(ctl_config.shutdown.server_timeout_secs())
     .run();
 
-    let result = server.await;
+    // Returns on SIGTERM or SIGINT, once in-flight work has drained.
+    let drained = shutdown::serve(server, &ctl_config.shutdown).await;
+
+    watcher.abort();
 
     // Shutdown processes
-    process_manager.stop_all().await;
+    let stopped = supervisor.stop_all().await;
+
+    // Sandboxes of jobs cut short by the shutdown.
+    if let Err(e) = sandbox::remove_jobs_dir() {
+        error!("Failed to remove the jobs directory: {}", e);
+    }
 
-    result
+    Ok(shutdown::exit_code(drained? && stopped))
 }
@@ -1,16 +1,82 @@
 #![cfg(not(coverage))]
 #![cfg(not(tarpaulin_include))]
 #![deny(missing_docs)]
-//! This binary dumps the OpenAPI schema generated by utoipa to `openapi.json` at the root.
+//! This binary dumps the OpenAPI schema generated by utoipa (or the OpenRPC document of
+//! `cdd-rpc`) to `openapi.json` (or `openrpc.json`) at the root.
 
+use std::io::Write;
+
+use clap::{Parser, ValueEnum};
 use utoipa::OpenApi;
 
+/// Which interface description to dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Document {
+    /// The REST API of `cdd-ctl`.
+    Openapi,
+    /// The JSON-RPC API of `cdd-rpc`.
+    Openrpc,
+}
+
+/// Serialization format of the dumped document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Pretty-printed JSON.
+    Json,
+    /// YAML.
+    Yaml,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "dump_openapi", author, version, about, long_about = None)]
+/// Command line arguments
+struct Args {
+    /// Document to dump
+    #[arg(short, long, value_enum, default_value_t = Document::Openapi)]
+    document: Document,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// Output path, or `-` for stdout [default: `<document>.<format>`]
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
 /// Main function to dump the `openapi.json` schema.
 fn main() -> Result<(), cdd_engine::error::CddEngineError> {
-    let doc = cdd_gateway::api::ApiDoc::openapi();
-    let json = doc
-        .to_pretty_json()
-        .map_err(|e| cdd_engine::error::CddEngineError::Config(e.to_string()))?;
-    std::fs::write("openapi.json", json).map_err(cdd_engine::error::CddEngineError::Io)?;
+    let args = Args::parse();
+    let doc = match args.document {
+        Document::Openapi => {
+            serde_json::to_value(cdd_gateway::api::ApiDoc::openapi()).map_err(config_error)?
+        }
+        Document::Openrpc => cdd_ctl::openrpc::document(),
+    };
+    let (text, extension) = match args.format {
+        Format::Json => (
+            serde_json::to_string_pretty(&doc).map_err(config_error)?,
+            "json",
+        ),
+        Format::Yaml => (serde_norway::to_string(&doc).map_err(config_error)?, "yaml"),
+    };
+    let name = match args.document {
+        Document::Openapi => "openapi",
+        Document::Openrpc => "openrpc",
+    };
+    let output = args
+        .output
+        .unwrap_or_else(|| format!("{}.{}", name, extension));
+    if output == "-" {
+        std::io::stdout()
+            .write_all(text.as_bytes())
+            .map_err(cdd_engine::error::CddEngineError::Io)?;
+    } else {
+        std::fs::write(output, text).map_err(cdd_engine::error::CddEngineError::Io)?;
+    }
     Ok(())
 }
+
+fn config_error(e: impl std::fmt::Display) -> cdd_engine::error::CddEngineError {
+    cdd_engine::error::CddEngineError::Config(e.to_string())
+}
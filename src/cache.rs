@@ -0,0 +1,377 @@
+//! Persistent on-disk cache of precompiled WASM modules.
+//!
+//! Compiling the larger toolchains (`cdd-swift.wasm`, `cdd-ruby.wasm`, ...) takes seconds, so
+//! compiled artifacts are serialized to `$XDG_CACHE_HOME/cdd-ctl/` (or `~/.cache/cdd-ctl/`).
+//! Entries are keyed by the SHA-256 of the `.wasm` file together with the engine's
+//! compatibility hash (wasmtime version and configuration) and the enabled WASM features, so a
+//! rebuilt module or an upgraded engine simply misses and recompiles; `prune` removes the
+//! superseded entries.
+//!
+//! Artifacts are native code that is loaded without further checks, so the cache directory is
+//! kept private to the current user (mode `0700`) and is not read from when it is not.
+
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use wasmtime::{Engine, Module};
+
+use crate::executor::ExecError;
+use crate::metrics::{CacheLayer, Metrics};
+use crate::wasm::WasmEngine;
+
+const ARTIFACT_EXT: &str = "cwasm";
+const METADATA_EXT: &str = "json";
+
+/// Sidecar metadata stored next to every cached artifact.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Cache key (file stem of the artifact).
+    pub key: String,
+    /// Path of the `.wasm` module the artifact was compiled from.
+    pub source: PathBuf,
+    /// SHA-256 of the source module at compile time.
+    pub source_sha256: String,
+    /// WASM features the module was compiled with.
+    pub features: Vec<String>,
+    /// Size of the compiled artifact in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// Seconds since the Unix epoch when the artifact was written.
+    pub created: u64,
+}
+
+impl CacheEntry {
+    /// Whether the entry can no longer be hit: the source module is gone or has changed since
+    /// compilation, or the current engine for its features would produce a different key.
+    pub fn is_stale(&self, engines: &WasmEngine) -> bool {
+        let features: Vec<&str> = self.features.iter().map(String::as_str).collect();
+        match (sha256_file(&self.source), engines.engine(&features)) {
+            (Ok(hash), Ok(engine)) => cache_key(&engine, &hash, &self.features) != self.key,
+            _ => true,
+        }
+    }
+}
+
+/// Directory of serialized [`Module`]s.
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+    dir: PathBuf,
+}
+
+impl ModuleCache {
+    /// Uses `dir` as the cache directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Cache rooted at `$XDG_CACHE_HOME/cdd-ctl`, falling back to `$HOME/.cache/cdd-ctl`.
+    ///
+    /// Returns `None` when neither variable is set, or when `CDD_WASM_CACHE=0`.
+    pub fn from_env() -> Option<Self> {
+        if std::env::var("CDD_WASM_CACHE").is_ok_and(|v| v == "0") {
+            return None;
+        }
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::new(base.join("cdd-ctl")))
+    }
+
+    /// The cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Loads the precompiled artifact for `path`, compiling and storing it on a miss.
+    ///
+    /// Failing to write the cache is logged and otherwise ignored.
+    pub fn load_or_compile(
+        &self,
+        engine: &Engine,
+        path: &Path,
+        features: &[String],
+    ) -> Result<Module, ExecError> {
+        let source_sha256 = sha256_file(path)?;
+        let key = cache_key(engine, &source_sha256, features);
+        let artifact = self.artifact_path(&key);
+
+        if artifact.is_file() {
+            match check_private(&self.dir) {
+                // SAFETY: nobody but this user can write to the directory, into which artifacts
+                // are only ever written by `Module::serialize` below, and `deserialize_file`
+                // verifies that the artifact was produced by a compatible engine before using it.
+                Ok(()) => match unsafe { Module::deserialize_file(engine, &artifact) } {
+                    Ok(module) => {
+                        log::debug!("WASM cache hit for {}", path.display());
+                        Metrics::global().observe_wasm_cache(CacheLayer::Disk, true);
+                        return Ok(module);
+                    }
+                    Err(e) => log::warn!(
+                        "Discarding unusable cached artifact {}: {:#}",
+                        artifact.display(),
+                        e
+                    ),
+                },
+                Err(e) => log::warn!("Not loading {}: {}", artifact.display(), e),
+            }
+        }
+
+        log::info!("Compiling {} (WASM cache miss)", path.display());
+        Metrics::global().observe_wasm_cache(CacheLayer::Disk, false);
+        let module =
+            Module::from_file(engine, path).map_err(|e| ExecError::Wasm(format!("{:#}", e)))?;
+        let entry = CacheEntry {
+            key: key.clone(),
+            source: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            source_sha256,
+            features: features.to_vec(),
+            size: 0,
+            created: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        if let Err(e) = self.store(&module, entry) {
+            log::warn!("Failed to write WASM cache entry {}: {}", key, e);
+        }
+        Ok(module)
+    }
+
+    fn store(&self, module: &Module, mut entry: CacheEntry) -> io::Result<()> {
+        let bytes = module
+            .serialize()
+            .map_err(|e| io::Error::other(format!("{:#}", e)))?;
+        entry.size = bytes.len() as u64;
+        fs::create_dir_all(&self.dir)?;
+        // Fails, as it should, for a directory belonging to another user.
+        #[cfg(unix)]
+        fs::set_permissions(
+            &self.dir,
+            std::os::unix::fs::PermissionsExt::from_mode(0o700),
+        )?;
+
+        // Write to a temporary file and rename so that concurrent readers never observe a
+        // partially written artifact.
+        let artifact = self.artifact_path(&entry.key);
+        let tmp = artifact.with_extension(format!("{}.tmp{}", ARTIFACT_EXT, std::process::id()));
+        fs::write(&tmp, &bytes)?;
+        fs::rename(&tmp, &artifact)?;
+
+        let metadata = serde_json::to_vec_pretty(&entry).map_err(io::Error::other)?;
+        fs::write(self.metadata_path(&entry.key), metadata)
+    }
+
+    /// Lists the cached artifacts, oldest first.
+    pub fn list(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e),
+        };
+        for dir_entry in read_dir {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|ext| ext != METADATA_EXT) {
+                continue;
+            }
+            let entry = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<CacheEntry>(&bytes).ok());
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => log::warn!("Ignoring unreadable cache metadata {}", path.display()),
+            }
+        }
+        entries.sort_by_key(|e| e.created);
+        Ok(entries)
+    }
+
+    /// Removes entries that are stale with respect to `engines`, and entries older than
+    /// `max_age` if given.
+    ///
+    /// Returns the removed entries.
+    pub fn prune(
+        &self,
+        engines: &WasmEngine,
+        max_age: Option<Duration>,
+    ) -> io::Result<Vec<CacheEntry>> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut removed = Vec::new();
+        for entry in self.list()? {
+            let expired =
+                max_age.is_some_and(|age| now.saturating_sub(entry.created) > age.as_secs());
+            if expired || entry.is_stale(engines) {
+                self.remove(&entry.key)?;
+                removed.push(entry);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes every cached artifact. Returns the number of files deleted.
+    pub fn clear(&self) -> io::Result<usize> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut count = 0;
+        for dir_entry in read_dir {
+            let path = dir_entry?.path();
+            if path.is_file() {
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        for path in [self.artifact_path(key), self.metadata_path(key)] {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn artifact_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, ARTIFACT_EXT))
+    }
+
+    fn metadata_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, METADATA_EXT))
+    }
+}
+
+/// `<module sha256>-<sha256 of the engine compatibility hash and the features>`.
+fn cache_key(engine: &Engine, source_sha256: &str, features: &[String]) -> String {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    for feature in features {
+        hasher.0.update(feature.as_bytes());
+        hasher.0.update([0]);
+    }
+    format!("{}-{}", source_sha256, hex::encode(hasher.0.finalize()))
+}
+
+/// A [`Hasher`] feeding SHA-256, whose digest, unlike that of `DefaultHasher`, stays the same
+/// across Rust releases.
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        digest
+            .iter()
+            .take(8)
+            .fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+    }
+}
+
+/// Checks that only the current user can write to `dir`: it must belong to them and have mode
+/// `0700`.
+#[cfg(unix)]
+fn check_private(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(dir)?;
+    // SAFETY: `getuid` has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not private to the current user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The smallest valid module: the magic number and version.
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cdd-ctl-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn key_changes_with_the_module_and_features() {
+        let engine = Engine::default();
+        let simd = ["simd".to_string()];
+        let key = cache_key(&engine, "abc", &[]);
+        assert_eq!(key, cache_key(&engine, "abc", &[]));
+        assert!(key.starts_with("abc-"));
+        assert_eq!(key.len(), "abc-".len() + 64);
+        assert_ne!(key, cache_key(&engine, "abd", &[]));
+        assert_ne!(key, cache_key(&engine, "abc", &simd));
+    }
+
+    #[test]
+    fn compiled_modules_are_stored_and_hit() {
+        let dir = temp_dir("cache");
+        let source = dir.join("empty.wasm");
+        fs::write(&source, EMPTY_MODULE).unwrap();
+        let cache = ModuleCache::new(dir.join("modules"));
+        let engine = Engine::default();
+        assert!(cache.list().unwrap().is_empty());
+
+        cache.load_or_compile(&engine, &source, &[]).unwrap();
+        let entries = cache.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].key,
+            cache_key(&engine, &sha256_file(&source).unwrap(), &[])
+        );
+        assert!(cache.artifact_path(&entries[0].key).is_file());
+
+        check_private(cache.dir()).unwrap();
+        cache.load_or_compile(&engine, &source, &[]).unwrap();
+        assert_eq!(cache.list().unwrap().len(), 1);
+        assert_eq!(cache.clear().unwrap(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_directories_are_not_trusted() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("cache-shared");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o770)).unwrap();
+        assert!(check_private(&dir).is_err());
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        check_private(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
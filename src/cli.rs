@@ -0,0 +1,608 @@
+//! Subcommands shared by the `cdd-ctl` and `cdd-ctl-wasm` binaries.
+//!
+//! Both binaries parse the same [`Commands`] and hand them to [`run`]; they differ only in the
+//! default `--runtime` used to build the [`Executor`].
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use log::{error, info};
+use tokio::io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::admin::DEFAULT_TAIL;
+use crate::batch;
+use crate::doctor::{self, OutputFormat};
+use crate::executor::{ExecError, Executor};
+use crate::jsonrpc;
+use crate::logging::{self, LogFormat};
+use crate::logs;
+use crate::mcp::McpAggregator;
+use crate::toolchain::{executable_name, wasm_module_path, ServeMode, ToolchainRegistry};
+use crate::wasm::{Preopen, WasmEngine, WasmLimits, WasmLimitsConfig, WasmOptions};
+use crate::{AppConfig, CtlConfig, Supervisor};
+
+/// One-shot subcommands executed against a single toolchain (or a set of them).
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Generate JSON documentation with code snippets for an OpenAPI specification.
+    #[command(name = "to_docs_json")]
+    ToDocsJson {
+        /// Target language
+        target_language: String,
+
+        /// Additional arguments to pass to the tool
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Generate code from an OpenAPI specification.
+    #[command(name = "from_openapi")]
+    FromOpenApi {
+        /// Generate for several languages concurrently, e.g. `--languages go,rust,ts`
+        #[arg(long, value_delimiter = ',')]
+        languages: Vec<String>,
+
+        /// Generate for every language available under the selected runtime
+        #[arg(long, conflicts_with = "languages")]
+        all: bool,
+
+        /// Maximum number of generators run concurrently with --languages/--all
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+
+        /// Target language
+        #[arg(
+            required_unless_present_any = ["languages", "all"],
+            conflicts_with_all = ["languages", "all"]
+        )]
+        target_language: Option<String>,
+
+        /// Generation target, e.g. `to_sdk`
+        #[arg(required_unless_present_any = ["languages", "all"])]
+        target: Option<String>,
+
+        /// Additional arguments to pass to the tool
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// `<target> [args...]` given after `--` with --languages/--all
+        #[arg(
+            last = true,
+            value_name = "BATCH_ARGS",
+            conflicts_with = "target_language",
+            required_unless_present = "target_language"
+        )]
+        batch_args: Vec<String>,
+    },
+
+    /// Generate an OpenAPI specification from source code.
+    #[command(name = "to_openapi")]
+    ToOpenApi {
+        /// Target language
+        target_language: String,
+
+        /// Remaining arguments to pass to the target language CLI
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Run an MCP server over stdio, for one language or for every configured server.
+    #[command(name = "mcp")]
+    Mcp {
+        /// Target language; omit to serve every configured language
+        target_language: Option<String>,
+    },
+
+    /// Inspect the configured language toolchains.
+    #[command(name = "toolchain")]
+    Toolchain {
+        /// Toolchain subcommand
+        #[command(subcommand)]
+        command: ToolchainCommands,
+    },
+
+    /// Manage the on-disk cache of precompiled WASM modules.
+    #[command(name = "cache")]
+    Cache {
+        /// Cache subcommand
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Print the captured output of a supervised server, from a running gateway.
+    #[command(name = "logs")]
+    Logs {
+        /// Server name, e.g. `cdd-kotlin`
+        name: String,
+
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Number of past lines to print
+        #[arg(short = 'n', long, default_value_t = DEFAULT_TAIL)]
+        tail: usize,
+
+        /// Base URL of the gateway (default: derived from `server_bind`)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Bearer token of an administrator (default: the `CDD_CTL_TOKEN` environment variable)
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Subcommands used when the binary re-executes itself.
+    #[command(flatten)]
+    Internal(InternalCommands),
+}
+
+/// Environment variable holding the bearer token used by client subcommands such as `logs`.
+pub const TOKEN_VAR: &str = "CDD_CTL_TOKEN";
+
+/// `cache` subcommands.
+#[derive(Subcommand, Debug)]
+pub enum CacheCommands {
+    /// List the cached precompiled modules.
+    #[command(name = "list")]
+    List {
+        /// Output format of the listing
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Remove entries whose source module or engine has changed.
+    #[command(name = "prune")]
+    Prune {
+        /// Also remove entries older than this many days
+        #[arg(long)]
+        older_than_days: Option<u64>,
+    },
+    /// Remove every cached module.
+    #[command(name = "clear")]
+    Clear,
+}
+
+/// Hidden subcommands used when a binary re-executes itself.
+#[derive(Subcommand, Debug)]
+pub enum InternalCommands {
+    /// Run a `cdd-*.wasm` module with the embedded engine, connected to this process's stdio.
+    #[command(name = "wasm_run", hide = true)]
+    WasmRun {
+        /// Toolchain whose module should be run
+        language: String,
+
+        /// Host directory to expose to the module as `HOST[::GUEST]` (none by default)
+        #[arg(long = "dir")]
+        dirs: Vec<String>,
+
+        /// Resource limits for the module
+        #[command(flatten)]
+        limits: WasmLimitArgs,
+
+        /// Arguments passed to the module
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+/// `--wasm-*` flags overriding the configured [`WasmLimits`] for every language.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct WasmLimitArgs {
+    /// Fuel budget per WASM execution
+    #[arg(long = "wasm-fuel")]
+    pub fuel: Option<u64>,
+
+    /// Maximum linear memory per WASM execution, in bytes
+    #[arg(long = "wasm-max-memory-bytes")]
+    pub max_memory_bytes: Option<usize>,
+
+    /// Wall-clock deadline per WASM execution, in milliseconds
+    #[arg(long = "wasm-timeout-ms")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl WasmLimitArgs {
+    /// The limits given on the command line; unset flags are left unset.
+    pub fn limits(&self) -> WasmLimits {
+        WasmLimits {
+            fuel: self.fuel,
+            max_memory_bytes: self.max_memory_bytes,
+            timeout_ms: self.timeout_ms,
+        }
+    }
+}
+
+/// `toolchain` subcommands.
+#[derive(Subcommand, Debug)]
+pub enum ToolchainCommands {
+    /// Check that every configured backend is installed and responds to a version probe.
+    #[command(name = "doctor")]
+    Doctor {
+        /// Output format of the report
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+
+        /// Seconds to wait for each probe before failing it
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+    },
+}
+
+/// Executes `command`, exiting the process with the toolchain's status on failure.
+pub async fn run(
+    command: Commands,
+    config: Option<&str>,
+    executor: Executor,
+) -> std::io::Result<()> {
+    match command {
+        Commands::ToDocsJson {
+            target_language,
+            args,
+        } => {
+            let mut full_args = vec!["to_docs_json".to_string()];
+            full_args.extend(args);
+            run_to_completion(&executor, &target_language, &full_args).await
+        }
+        Commands::FromOpenApi {
+            languages,
+            all,
+            jobs,
+            target_language,
+            target,
+            args,
+            batch_args,
+        } => {
+            if all || !languages.is_empty() {
+                let languages = if all { executor.languages() } else { languages };
+                let Some((target, rest)) = batch_args.split_first() else {
+                    eprintln!("from_openapi: missing <target>");
+                    std::process::exit(2);
+                };
+                let (rest, output) = batch::take_output_dir(rest);
+                let output = output.unwrap_or_else(|| PathBuf::from("."));
+
+                let results =
+                    batch::from_openapi_many(&executor, &languages, target, &rest, &output, jobs)
+                        .await;
+                for r in results.iter().filter(|r| !r.success) {
+                    error!("{} failed:\n{}", r.language, r.stderr.trim_end());
+                }
+                print!("{}", batch::summary(&results));
+                std::process::exit(if results.iter().all(|r| r.success) {
+                    0
+                } else {
+                    1
+                });
+            }
+
+            // clap requires both positionals whenever --languages/--all is absent.
+            let (Some(target_language), Some(target)) = (target_language, target) else {
+                eprintln!("from_openapi: expected <target_language> <target>");
+                std::process::exit(2);
+            };
+            let mut full_args = vec!["from_openapi".to_string(), target];
+            full_args.extend(args);
+            run_to_completion(&executor, &target_language, &full_args).await
+        }
+        Commands::ToOpenApi {
+            target_language,
+            args,
+        } => {
+            let mut full_args = vec!["to_openapi".to_string()];
+            full_args.extend(args);
+            run_to_completion(&executor, &target_language, &full_args).await
+        }
+        Commands::Mcp {
+            target_language: Some(lang),
+        } => {
+            let invocation = executor
+                .invocation(&lang, &["mcp".to_string()])
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+            let mut child = invocation.std_command().spawn().unwrap_or_else(|e| {
+                eprintln!("Failed to spawn {}: {}", invocation.program, e);
+                std::process::exit(1);
+            });
+            let status = child.wait()?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        Commands::Mcp {
+            target_language: None,
+        } => {
+            let app_config = load_config(config, &executor);
+
+            let supervisor = Arc::new(
+                Supervisor::new(app_config.servers).with_options(&ctl_config(config).servers),
+            );
+            if let Err(e) = supervisor.start_all().await {
+                error!("Error starting processes: {}", e);
+            }
+            let aggregator = McpAggregator::new(supervisor.clone());
+            info!("Aggregating {} MCP backends", aggregator.backends().len());
+
+            let mut stdin_reader = BufReader::new(stdin()).lines();
+            let mut stdout_writer = stdout();
+
+            while let Ok(Some(line)) = stdin_reader.next_line().await {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let reply = jsonrpc::dispatch(&line, |request| {
+                    let aggregator = &aggregator;
+                    async move { aggregator.handle(&request.method, request.params).await }
+                })
+                .await;
+                if let Some(reply) = reply {
+                    let msg = format!("{}\n", reply);
+                    let _ = stdout_writer.write_all(msg.as_bytes()).await;
+                    let _ = stdout_writer.flush().await;
+                }
+            }
+
+            supervisor.stop_all().await;
+            Ok(())
+        }
+        Commands::Toolchain {
+            command:
+                ToolchainCommands::Doctor {
+                    format,
+                    timeout_secs,
+                },
+        } => {
+            let app_config = load_config(config, &executor);
+
+            let report =
+                doctor::diagnose(&app_config.servers, Duration::from_secs(timeout_secs)).await;
+            match format {
+                OutputFormat::Table => print!("{}", report.to_table()),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                ),
+            }
+            std::process::exit(if report.passed() { 0 } else { 1 });
+        }
+        Commands::Logs {
+            name,
+            follow,
+            tail,
+            url,
+            token,
+        } => {
+            let url = url.unwrap_or_else(|| gateway_url(config));
+            let token = token.or_else(|| std::env::var(TOKEN_VAR).ok());
+            if let Err(e) = logs::print_remote(&url, token.as_deref(), &name, tail, follow).await {
+                eprintln!("logs: {}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Cache { command } => run_cache(command),
+        Commands::Internal(command) => run_internal(command, config),
+    }
+}
+
+/// Executes a `cache` subcommand against the process-wide engine's on-disk cache.
+fn run_cache(command: CacheCommands) -> std::io::Result<()> {
+    let engine = WasmEngine::global();
+    let Some(cache) = engine.disk_cache() else {
+        eprintln!("The WASM module cache is disabled (no cache directory, or CDD_WASM_CACHE=0).");
+        std::process::exit(1);
+    };
+    match command {
+        CacheCommands::List { format } => {
+            let entries = cache.list()?;
+            match format {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&entries).unwrap_or_default()
+                ),
+                OutputFormat::Table => {
+                    println!("{}", cache.dir().display());
+                    for entry in &entries {
+                        println!(
+                            "{:>9.1} MiB  {}  {}{}",
+                            entry.size as f64 / (1024.0 * 1024.0),
+                            &entry.key[..12.min(entry.key.len())],
+                            entry.source.display(),
+                            if entry.features.is_empty() {
+                                String::new()
+                            } else {
+                                format!(" [{}]", entry.features.join(","))
+                            }
+                        );
+                    }
+                    println!("{} entries", entries.len());
+                }
+            }
+        }
+        CacheCommands::Prune { older_than_days } => {
+            let max_age = older_than_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let removed = cache.prune(engine, max_age)?;
+            for entry in &removed {
+                println!("removed {} ({})", entry.key, entry.source.display());
+            }
+            println!("{} entries pruned", removed.len());
+        }
+        CacheCommands::Clear => {
+            let count = cache.clear()?;
+            println!("{} files removed from {}", count, cache.dir().display());
+        }
+    }
+    Ok(())
+}
+
+/// Executes a hidden subcommand, exiting with the module's exit code.
+///
+/// `wasm_run` limits that are not given as flags are taken from the configuration. A module
+/// that runs into one exits with its [`LimitKind::exit_code`](crate::executor::LimitKind::exit_code).
+pub fn run_internal(command: InternalCommands, config: Option<&str>) -> ! {
+    match command {
+        InternalCommands::WasmRun {
+            language,
+            dirs,
+            limits,
+            args,
+        } => {
+            let executable = executable_name(&language);
+            let options = WasmOptions {
+                preopens: dirs.iter().map(|d| Preopen::parse(d)).collect(),
+                inherit_stdio: true,
+                limits: wasm_limits(config, &limits).for_language(&executable),
+                ..WasmOptions::default()
+            };
+            let features = ToolchainRegistry::builtin()
+                .get(&executable)
+                .map_or(&[][..], |t| t.wasm_features);
+            let path = PathBuf::from(wasm_module_path(&executable));
+
+            match WasmEngine::global().run(&path, features, &args, &options) {
+                Ok(output) => std::process::exit(output.exit_code),
+                Err(e) => {
+                    eprintln!("Failed to run {}: {}", path.display(), e);
+                    let code = match e {
+                        ExecError::LimitExceeded { limit, .. } => limit.exit_code(),
+                        _ => 1,
+                    };
+                    std::process::exit(code);
+                }
+            }
+        }
+    }
+}
+
+/// Installs the logger in `format`, or in the configured `log_format` when not given.
+pub fn init_logging(config: Option<&str>, format: Option<LogFormat>) {
+    let format = format.unwrap_or_else(|| {
+        CtlConfig::load(config)
+            .map(|c| c.log_format)
+            .unwrap_or_default()
+    });
+    logging::init(format);
+}
+
+/// Loads the [`CtlConfig`], exiting on error.
+pub fn ctl_config(config: Option<&str>) -> CtlConfig {
+    CtlConfig::load(config).unwrap_or_else(|e| {
+        error!("Failed to load configuration: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Loads the `wasm_limits` configuration, with the `--wasm-*` flags taking precedence.
+pub fn wasm_limits(config: Option<&str>, overrides: &WasmLimitArgs) -> WasmLimitsConfig {
+    ctl_config(config)
+        .wasm_limits
+        .overridden(overrides.limits())
+}
+
+/// `http://<server_bind>` of the configured gateway, reached over loopback when it binds every
+/// interface.
+fn gateway_url(config: Option<&str>) -> String {
+    let bind = AppConfig::load(config)
+        .map(|c| c.server_bind)
+        .unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    format!("http://{}", bind.replace("0.0.0.0", "127.0.0.1"))
+}
+
+/// Loads the configuration, filling in the default MCP servers for the executor's runtime.
+fn load_config(config: Option<&str>, executor: &Executor) -> AppConfig {
+    let mut app_config = match AppConfig::load(config) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if app_config.servers.is_empty() {
+        app_config
+            .servers
+            .extend(executor.process_configs(ServeMode::Mcp));
+    }
+    app_config
+}
+
+/// Runs `cdd-<lang> <args...>`, forwarding its stdout, or its stderr and exit code on failure.
+async fn run_to_completion(
+    executor: &Executor,
+    language: &str,
+    args: &[String],
+) -> std::io::Result<()> {
+    let output = executor.output(language, args).await.unwrap_or_else(|e| {
+        eprintln!("Failed to execute {}: {}", language, e);
+        std::process::exit(1);
+    });
+
+    if !output.success() {
+        std::io::Write::write_all(&mut std::io::stderr(), &output.stderr)?;
+        std::process::exit(output.code.unwrap_or(1));
+    }
+    std::io::Write::write_all(&mut std::io::stdout(), &output.stdout)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[command(subcommand)]
+        command: Commands,
+    }
+
+    fn parse(line: &str) -> Result<Commands, clap::Error> {
+        Cli::try_parse_from(std::iter::once("cdd-ctl").chain(line.split(' '))).map(|c| c.command)
+    }
+
+    #[test]
+    fn from_openapi_takes_a_positional_language() {
+        let Ok(Commands::FromOpenApi {
+            target_language,
+            target,
+            args,
+            batch_args,
+            ..
+        }) = parse("from_openapi go to_sdk -i spec.yaml -o out/")
+        else {
+            panic!("from_openapi did not parse");
+        };
+        assert_eq!(target_language.as_deref(), Some("go"));
+        assert_eq!(target.as_deref(), Some("to_sdk"));
+        assert_eq!(args, ["-i", "spec.yaml", "-o", "out/"]);
+        assert!(batch_args.is_empty());
+
+        assert!(parse("from_openapi go").is_err());
+    }
+
+    #[test]
+    fn from_openapi_batches_take_the_target_after_a_separator() {
+        let Ok(Commands::FromOpenApi {
+            languages,
+            all,
+            jobs,
+            target_language,
+            batch_args,
+            ..
+        }) = parse("from_openapi --languages go,rust --jobs 2 -- to_sdk -i spec.yaml")
+        else {
+            panic!("from_openapi did not parse");
+        };
+        assert_eq!(languages, ["go", "rust"]);
+        assert!(!all);
+        assert_eq!(jobs, 2);
+        assert_eq!(target_language, None);
+        assert_eq!(batch_args, ["to_sdk", "-i", "spec.yaml"]);
+
+        assert!(parse("from_openapi --all -- to_sdk").is_ok());
+        assert!(parse("from_openapi --all").is_err());
+        assert!(parse("from_openapi --all go -- to_sdk").is_err());
+        assert!(parse("from_openapi --languages go,rust go to_sdk").is_err());
+        assert!(parse("from_openapi go to_sdk -- -i spec.yaml").is_err());
+    }
+}
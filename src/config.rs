@@ -0,0 +1,360 @@
+//! Configuration for `cdd-ctl`.
+//!
+//! [`AppConfig`] (bind address, database, `servers`) comes from `cdd-engine`; [`CtlConfig`]
+//! holds the settings owned by this crate and is read from the same file and `CDD__*`
+//! environment variables, so a single `config.json` configures both. Supervision settings
+//! that `ProcessConfig` has no field for ([`ServerOptions`]) are read from the same
+//! `servers.<name>` entries.
+
+pub use cdd_engine::config::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::backoff::{Backoff, CircuitBreaker};
+use crate::health::HealthCheck;
+use crate::logging::LogFormat;
+use crate::logs::LogOptions;
+use crate::process;
+use crate::readiness::ReadyWhen;
+use crate::shutdown::ShutdownConfig;
+use crate::telemetry::TracingConfig;
+use crate::wasm::WasmLimitsConfig;
+
+/// Settings specific to `cdd-ctl`, read alongside [`AppConfig`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CtlConfig {
+    /// Resource limits for WASM generator executions.
+    pub wasm_limits: WasmLimitsConfig,
+    /// Per-server supervision settings, keyed like [`AppConfig::servers`].
+    pub servers: HashMap<String, ServerOptions>,
+    /// Export of the request spans.
+    pub tracing: TracingConfig,
+    /// Format of the log records; `--log-format` takes precedence.
+    pub log_format: LogFormat,
+    /// How long a termination signal waits for in-flight work.
+    pub shutdown: ShutdownConfig,
+    /// Gateway organization whose owners administer the supervised processes; without one,
+    /// nobody does.
+    pub admin_organization: Option<String>,
+}
+
+/// Supervision settings of one server beyond its `ProcessConfig`, given in the same object:
+///
+/// ```json
+/// "cdd-java": {
+///   "command": "cdd-java",
+///   "health_check": { "probe": { "type": "tcp", "port": 9093 }, "interval_ms": 5000 },
+///   "backoff": { "strategy": "exponential", "max_delay_ms": 60000, "jitter_percent": 50 },
+///   "circuit_breaker": { "cool_down_ms": 600000 },
+///   "depends_on": ["cdd-c"],
+///   "ready_when": { "type": "log_line", "pattern": "listening on" },
+///   "env": { "JAVA_OPTS": "-Xmx2g" },
+///   "memory_limit": 4294967296,
+///   "cpu_limit": 1.5,
+///   "cgroup": "/sys/fs/cgroup/cdd/cdd-java",
+///   "logs": { "buffer_lines": 5000, "file": "/var/log/cdd/cdd-java.log" },
+///   "stop_grace_ms": 30000
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ServerOptions {
+    /// Probe restarting the server once it stops answering.
+    pub health_check: Option<HealthCheck>,
+    /// How the delay between restarts grows; `restart_delay_ms` is its starting point.
+    pub backoff: Backoff,
+    /// Retries a server after a cool-down once it exhausted `max_retries`, instead of giving up.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Servers that must be ready before this one is spawned.
+    pub depends_on: Vec<String>,
+    /// When the server starts counting as ready; as soon as it is running without one.
+    pub ready_when: Option<ReadyWhen>,
+    /// Whether `/readyz` may report ready while this server is not.
+    pub optional: bool,
+    /// Environment variables set for the process, on top of the inherited ones.
+    pub env: BTreeMap<String, String>,
+    /// A `.env`-style file of further variables; `env` takes precedence over it.
+    pub env_file: Option<PathBuf>,
+    /// Working directory of the process.
+    pub cwd: Option<PathBuf>,
+    /// User to run the process as: a name, a uid or `uid:gid` (Unix only).
+    pub user: Option<String>,
+    /// Cap on the address space of the process, in bytes (Unix only).
+    pub memory_limit: Option<u64>,
+    /// Share of CPU time the process may use, enforced through its `cgroup` (Linux only).
+    pub cpu_limit: Option<process::CpuLimit>,
+    /// cgroup v2 directory the process is placed in, created if missing (Linux only).
+    pub cgroup: Option<PathBuf>,
+    /// In-memory buffer and optional rotating file capturing the process's output.
+    pub logs: LogOptions,
+    /// Time the process is given to exit after `SIGTERM` before it is killed, in milliseconds;
+    /// [`DEFAULT_STOP_GRACE`](process::DEFAULT_STOP_GRACE) if unset.
+    pub stop_grace_ms: Option<u64>,
+}
+
+impl CtlConfig {
+    /// Loads from `path` (or an optional `config.*` in the working directory) and `CDD__*`
+    /// environment variables, e.g. `CDD__WASM_LIMITS__DEFAULT__TIMEOUT_MS=30000`.
+    pub fn load(path: Option<&str>) -> Result<Self, ::config::ConfigError> {
+        ::config::Config::builder()
+            .add_source(
+                ::config::File::with_name(path.unwrap_or("config")).required(path.is_some()),
+            )
+            .add_source(
+                ::config::Environment::with_prefix("CDD")
+                    .separator("__")
+                    .try_parsing(true),
+            )
+            .build()?
+            .try_deserialize()
+    }
+}
+
+/// Checks the `servers` of an [`AppConfig`] and their [`ServerOptions`] for definitions that
+/// could never run.
+pub fn validate_servers(
+    servers: &HashMap<String, ProcessConfig>,
+    options: &HashMap<String, ServerOptions>,
+) -> Result<(), String> {
+    let mut names: Vec<&String> = servers.keys().collect();
+    names.sort();
+    for name in names {
+        let server = &servers[name];
+        if name.trim().is_empty() {
+            return Err("servers: a server has an empty name".to_string());
+        }
+        if server
+            .command
+            .as_deref()
+            .is_some_and(|c| c.trim().is_empty())
+        {
+            return Err(format!("servers.{}: `command` is empty", name));
+        }
+        if let Some(address) = &server.external_address {
+            let scheme = address.split_once("://").map(|(scheme, _)| scheme);
+            if address.trim().is_empty() || scheme.is_some_and(|s| s != "http" && s != "https") {
+                return Err(format!(
+                    "servers.{}: `external_address` must be host:port or an http(s) URL, got {:?}",
+                    name, address
+                ));
+            }
+        }
+        let options = options.get(name.as_str()).cloned().unwrap_or_default();
+        if let Some(check) = &options.health_check {
+            check
+                .validate(server.external_address.as_deref())
+                .map_err(|e| format!("servers.{}.health_check: {}", name, e))?;
+        }
+        options
+            .backoff
+            .validate()
+            .map_err(|e| format!("servers.{}.backoff: {}", name, e))?;
+        if let Some(breaker) = &options.circuit_breaker {
+            breaker
+                .validate()
+                .map_err(|e| format!("servers.{}.circuit_breaker: {}", name, e))?;
+        }
+        if server.external_address.is_none() {
+            process::validate(&options).map_err(|e| format!("servers.{}: {}", name, e))?;
+            options
+                .logs
+                .validate()
+                .map_err(|e| format!("servers.{}.logs: {}", name, e))?;
+        }
+        if let Some(ready_when) = &options.ready_when {
+            if server.external_address.is_some() {
+                return Err(format!(
+                    "servers.{}.ready_when: external servers are not spawned",
+                    name
+                ));
+            }
+            ready_when
+                .validate()
+                .map_err(|e| format!("servers.{}.ready_when: {}", name, e))?;
+        }
+        for dependency in &options.depends_on {
+            if !servers.contains_key(dependency) {
+                return Err(format!(
+                    "servers.{}.depends_on: unknown server {:?}",
+                    name, dependency
+                ));
+            }
+        }
+    }
+    if let Some(cycle) = dependency_cycle(servers, options) {
+        return Err(format!(
+            "servers: `depends_on` forms a cycle: {}",
+            cycle.join(" -> ")
+        ));
+    }
+    Ok(())
+}
+
+/// A chain of `depends_on` edges leading from a server back to itself, if there is one.
+fn dependency_cycle(
+    servers: &HashMap<String, ProcessConfig>,
+    options: &HashMap<String, ServerOptions>,
+) -> Option<Vec<String>> {
+    fn visit<'a>(
+        name: &'a str,
+        options: &'a HashMap<String, ServerOptions>,
+        path: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.to_string());
+            return Some(cycle);
+        }
+        if done.contains(&name) {
+            return None;
+        }
+        path.push(name);
+        for dependency in options
+            .get(name)
+            .map(|o| &o.depends_on)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(cycle) = visit(dependency, options, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.push(name);
+        None
+    }
+
+    let mut names: Vec<&String> = servers.keys().collect();
+    names.sort();
+    let mut done = Vec::new();
+    names
+        .into_iter()
+        .find_map(|name| visit(name, options, &mut Vec::new(), &mut done))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: &str) -> ProcessConfig {
+        ProcessConfig {
+            command: Some(command.to_string()),
+            args: None,
+            external_address: None,
+            max_retries: 0,
+            restart_delay_ms: 0,
+        }
+    }
+
+    fn external(address: &str) -> ProcessConfig {
+        ProcessConfig {
+            command: None,
+            args: None,
+            external_address: Some(address.to_string()),
+            max_retries: 0,
+            restart_delay_ms: 0,
+        }
+    }
+
+    fn servers(entries: &[(&str, ProcessConfig)]) -> HashMap<String, ProcessConfig> {
+        entries
+            .iter()
+            .map(|(name, config)| (name.to_string(), config.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn usable_servers_are_accepted() {
+        let servers = servers(&[
+            ("cdd-go", command("cdd-go")),
+            ("cdd-rust", external("127.0.0.1:9092")),
+            ("cdd-ts", external("https://ts.example.com/mcp")),
+        ]);
+        assert_eq!(validate_servers(&servers, &HashMap::new()), Ok(()));
+    }
+
+    #[test]
+    fn servers_that_could_never_run_are_rejected() {
+        for (name, config) in [
+            (" ", command("cdd-go")),
+            ("cdd-go", command("  ")),
+            ("cdd-go", external("")),
+            ("cdd-go", external("ftp://example.com")),
+        ] {
+            let servers = servers(&[(name, config)]);
+            assert!(
+                validate_servers(&servers, &HashMap::new()).is_err(),
+                "{:?}",
+                servers
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_options_name_their_server() {
+        let servers = servers(&[("cdd-go", command("cdd-go"))]);
+        let options = HashMap::from([(
+            "cdd-go".to_string(),
+            ServerOptions {
+                backoff: Backoff {
+                    multiplier: 0,
+                    ..Backoff::default()
+                },
+                ..ServerOptions::default()
+            },
+        )]);
+        let error = validate_servers(&servers, &options).unwrap_err();
+        assert!(error.starts_with("servers.cdd-go.backoff:"), "{}", error);
+    }
+
+    fn depending_on(dependencies: &[(&str, &[&str])]) -> HashMap<String, ServerOptions> {
+        dependencies
+            .iter()
+            .map(|(name, depends_on)| {
+                let options = ServerOptions {
+                    depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+                    ..ServerOptions::default()
+                };
+                (name.to_string(), options)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_must_be_configured_servers() {
+        let servers = servers(&[("cdd-go", command("cdd-go"))]);
+        let options = depending_on(&[("cdd-go", &["cdd-rust"])]);
+        let error = validate_servers(&servers, &options).unwrap_err();
+        assert_eq!(
+            error,
+            "servers.cdd-go.depends_on: unknown server \"cdd-rust\""
+        );
+    }
+
+    #[test]
+    fn dependency_cycles_are_rejected() {
+        let servers = servers(&[
+            ("a", command("a")),
+            ("b", command("b")),
+            ("c", command("c")),
+        ]);
+        let options = depending_on(&[("a", &["b"]), ("b", &["c"]), ("c", &[])]);
+        assert_eq!(validate_servers(&servers, &options), Ok(()));
+
+        let options = depending_on(&[("a", &["b"]), ("b", &["a"]), ("c", &["a"])]);
+        assert_eq!(
+            validate_servers(&servers, &options),
+            Err("servers: `depends_on` forms a cycle: a -> b -> a".to_string())
+        );
+        let options = depending_on(&[("c", &["c"])]);
+        assert_eq!(
+            dependency_cycle(&servers, &options),
+            Some(vec!["c".to_string(), "c".to_string()])
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! Environment diagnostics for the configured language backends (`cdd-ctl toolchain doctor`).
+//!
+//! Each supervised server is checked for the presence of its executable or `.wasm` module and
+//! then probed with a `--version` handshake, so that a missing toolchain is reported up front
+//! instead of surfacing as a "Failed to execute" error at request time.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use futures_util::future::{join_all, BoxFuture};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use crate::executor::find_executable;
+use crate::toolchain::{executable_name, wasm_module_path, ToolchainRegistry};
+use crate::wasm::WasmEngine;
+use crate::ProcessConfig;
+
+/// Output format of the doctor report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable pass/fail matrix.
+    #[default]
+    Table,
+    /// Machine readable JSON document.
+    Json,
+}
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// The check succeeded.
+    Pass,
+    /// The check failed.
+    Fail,
+    /// The check does not apply to this backend.
+    Skip,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skip => "-",
+        }
+    }
+}
+
+/// Diagnostic result for one configured backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendReport {
+    /// Server name as configured in `AppConfig.servers`.
+    pub name: String,
+    /// How the backend is reached: `native`, `wasm` or `external`.
+    pub runtime: &'static str,
+    /// Whether the executable, `.wasm` module or remote address is present.
+    pub found: CheckStatus,
+    /// Whether the version/handshake probe succeeded.
+    pub probe: CheckStatus,
+    /// Resolved path, probe output or error message.
+    pub detail: String,
+}
+
+impl BackendReport {
+    /// Whether every applicable check passed.
+    pub fn passed(&self) -> bool {
+        self.found != CheckStatus::Fail && self.probe != CheckStatus::Fail
+    }
+}
+
+/// Availability of `wasmtime` and the WASM proposals required by the backends.
+#[derive(Debug, Clone, Serialize)]
+pub struct WasmtimeReport {
+    /// Whether the embedded engine was checked rather than the `wasmtime` CLI.
+    pub embedded: bool,
+    /// Whether `wasmtime --version` (or the embedded engine) succeeded.
+    pub available: CheckStatus,
+    /// Reported `wasmtime` version, or the error encountered.
+    pub detail: String,
+    /// Per-feature result of enabling each WASM proposal.
+    pub features: BTreeMap<String, CheckStatus>,
+}
+
+impl WasmtimeReport {
+    /// Whether `wasmtime` and every required feature flag are usable.
+    pub fn passed(&self) -> bool {
+        self.available != CheckStatus::Fail
+            && self.features.values().all(|s| *s != CheckStatus::Fail)
+    }
+}
+
+/// Full doctor report.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    /// Per-backend results, sorted by name.
+    pub backends: Vec<BackendReport>,
+    /// `wasmtime` results, present only when a backend runs through `wasmtime`.
+    pub wasmtime: Option<WasmtimeReport>,
+}
+
+impl DoctorReport {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.backends.iter().all(BackendReport::passed)
+            && self.wasmtime.as_ref().is_none_or(WasmtimeReport::passed)
+    }
+
+    /// Renders the report as a fixed-width pass/fail matrix.
+    pub fn to_table(&self) -> String {
+        let width = self
+            .backends
+            .iter()
+            .map(|b| b.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("BACKEND".len());
+        let mut out = format!(
+            "{:<width$}  {:<8}  {:<5}  {:<5}  DETAIL\n",
+            "BACKEND", "RUNTIME", "FOUND", "PROBE"
+        );
+        for b in &self.backends {
+            out.push_str(&format!(
+                "{:<width$}  {:<8}  {:<5}  {:<5}  {}\n",
+                b.name,
+                b.runtime,
+                b.found.label(),
+                b.probe.label(),
+                b.detail
+            ));
+        }
+        if let Some(w) = &self.wasmtime {
+            out.push_str(&format!(
+                "\n{}: {} ({})\n",
+                if w.embedded {
+                    "wasmtime (embedded)"
+                } else {
+                    "wasmtime"
+                },
+                w.available.label(),
+                w.detail
+            ));
+            for (feature, status) in &w.features {
+                out.push_str(&format!(
+                    "  --wasm-features={}: {}\n",
+                    feature,
+                    status.label()
+                ));
+            }
+        }
+        out.push_str(if self.passed() {
+            "\nAll checks passed.\n"
+        } else {
+            "\nSome checks failed.\n"
+        });
+        out
+    }
+}
+
+/// Checks every configured backend, probing each with the given timeout.
+pub async fn diagnose(servers: &HashMap<String, ProcessConfig>, timeout: Duration) -> DoctorReport {
+    let mut names: Vec<&String> = servers.keys().collect();
+    names.sort();
+
+    let mut checks: Vec<BoxFuture<'_, BackendReport>> = Vec::with_capacity(names.len());
+    let mut wasm_features = BTreeSet::new();
+    let mut uses_wasmtime = false;
+    let mut uses_embedded = false;
+
+    for name in names {
+        let config = &servers[name];
+        if let Some(address) = &config.external_address {
+            checks.push(Box::pin(check_external(name, address, timeout)));
+            continue;
+        }
+        let command = config.command.clone().unwrap_or_else(|| name.clone());
+        let args = config.args.clone().unwrap_or_default();
+        if is_wasmtime(&command) {
+            uses_wasmtime = true;
+            if let Some(toolchain) = ToolchainRegistry::builtin().get(name) {
+                wasm_features.extend(toolchain.wasm_features.iter().copied());
+            }
+            checks.push(Box::pin(async move {
+                check_wasm(name, &command, &args, timeout).await
+            }));
+        } else if let [subcommand, language, ..] = args.as_slice() {
+            if subcommand != "wasm_run" {
+                checks.push(Box::pin(async move {
+                    check_native(name, &command, timeout).await
+                }));
+                continue;
+            }
+            uses_embedded = true;
+            if let Some(toolchain) = ToolchainRegistry::builtin().get(language) {
+                wasm_features.extend(toolchain.wasm_features.iter().copied());
+            }
+            let language = language.clone();
+            checks.push(Box::pin(async move {
+                check_embedded(name, &command, &language, timeout).await
+            }));
+        } else {
+            checks.push(Box::pin(async move {
+                check_native(name, &command, timeout).await
+            }));
+        }
+    }
+    // Probes are independent, so one slow or unreachable backend does not hold up the rest.
+    let backends = join_all(checks).await;
+
+    let wasmtime = if uses_wasmtime {
+        Some(check_wasmtime(&wasm_features, timeout).await)
+    } else if uses_embedded {
+        Some(check_embedded_engine(&wasm_features))
+    } else {
+        None
+    };
+
+    DoctorReport { backends, wasmtime }
+}
+
+fn is_wasmtime(command: &str) -> bool {
+    Path::new(command)
+        .file_stem()
+        .is_some_and(|stem| stem == "wasmtime")
+}
+
+async fn check_native(name: &str, command: &str, timeout: Duration) -> BackendReport {
+    let Some(path) = find_executable(command) else {
+        return BackendReport {
+            name: name.to_string(),
+            runtime: "native",
+            found: CheckStatus::Fail,
+            probe: CheckStatus::Skip,
+            detail: format!("`{}` not found on PATH", command),
+        };
+    };
+    let (probe, detail) =
+        run_probe(&path.to_string_lossy(), &["--version".to_string()], timeout).await;
+    BackendReport {
+        name: name.to_string(),
+        runtime: "native",
+        found: CheckStatus::Pass,
+        probe,
+        detail,
+    }
+}
+
+async fn check_wasm(
+    name: &str,
+    command: &str,
+    args: &[String],
+    timeout: Duration,
+) -> BackendReport {
+    // `wasmtime [--flags] <module>.wasm <subcommand>...`: keep the flags and module only.
+    let Some(module_idx) = args.iter().position(|a| a.ends_with(".wasm")) else {
+        return BackendReport {
+            name: name.to_string(),
+            runtime: "wasm",
+            found: CheckStatus::Fail,
+            probe: CheckStatus::Skip,
+            detail: "no `.wasm` module in args".to_string(),
+        };
+    };
+    let module = &args[module_idx];
+    if !Path::new(module).is_file() {
+        return BackendReport {
+            name: name.to_string(),
+            runtime: "wasm",
+            found: CheckStatus::Fail,
+            probe: CheckStatus::Skip,
+            detail: format!("{} does not exist", module),
+        };
+    }
+    let mut probe_args = args[..=module_idx].to_vec();
+    probe_args.push("--version".to_string());
+    let (probe, detail) = run_probe(command, &probe_args, timeout).await;
+    BackendReport {
+        name: name.to_string(),
+        runtime: "wasm",
+        found: CheckStatus::Pass,
+        probe,
+        detail,
+    }
+}
+
+/// Checks a module run through `<binary> wasm_run <language>` by the embedded engine.
+async fn check_embedded(
+    name: &str,
+    command: &str,
+    language: &str,
+    timeout: Duration,
+) -> BackendReport {
+    let module = wasm_module_path(&executable_name(language));
+    if !Path::new(&module).is_file() {
+        return BackendReport {
+            name: name.to_string(),
+            runtime: "wasm",
+            found: CheckStatus::Fail,
+            probe: CheckStatus::Skip,
+            detail: format!("{} does not exist", module),
+        };
+    }
+    let probe_args = [
+        "wasm_run".to_string(),
+        language.to_string(),
+        "--".to_string(),
+        "--version".to_string(),
+    ];
+    let (probe, detail) = run_probe(command, &probe_args, timeout).await;
+    BackendReport {
+        name: name.to_string(),
+        runtime: "wasm",
+        found: CheckStatus::Pass,
+        probe,
+        detail,
+    }
+}
+
+async fn check_external(name: &str, address: &str, timeout: Duration) -> BackendReport {
+    let connected = match socket_address(address) {
+        Ok(authority) => {
+            match tokio::time::timeout(timeout, TcpStream::connect(&authority)).await {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err(format!("timed out after {:?}", timeout)),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let (found, detail) = match connected {
+        Ok(()) => (CheckStatus::Pass, format!("{} reachable", address)),
+        Err(e) => (CheckStatus::Fail, format!("{}: {}", address, e)),
+    };
+    BackendReport {
+        name: name.to_string(),
+        runtime: "external",
+        found,
+        probe: CheckStatus::Skip,
+        detail,
+    }
+}
+
+/// The `host:port` to connect to for an `external_address`, which is either a socket address
+/// (`127.0.0.1:8080`, `[::1]:8080`) or a URL, whose port defaults to that of its scheme.
+fn socket_address(address: &str) -> Result<String, String> {
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return Ok(addr.to_string());
+    }
+    let url = if address.contains("://") {
+        reqwest::Url::parse(address)
+    } else {
+        reqwest::Url::parse(&format!("http://{}", address))
+    }
+    .map_err(|e| format!("invalid address: {}", e))?;
+    match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => Ok(format!("{}:{}", host, port)),
+        _ => Err("invalid address: expected a host and port".to_string()),
+    }
+}
+
+async fn check_wasmtime(features: &BTreeSet<&str>, timeout: Duration) -> WasmtimeReport {
+    let (available, detail) = run_probe("wasmtime", &["--version".to_string()], timeout).await;
+    let mut results = BTreeMap::new();
+    for feature in features {
+        let status = if available == CheckStatus::Pass {
+            let args = [
+                format!("--wasm-features={}", feature),
+                "--version".to_string(),
+            ];
+            run_probe("wasmtime", &args, timeout).await.0
+        } else {
+            CheckStatus::Skip
+        };
+        results.insert(feature.to_string(), status);
+    }
+    WasmtimeReport {
+        embedded: false,
+        available,
+        detail,
+        features: results,
+    }
+}
+
+fn check_embedded_engine(features: &BTreeSet<&str>) -> WasmtimeReport {
+    let engine = WasmEngine::global();
+    let (available, detail) = match engine.engine(&[]) {
+        Ok(_) => (CheckStatus::Pass, "embedded engine".to_string()),
+        Err(e) => (CheckStatus::Fail, e.to_string()),
+    };
+    let features = features
+        .iter()
+        .map(|feature| {
+            let status = match engine.engine(&[*feature]) {
+                Ok(_) => CheckStatus::Pass,
+                Err(_) => CheckStatus::Fail,
+            };
+            (feature.to_string(), status)
+        })
+        .collect();
+    WasmtimeReport {
+        embedded: true,
+        available,
+        detail,
+        features,
+    }
+}
+
+/// Runs `command args...` and reports the first line of its output.
+async fn run_probe(command: &str, args: &[String], timeout: Duration) -> (CheckStatus, String) {
+    let output = Command::new(command).args(args).kill_on_drop(true).output();
+    match tokio::time::timeout(timeout, output).await {
+        Err(_) => (
+            CheckStatus::Fail,
+            format!("timed out after {}s", timeout.as_secs()),
+        ),
+        Ok(Err(e)) => (CheckStatus::Fail, format!("failed to execute: {}", e)),
+        Ok(Ok(output)) => {
+            let text = if output.stdout.is_empty() {
+                String::from_utf8_lossy(&output.stderr).into_owned()
+            } else {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            };
+            let first_line = text.lines().next().unwrap_or_default().trim().to_string();
+            if output.status.success() {
+                (CheckStatus::Pass, first_line)
+            } else {
+                (
+                    CheckStatus::Fail,
+                    format!("exited with {}: {}", output.status, first_line),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn external_addresses_are_socket_addresses_or_urls() {
+        for (address, expected) in [
+            ("127.0.0.1:8080", "127.0.0.1:8080"),
+            ("[::1]:8080", "[::1]:8080"),
+            ("localhost:8080", "localhost:8080"),
+            ("http://[::1]:8080/mcp", "[::1]:8080"),
+            ("http://example.com/mcp", "example.com:80"),
+            ("https://example.com", "example.com:443"),
+        ] {
+            assert_eq!(
+                socket_address(address).as_deref(),
+                Ok(expected),
+                "{}",
+                address
+            );
+        }
+        assert!(socket_address("ftp+unknown://example.com").is_err());
+        assert!(socket_address("http://").is_err());
+    }
+
+    #[tokio::test]
+    async fn unreachable_external_servers_fail() {
+        // Nothing listens on the port of a listener that has just been closed.
+        let listener = std::net::TcpListener::bind("[::1]:0")
+            .or_else(|_| std::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let address = format!("http://{}/mcp", listener.local_addr().unwrap());
+        drop(listener);
+
+        let report = check_external("remote", &address, TIMEOUT).await;
+        assert_eq!(report.runtime, "external");
+        assert_eq!(report.found, CheckStatus::Fail);
+        assert_eq!(report.probe, CheckStatus::Skip);
+        assert!(report.detail.starts_with(&address), "{}", report.detail);
+    }
+
+    #[tokio::test]
+    async fn missing_native_executables_fail() {
+        let report = check_native("go", "cdd-ctl-no-such-executable", TIMEOUT).await;
+        assert_eq!(report.runtime, "native");
+        assert_eq!(report.found, CheckStatus::Fail);
+        assert_eq!(report.probe, CheckStatus::Skip);
+        assert!(report.detail.contains("not found on PATH"));
+    }
+
+    #[tokio::test]
+    async fn missing_wasm_modules_fail() {
+        let module = std::env::temp_dir()
+            .join(format!("cdd-ctl-doctor-{}.wasm", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let args = vec!["run".to_string(), module.clone(), "serve_mcp".to_string()];
+        let report = check_wasm("go", "wasmtime", &args, TIMEOUT).await;
+        assert_eq!(report.runtime, "wasm");
+        assert_eq!(report.found, CheckStatus::Fail);
+        assert_eq!(report.detail, format!("{} does not exist", module));
+
+        let report = check_wasm("go", "wasmtime", &["run".to_string()], TIMEOUT).await;
+        assert_eq!(report.found, CheckStatus::Fail);
+
+        let report = check_embedded("go", "cdd-ctl-wasm", "no-such-language", TIMEOUT).await;
+        assert_eq!(report.found, CheckStatus::Fail);
+        assert!(report.detail.ends_with("does not exist"));
+    }
+}
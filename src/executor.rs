@@ -0,0 +1,595 @@
+//! Runtime-agnostic execution of `cdd-*` toolchains.
+//!
+//! `cdd-ctl` and `cdd-ctl-wasm` share every CLI handler and differ only in the default
+//! [`RuntimeSelector`]: a toolchain is either spawned as a native `cdd-<lang>` executable or
+//! evaluated from `cdd-ctl-wasm-sdk/assets/wasm/cdd-<lang>.wasm` by the embedded engine. In
+//! `auto` mode the native binary on `PATH` is preferred and the WASM module is used as a
+//! fallback.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::http::StatusCode;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+
+use crate::batch;
+use crate::logging::{self, REQUEST_ID_VAR};
+use crate::metrics::{Metrics, RunOutcome};
+use crate::sandbox::Sandbox;
+use crate::shutdown::Job;
+use crate::telemetry::{self, TRACEPARENT_VAR};
+use crate::toolchain::{executable_name, wasm_module_path, Runtime, ServeMode, ToolchainRegistry};
+use crate::wasm::{Preopen, WasmEngine, WasmLimits, WasmLimitsConfig, WasmOptions};
+use crate::ProcessConfig;
+
+/// Id of the request [`Executor::serve_in`] sends, and so of the reply to look for.
+pub const SERVE_REQUEST_ID: u64 = 1;
+
+/// Which runtime the CLI should execute toolchains under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RuntimeSelector {
+    /// Spawn the native `cdd-<lang>` executable.
+    #[default]
+    Native,
+    /// Evaluate the `cdd-<lang>.wasm` module.
+    Wasm,
+    /// Prefer the native executable on `PATH`, falling back to the WASM module.
+    Auto,
+}
+
+/// Error resolving or running a toolchain.
+#[derive(Debug)]
+pub enum ExecError {
+    /// The toolchain does not exist under the selected runtime.
+    NotFound {
+        /// Executable name, e.g. `cdd-go`.
+        executable: String,
+        /// The runtime(s) that were searched.
+        selector: RuntimeSelector,
+    },
+    /// The toolchain is known not to support the selected runtime.
+    Unsupported {
+        /// Executable name, e.g. `cdd-sh`.
+        executable: String,
+        /// The runtime that was requested.
+        runtime: Runtime,
+    },
+    /// Spawning or waiting for the process failed.
+    Io(std::io::Error),
+    /// Compiling, instantiating or running a WASM module failed.
+    Wasm(String),
+    /// A WASM module was stopped for exceeding one of its [`WasmLimits`].
+    LimitExceeded {
+        /// The limit that was hit.
+        limit: LimitKind,
+        /// Whatever the module wrote to stderr before it was stopped.
+        stderr: Vec<u8>,
+    },
+}
+
+/// The resource limit a WASM execution ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// The fuel budget was used up.
+    Fuel,
+    /// Linear memory growth was refused.
+    Memory,
+    /// The wall-clock deadline passed.
+    Timeout,
+}
+
+impl LimitKind {
+    /// Every limit.
+    pub const ALL: [LimitKind; 3] = [LimitKind::Fuel, LimitKind::Memory, LimitKind::Timeout];
+
+    /// Exit code of a `wasm_run` process whose module ran into this limit, so that its
+    /// supervisor can tell why it exited.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            LimitKind::Fuel => 121,
+            LimitKind::Memory => 122,
+            LimitKind::Timeout => 123,
+        }
+    }
+
+    /// The limit whose [`LimitKind::exit_code`] is `code`, if any.
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|limit| limit.exit_code() == code)
+    }
+
+    /// The limit displayed as `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|limit| limit.to_string() == name)
+    }
+
+    /// HTTP status of a job that ran into this limit: `413` for memory, `504` otherwise.
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            LimitKind::Memory => StatusCode::PAYLOAD_TOO_LARGE,
+            LimitKind::Fuel | LimitKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LimitKind::Fuel => "fuel",
+            LimitKind::Memory => "memory",
+            LimitKind::Timeout => "timeout",
+        })
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::NotFound {
+                executable,
+                selector,
+            } => write!(
+                f,
+                "{} is not available under the {:?} runtime",
+                executable, selector
+            ),
+            ExecError::Unsupported {
+                executable,
+                runtime,
+            } => write!(
+                f,
+                "{} does not support the {:?} runtime",
+                executable, runtime
+            ),
+            ExecError::Io(e) => write!(f, "{}", e),
+            ExecError::Wasm(e) => write!(f, "WASM execution failed: {}", e),
+            ExecError::LimitExceeded { limit, .. } => {
+                write!(f, "WASM execution exceeded its {} limit", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+impl From<std::io::Error> for ExecError {
+    fn from(e: std::io::Error) -> Self {
+        ExecError::Io(e)
+    }
+}
+
+/// Captured result of running a toolchain to completion.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOutput {
+    /// Exit code, or `None` if the process was killed by a signal.
+    pub code: Option<i32>,
+    /// Captured stdout.
+    pub stdout: Vec<u8>,
+    /// Captured stderr.
+    pub stderr: Vec<u8>,
+}
+
+impl ExecOutput {
+    /// Whether the toolchain exited with status `0`.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// A fully resolved program and argument list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// The runtime the toolchain was resolved to.
+    pub runtime: Runtime,
+    /// Program to execute (`cdd-<lang>`, or this binary's `wasm_run` for WASM modules).
+    pub program: String,
+    /// Arguments passed to `program`.
+    pub args: Vec<String>,
+}
+
+impl Invocation {
+    /// Builds a blocking [`std::process::Command`] for this invocation.
+    pub fn std_command(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(&self.program);
+        cmd.args(&self.args);
+        cmd
+    }
+
+    /// Builds an async [`tokio::process::Command`] for this invocation.
+    pub fn tokio_command(&self) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(&self.program);
+        cmd.args(&self.args).kill_on_drop(true);
+        cmd
+    }
+}
+
+/// Resolves toolchains to invocations under a [`RuntimeSelector`].
+#[derive(Debug, Clone, Default)]
+pub struct Executor {
+    selector: RuntimeSelector,
+    registry: ToolchainRegistry,
+    wasm_limits: Arc<WasmLimitsConfig>,
+}
+
+impl Executor {
+    /// Creates an executor for the given runtime selector.
+    pub fn new(selector: RuntimeSelector) -> Self {
+        Self {
+            selector,
+            registry: ToolchainRegistry::builtin(),
+            wasm_limits: Arc::default(),
+        }
+    }
+
+    /// Applies `limits` to every WASM module this executor runs.
+    pub fn with_wasm_limits(mut self, limits: WasmLimitsConfig) -> Self {
+        self.wasm_limits = Arc::new(limits);
+        self
+    }
+
+    /// The configured limits for `language`'s WASM module.
+    pub fn wasm_limits(&self, language: &str) -> WasmLimits {
+        self.wasm_limits.for_language(&executable_name(language))
+    }
+
+    /// The runtime selector this executor was created with.
+    pub fn selector(&self) -> RuntimeSelector {
+        self.selector
+    }
+
+    /// Picks the concrete runtime `language` will be executed under.
+    pub fn resolve(&self, language: &str) -> Result<Runtime, ExecError> {
+        let executable = executable_name(language);
+        let toolchain = self.registry.get(&executable);
+        let supports = |runtime: Runtime| toolchain.is_none_or(|t| t.supports(runtime));
+
+        let wanted = match self.selector {
+            RuntimeSelector::Native => Runtime::Native,
+            RuntimeSelector::Wasm => Runtime::Wasm,
+            RuntimeSelector::Auto => {
+                if supports(Runtime::Native) && find_executable(&executable).is_some() {
+                    return Ok(Runtime::Native);
+                }
+                if supports(Runtime::Wasm) && Path::new(&wasm_module_path(&executable)).is_file() {
+                    return Ok(Runtime::Wasm);
+                }
+                return Err(ExecError::NotFound {
+                    executable,
+                    selector: self.selector,
+                });
+            }
+        };
+        if !supports(wanted) {
+            return Err(ExecError::Unsupported {
+                executable,
+                runtime: wanted,
+            });
+        }
+        Ok(wanted)
+    }
+
+    /// Resolves `cdd-<lang> <args...>` into a program and argument list.
+    ///
+    /// WASM modules are run by re-executing the current binary as `wasm_run`, which evaluates
+    /// the module with the embedded engine and the current directory preopened; use this for
+    /// interactive children.
+    pub fn invocation(&self, language: &str, args: &[String]) -> Result<Invocation, ExecError> {
+        let executable = executable_name(language);
+        let runtime = self.resolve(&executable)?;
+        let (program, full_args) = match runtime {
+            Runtime::Native => (executable, args.to_vec()),
+            _ => {
+                // Interactive use: expose the caller's working directory, as a shell would.
+                let mut wasm_args = vec![
+                    "wasm_run".to_string(),
+                    executable,
+                    "--dir".to_string(),
+                    ".".to_string(),
+                    "--".to_string(),
+                ];
+                wasm_args.extend(args.iter().cloned());
+                (wasm_runner(), wasm_args)
+            }
+        };
+        Ok(Invocation {
+            runtime,
+            program,
+            args: full_args,
+        })
+    }
+
+    /// Runs `cdd-<lang> <args...>` to completion, capturing its output.
+    ///
+    /// WASM modules are evaluated in-process by the shared [`WasmEngine`] and see the current
+    /// directory, as they would when run from a shell, along with an `-o`/`--output` directory
+    /// outside of it (see [`mount_output_dir`]); use [`Executor::serve_in`] for untrusted input.
+    pub async fn output(&self, language: &str, args: &[String]) -> Result<ExecOutput, ExecError> {
+        let executable = executable_name(language);
+        let runtime = self.resolve(&executable)?;
+        let mut args = args.to_vec();
+        let mut preopens = vec![Preopen::new(".", ".")];
+        if runtime != Runtime::Native {
+            preopens.extend(mount_output_dir(&mut args).await?);
+        }
+        let options = WasmOptions {
+            preopens,
+            limits: self.wasm_limits(&executable),
+            ..WasmOptions::default()
+        };
+        self.run(&executable, runtime, args, options).await
+    }
+
+    /// Runs `cdd-<lang> serve_json_rpc` confined to `sandbox` for a single request: `method`
+    /// with the params built by `params` for the runtime the toolchain resolves to (guest paths
+    /// for WASM, host paths for native executables). The request is the server's whole stdin,
+    /// so it exits once it has answered; the reply is in the captured stdout.
+    ///
+    /// WASM modules see `/workspace` and `/out` and nothing else of the host filesystem, and
+    /// run within the configured [`WasmLimits`], tightened by `overrides` (see
+    /// [`WasmLimits::within`]). The caller accounts for the run.
+    pub async fn serve_in(
+        &self,
+        language: &str,
+        sandbox: &Sandbox,
+        method: &str,
+        params: impl FnOnce(Runtime) -> Value,
+        overrides: WasmLimits,
+    ) -> Result<ExecOutput, ExecError> {
+        let executable = executable_name(language);
+        let runtime = self.resolve(&executable)?;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": SERVE_REQUEST_ID,
+            "method": method,
+            "params": params(runtime),
+        });
+        let mut stdin = serde_json::to_vec(&request).map_err(std::io::Error::other)?;
+        stdin.push(b'\n');
+        let options = WasmOptions {
+            preopens: sandbox.preopens(),
+            stdin,
+            limits: overrides.within(self.wasm_limits(&executable)),
+            ..WasmOptions::default()
+        };
+        let args = vec![ServeMode::JsonRpc.subcommand().to_string()];
+        self.execute(&executable, runtime, args, options).await
+    }
+
+    /// Runs `executable` under the already resolved `runtime`, capturing its output and
+    /// recording the run in [`Metrics::global`]. The run counts as an in-flight [`Job`] for
+    /// graceful shutdown.
+    #[tracing::instrument(
+        name = "toolchain.run",
+        skip_all,
+        fields(
+            executable = %executable,
+            runtime = ?runtime,
+            command = %args.first().map(String::as_str).unwrap_or_default()
+        )
+    )]
+    async fn run(
+        &self,
+        executable: &str,
+        runtime: Runtime,
+        args: Vec<String>,
+        options: WasmOptions,
+    ) -> Result<ExecOutput, ExecError> {
+        let _job = Job::start();
+        let started = Instant::now();
+        let labels = args.iter().take(2).cloned().collect::<Vec<_>>();
+        let result = self.execute(executable, runtime, args, options).await;
+        let outcome = match &result {
+            Ok(output) if output.success() => RunOutcome::Success,
+            Ok(_) => RunOutcome::Failure,
+            Err(_) => RunOutcome::Error,
+        };
+        Metrics::global().observe_run(executable, &labels, outcome, started.elapsed());
+        result
+    }
+
+    async fn execute(
+        &self,
+        executable: &str,
+        runtime: Runtime,
+        args: Vec<String>,
+        options: WasmOptions,
+    ) -> Result<ExecOutput, ExecError> {
+        if runtime == Runtime::Native {
+            let mut command = tokio::process::Command::new(executable);
+            let stdin = if options.stdin.is_empty() {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            };
+            command
+                .args(&args)
+                .stdin(stdin)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true);
+            if let Some(traceparent) = telemetry::traceparent() {
+                command.env(TRACEPARENT_VAR, traceparent);
+            }
+            if let Some(request_id) = logging::request_id() {
+                command.env(REQUEST_ID_VAR, request_id);
+            }
+            let mut child = command.spawn()?;
+            let pid = child.id();
+            if let Some(mut stdin) = child.stdin.take() {
+                // Written alongside the wait, so that a child answering before it has read
+                // everything cannot block on a full stdout pipe.
+                let input = options.stdin;
+                tokio::spawn(async move {
+                    if let Err(e) = stdin.write_all(&input).await {
+                        log::debug!("Failed to write to the stdin of a toolchain: {}", e);
+                    }
+                });
+            }
+            let output = logging::with_process(executable.to_string(), pid, async move {
+                let output = child.wait_with_output().await?;
+                log::info!(
+                    "Ran {} {}: {}",
+                    executable,
+                    args.first().map(String::as_str).unwrap_or_default(),
+                    output.status
+                );
+                Ok::<_, std::io::Error>(output)
+            })
+            .await?;
+            return Ok(ExecOutput {
+                code: output.status.code(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            });
+        }
+
+        let path = PathBuf::from(wasm_module_path(executable));
+        let features = self.wasm_features(executable);
+        let output = tokio::task::spawn_blocking(move || {
+            WasmEngine::global().run(&path, features, &args, &options)
+        })
+        .await
+        .map_err(|e| ExecError::Wasm(e.to_string()))??;
+        Ok(ExecOutput {
+            code: Some(output.exit_code),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+
+    /// WASM proposals required by the module of `executable`.
+    pub fn wasm_features(&self, executable: &str) -> &'static [&'static str] {
+        self.registry
+            .get(executable)
+            .map_or(&[], |t| t.wasm_features)
+    }
+
+    /// Builds the default `servers` map, resolving each toolchain to a runtime.
+    ///
+    /// Toolchains that are unavailable under the selected runtime are left out; WASM servers
+    /// run within the executor's [`WasmLimits`].
+    pub fn process_configs(&self, mode: ServeMode) -> HashMap<String, ProcessConfig> {
+        self.registry
+            .iter()
+            .filter_map(|t| {
+                let runtime = self.resolve(t.name).ok()?;
+                t.process_config(runtime, mode, &self.wasm_limits)
+                    .map(|config| (t.name.to_string(), config))
+            })
+            .collect()
+    }
+
+    /// Languages (without the `cdd-` prefix) available under the selected runtime.
+    pub fn languages(&self) -> Vec<String> {
+        self.registry
+            .iter()
+            .filter(|t| self.resolve(t.name).is_ok())
+            .map(|t| t.language().to_string())
+            .collect()
+    }
+}
+
+/// Program used to run WASM modules out-of-process: the current binary's `wasm_run` subcommand.
+pub fn wasm_runner() -> String {
+    std::env::current_exe()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "cdd-ctl-wasm".to_string())
+}
+
+/// Resolves `command` against `PATH` the way the OS would when spawning it.
+pub fn find_executable(command: &str) -> Option<PathBuf> {
+    let candidate = Path::new(command);
+    if candidate.components().count() > 1 {
+        return candidate.is_file().then(|| candidate.to_path_buf());
+    }
+    let extensions: &[&str] = if cfg!(windows) { &["", ".exe"] } else { &[""] };
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths).find_map(|dir| {
+            extensions.iter().find_map(|ext| {
+                let path = dir.join(format!("{}{}", command, ext));
+                path.is_file().then_some(path)
+            })
+        })
+    })
+}
+
+/// Preopens an `-o`/`--output` directory that the current directory does not contain, which
+/// a WASM module would otherwise not be able to write to.
+///
+/// The directory is created, mounted at its absolute host path and the argument rewritten to
+/// that path, so that the guest writes where the caller asked it to.
+async fn mount_output_dir(args: &mut Vec<String>) -> std::io::Result<Option<Preopen>> {
+    let (rest, Some(dir)) = batch::take_output_dir(args) else {
+        return Ok(None);
+    };
+    if dir
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Ok(None);
+    }
+    tokio::fs::create_dir_all(&dir).await?;
+    let dir = tokio::fs::canonicalize(&dir).await?;
+    let guest = dir.to_string_lossy().into_owned();
+    *args = rest;
+    args.push("-o".to_string());
+    args.push(guest.clone());
+    Ok(Some(Preopen::new(dir, guest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn output_dirs_below_the_current_directory_are_not_mounted() {
+        for given in [
+            args(&["from_openapi", "to_sdk", "-o", "out"]),
+            args(&["from_openapi", "to_sdk", "--output=./out/go"]),
+            args(&["from_openapi", "to_sdk"]),
+        ] {
+            let mut rewritten = given.clone();
+            assert!(mount_output_dir(&mut rewritten).await.unwrap().is_none());
+            assert_eq!(rewritten, given);
+        }
+    }
+
+    #[tokio::test]
+    async fn output_dirs_elsewhere_are_mounted_at_their_host_path() {
+        let dir = std::env::temp_dir().join(format!("cdd-ctl-output-{}", std::process::id()));
+        let out = dir.join("go");
+        let mut given = args(&["from_openapi", "to_sdk", "-o"]);
+        given.push(out.to_string_lossy().into_owned());
+        given.push("-i".to_string());
+        given.push("spec.yaml".to_string());
+
+        let preopen = mount_output_dir(&mut given).await.unwrap().unwrap();
+        let host = std::fs::canonicalize(&out).unwrap();
+        assert_eq!(preopen.host, host);
+        assert_eq!(preopen.guest, host.to_string_lossy());
+        assert_eq!(
+            given,
+            args(&[
+                "from_openapi",
+                "to_sdk",
+                "-i",
+                "spec.yaml",
+                "-o",
+                &preopen.guest
+            ])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
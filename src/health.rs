@@ -0,0 +1,258 @@
+//! Health-check probes for supervised servers.
+//!
+//! A process that is alive but no longer answering (a deadlocked JVM, a wedged event loop) is
+//! only noticed if something talks to it. A [`HealthCheck`] probes the server every
+//! `interval_ms` with one of:
+//!
+//! - [`Probe::JsonRpc`]: a JSON-RPC `ping` (any well-formed response, even an error, counts);
+//! - [`Probe::Tcp`]: a TCP connect to the listen port;
+//! - [`Probe::Http`]: an HTTP `GET` expecting a 2xx or 3xx status.
+//!
+//! After `failure_threshold` consecutive failures the supervisor restarts the process through
+//! its usual backoff; `external_address` servers are only reported.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// How to check that a server is responsive.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Probe {
+    /// POST a JSON-RPC request to `url` (default: the server's `external_address`).
+    JsonRpc {
+        /// Endpoint, e.g. `http://127.0.0.1:9091/`.
+        #[serde(default)]
+        url: Option<String>,
+        /// Method to call.
+        #[serde(default = "default_method")]
+        method: String,
+    },
+    /// Connect to `host:port`.
+    Tcp {
+        /// Host to connect to.
+        #[serde(default = "default_host")]
+        host: String,
+        /// Listen port of the server.
+        port: u16,
+    },
+    /// GET `url` (default: the server's `external_address`).
+    Http {
+        /// URL to fetch.
+        #[serde(default)]
+        url: Option<String>,
+    },
+}
+
+/// An optional per-server health check, configured as `servers.<name>.health_check`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HealthCheck {
+    /// What to probe.
+    pub probe: Probe,
+    /// Milliseconds between probes; the first probe runs one interval after spawning.
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// Milliseconds a probe may take before it counts as failed.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive failures after which the server is considered unhealthy.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl HealthCheck {
+    /// Checks that the probe can be run against a server at `external_address`.
+    pub fn validate(&self, external_address: Option<&str>) -> Result<(), String> {
+        if self.interval_ms == 0 || self.timeout_ms == 0 || self.failure_threshold == 0 {
+            return Err(
+                "`interval_ms`, `timeout_ms` and `failure_threshold` must be positive".to_string(),
+            );
+        }
+        match &self.probe {
+            Probe::JsonRpc { url, .. } | Probe::Http { url } => {
+                match url.as_deref().or(external_address) {
+                    Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                        Ok(())
+                    }
+                    Some(url) => Err(format!("probe URL must be http(s), got {:?}", url)),
+                    None => Err("probe needs a `url` or an `external_address`".to_string()),
+                }
+            }
+            Probe::Tcp { .. } => Ok(()),
+        }
+    }
+
+    /// Runs the probe once.
+    pub async fn probe(&self, external_address: Option<&str>) -> Result<(), String> {
+        let timeout = Duration::from_millis(self.timeout_ms);
+        let result = tokio::time::timeout(timeout, run_probe(&self.probe, external_address)).await;
+        result.unwrap_or_else(|_| Err(format!("timed out after {:?}", timeout)))
+    }
+
+    /// Whether `failures` probes in a row have just reached `failure_threshold`. A streak of
+    /// failures is only reported once, until a successful probe ends it.
+    pub fn reached_threshold(&self, failures: u32) -> bool {
+        failures == self.failure_threshold
+    }
+}
+
+/// Outcome of the latest probes, as shown in daemon status.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HealthStatus {
+    /// Result of the last probe; `None` until the first one ran.
+    pub healthy: Option<bool>,
+    /// Failed probes in a row.
+    pub consecutive_failures: u32,
+    /// Error of the last failed probe.
+    pub last_error: Option<String>,
+    /// Seconds since the Unix epoch of the last probe.
+    pub checked_at: Option<u64>,
+}
+
+impl HealthStatus {
+    /// Records the `result` of a probe run at `checked_at` (seconds since the Unix epoch),
+    /// returning the number of failed probes in a row.
+    pub fn record(&mut self, result: &Result<(), String>, checked_at: u64) -> u32 {
+        self.checked_at = Some(checked_at);
+        match result {
+            Ok(()) => {
+                self.healthy = Some(true);
+                self.consecutive_failures = 0;
+            }
+            Err(e) => {
+                self.healthy = Some(false);
+                self.consecutive_failures += 1;
+                self.last_error = Some(e.clone());
+            }
+        }
+        self.consecutive_failures
+    }
+}
+
+async fn run_probe(probe: &Probe, external_address: Option<&str>) -> Result<(), String> {
+    match probe {
+        Probe::Tcp { host, port } => tokio::net::TcpStream::connect((host.as_str(), *port))
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Probe::Http { url } => {
+            let url = url.as_deref().or(external_address).ok_or("no URL")?;
+            let response = client().get(url).send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            if status.is_success() || status.is_redirection() {
+                Ok(())
+            } else {
+                Err(format!("HTTP {}", status))
+            }
+        }
+        Probe::JsonRpc { url, method } => {
+            let url = url.as_deref().or(external_address).ok_or("no URL")?;
+            let reply: Value = client()
+                .post(url)
+                .json(&json!({ "jsonrpc": "2.0", "id": 0, "method": method }))
+                .send()
+                .await
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| format!("invalid JSON-RPC reply: {}", e))?;
+            if reply.get("result").is_some() || reply.get("error").is_some() {
+                Ok(())
+            } else {
+                Err("reply has neither `result` nor `error`".to_string())
+            }
+        }
+    }
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+fn default_method() -> String {
+    "ping".to_string()
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_timeout_ms() -> u64 {
+    2_000
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(probe: Probe) -> HealthCheck {
+        HealthCheck {
+            probe,
+            interval_ms: 10,
+            timeout_ms: 100,
+            failure_threshold: 3,
+        }
+    }
+
+    #[test]
+    fn failures_are_reported_once_the_threshold_is_reached() {
+        let check = check(Probe::Tcp {
+            host: default_host(),
+            port: 9,
+        });
+        let mut status = HealthStatus::default();
+        let failed = Err("refused".to_string());
+        let reported: Vec<bool> = (0..5)
+            .map(|_| check.reached_threshold(status.record(&failed, 1)))
+            .collect();
+        assert_eq!(reported, [false, false, true, false, false]);
+        assert_eq!(status.consecutive_failures, 5);
+        assert_eq!(status.healthy, Some(false));
+        assert_eq!(status.last_error.as_deref(), Some("refused"));
+    }
+
+    #[test]
+    fn a_successful_probe_resets_the_failures() {
+        let mut status = HealthStatus::default();
+        status.record(&Err("refused".to_string()), 1);
+        status.record(&Err("refused".to_string()), 2);
+        assert_eq!(status.record(&Ok(()), 3), 0);
+        assert_eq!(status.healthy, Some(true));
+        assert_eq!(status.checked_at, Some(3));
+        assert_eq!(status.record(&Err("refused".to_string()), 4), 1);
+    }
+
+    #[tokio::test]
+    async fn probes_time_out() {
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let error = check(Probe::Http { url: Some(url) })
+            .probe(None)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "timed out after 100ms");
+    }
+
+    #[tokio::test]
+    async fn tcp_probes_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let probe = Probe::Tcp {
+            host: default_host(),
+            port,
+        };
+        assert_eq!(check(probe).probe(None).await, Ok(()));
+    }
+}
@@ -0,0 +1,275 @@
+//! JSON-RPC 2.0 request handling shared by the MCP and RPC front-ends.
+//!
+//! [`dispatch`] implements the transport-independent parts of the specification: parse and
+//! invalid-request errors, echoing the request `id`, notifications that produce no reply and
+//! batch arrays.
+
+use std::fmt;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Invalid JSON was received.
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist or is not available.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+/// Internal JSON-RPC error.
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// A JSON-RPC 2.0 error object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// Error code.
+    pub code: i64,
+    /// Short description of the error.
+    pub message: String,
+    /// Additional information about the error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    /// Creates an error without `data`.
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Attaches `data` to the error.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// `-32601` for `method`.
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    /// `-32602` with the given reason.
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    /// `-32603` with the given reason.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(INTERNAL_ERROR, message)
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Builds the response envelope for request `id`.
+pub fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+/// A validated request object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// Request id, or `None` for a notification.
+    pub id: Option<Value>,
+    /// Method name.
+    pub method: String,
+    /// Parameters (`null` when omitted).
+    pub params: Value,
+}
+
+impl Request {
+    /// Whether the client expects no reply.
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+
+    /// Validates a single request object, returning the error response to send if invalid.
+    pub fn parse(value: Value) -> Result<Self, Value> {
+        let Value::Object(mut object) = value else {
+            return Err(response(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "Request must be an object")),
+            ));
+        };
+        let id = object.remove("id");
+        if id
+            .as_ref()
+            .is_some_and(|id| !(id.is_string() || id.is_number() || id.is_null()))
+        {
+            return Err(response(
+                Value::Null,
+                Err(RpcError::new(
+                    INVALID_REQUEST,
+                    "`id` must be a string, number or null",
+                )),
+            ));
+        }
+        let invalid = |message: &str| {
+            response(
+                id.clone().unwrap_or(Value::Null),
+                Err(RpcError::new(INVALID_REQUEST, message)),
+            )
+        };
+        if object.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Err(invalid("`jsonrpc` must be \"2.0\""));
+        }
+        let method = match object.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return Err(invalid("`method` must be a string")),
+        };
+        let params = object.remove("params").unwrap_or(Value::Null);
+        if !(params.is_object() || params.is_array() || params.is_null()) {
+            return Err(invalid("`params` must be an object or an array"));
+        }
+        Ok(Self { id, method, params })
+    }
+}
+
+/// Parses `payload` (a request or a batch) and runs `handler` for every valid request.
+///
+/// Returns the reply to send, or `None` when nothing must be sent (only notifications).
+pub async fn dispatch<H, Fut>(payload: &str, handler: H) -> Option<Value>
+where
+    H: Fn(Request) -> Fut,
+    Fut: Future<Output = Result<Value, RpcError>>,
+{
+    let value = match serde_json::from_str::<Value>(payload) {
+        Ok(value) => value,
+        Err(e) => {
+            return Some(response(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, format!("Parse error: {}", e))),
+            ))
+        }
+    };
+    match value {
+        Value::Array(batch) if batch.is_empty() => Some(response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "Empty batch")),
+        )),
+        Value::Array(batch) => {
+            let mut replies = Vec::new();
+            for value in batch {
+                replies.extend(dispatch_one(value, &handler).await);
+            }
+            (!replies.is_empty()).then_some(Value::Array(replies))
+        }
+        value => dispatch_one(value, &handler).await,
+    }
+}
+
+async fn dispatch_one<H, Fut>(value: Value, handler: &H) -> Option<Value>
+where
+    H: Fn(Request) -> Fut,
+    Fut: Future<Output = Result<Value, RpcError>>,
+{
+    let request = match Request::parse(value) {
+        Ok(request) => request,
+        Err(reply) => return Some(reply),
+    };
+    let id = request.id.clone();
+    let result = handler(request).await;
+    id.map(|id| response(id, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Echoes `params` for `echo`, and fails every other method.
+    async fn run(payload: &str) -> Option<Value> {
+        dispatch(payload, |request| async move {
+            match request.method.as_str() {
+                "echo" => Ok(request.params),
+                method => Err(RpcError::method_not_found(method)),
+            }
+        })
+        .await
+    }
+
+    fn error_code(reply: &Value) -> Option<i64> {
+        reply["error"]["code"].as_i64()
+    }
+
+    #[tokio::test]
+    async fn request_gets_a_reply_with_its_id() {
+        let reply = run(r#"{"jsonrpc":"2.0","id":"a","method":"echo","params":[1]}"#).await;
+        assert_eq!(
+            reply,
+            Some(json!({ "jsonrpc": "2.0", "id": "a", "result": [1] }))
+        );
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_reply() {
+        assert_eq!(run(r#"{"jsonrpc":"2.0","method":"echo"}"#).await, None);
+        assert_eq!(run(r#"{"jsonrpc":"2.0","method":"nope"}"#).await, None);
+        let batch = r#"[{"jsonrpc":"2.0","method":"echo"},{"jsonrpc":"2.0","method":"echo"}]"#;
+        assert_eq!(run(batch).await, None);
+    }
+
+    #[tokio::test]
+    async fn handler_errors_are_returned() {
+        let reply = run(r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(error_code(&reply), Some(METHOD_NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn malformed_payloads_are_rejected() {
+        let cases = [
+            ("{", PARSE_ERROR),
+            ("[]", INVALID_REQUEST),
+            ("1", INVALID_REQUEST),
+            (r#"{"id":1,"method":"echo"}"#, INVALID_REQUEST),
+            (r#"{"jsonrpc":"2.0","id":1,"method":2}"#, INVALID_REQUEST),
+            (
+                r#"{"jsonrpc":"2.0","id":{},"method":"echo"}"#,
+                INVALID_REQUEST,
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":3}"#,
+                INVALID_REQUEST,
+            ),
+        ];
+        for (payload, code) in cases {
+            let reply = run(payload).await.unwrap();
+            assert_eq!(error_code(&reply), Some(code), "{}", payload);
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_replies_skip_notifications() {
+        let batch = r#"[
+            {"jsonrpc":"2.0","id":1,"method":"echo","params":{"x":1}},
+            {"jsonrpc":"2.0","method":"echo"},
+            {"jsonrpc":"2.0","id":2,"method":"nope"},
+            7
+        ]"#;
+        let Some(Value::Array(replies)) = run(batch).await else {
+            panic!("expected a batch reply");
+        };
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["result"], json!({ "x": 1 }));
+        assert_eq!(error_code(&replies[1]), Some(METHOD_NOT_FOUND));
+        assert_eq!(replies[2]["id"], Value::Null);
+        assert_eq!(error_code(&replies[2]), Some(INVALID_REQUEST));
+    }
+}
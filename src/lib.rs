@@ -17,7 +17,37 @@ pub use cdd_engine::daemon;
 pub use cdd_engine::error;
 pub use cdd_engine::{ProcessConfig, ProcessManager};
 
-/// Configuration re-exports
-pub mod config {
-    pub use cdd_engine::config::*;
-}
+pub mod admin;
+pub mod auth;
+pub mod backoff;
+pub mod batch;
+pub mod cache;
+pub mod cli;
+pub mod config;
+pub mod doctor;
+pub mod executor;
+pub mod health;
+pub mod jsonrpc;
+pub mod logging;
+pub mod logs;
+pub mod mcp;
+pub mod mcp_http;
+pub mod metrics;
+pub mod openrpc;
+pub mod process;
+pub mod readiness;
+pub mod reload;
+pub mod rpc;
+pub mod rpc_http;
+pub mod rpc_ws;
+pub mod sandbox;
+pub mod shutdown;
+pub mod stdio;
+pub mod supervisor;
+pub mod telemetry;
+pub mod toolchain;
+pub mod wasm;
+pub use config::CtlConfig;
+pub use executor::{Executor, RuntimeSelector};
+pub use supervisor::Supervisor;
+pub use toolchain::{Runtime, ServeMode, Toolchain, ToolchainRegistry};
@@ -0,0 +1,187 @@
+//! Log output of the gateway binaries.
+//!
+//! Records are filtered by `RUST_LOG` (`info` by default) and written to stderr either as
+//! `env_logger` text lines or, with `--log-format json` (or `log_format: "json"` in the config
+//! file), as one JSON object per line:
+//!
+//! ```json
+//! {"timestamp":"2026-10-18T09:14:03.512Z","level":"INFO","target":"cdd_ctl::executor",
+//!  "message":"Ran cdd-go from_openapi: exit status: 0","pid":4101,
+//!  "request_id":"5f3a9c1e0b7d2468","process":"cdd-go","process_pid":4242}
+//! ```
+//!
+//! - `pid` is the gateway's own process id.
+//! - `request_id` identifies the HTTP request being handled. The [`assign_request_id`]
+//!   middleware takes it from the `X-Request-Id` request header, or generates one. It is
+//!   returned in the `X-Request-Id` response header, and one-shot toolchain runs get it as
+//!   `CDD_REQUEST_ID`.
+//! - `process` and `process_pid` name the supervised server or toolchain the record is about,
+//!   such as each line of its output.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Header carrying the request id, in both directions.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Environment variable carrying the request id into one-shot toolchain runs.
+pub const REQUEST_ID_VAR: &str = "CDD_REQUEST_ID";
+
+/// Longest request id accepted from a client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Format of the log records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// `env_logger`'s human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per record.
+    Json,
+}
+
+/// What the records logged by the current task are about.
+#[derive(Debug, Clone, Default)]
+struct Context {
+    request_id: Option<String>,
+    process: Option<String>,
+    process_pid: Option<u32>,
+}
+
+tokio::task_local! {
+    static CONTEXT: Context;
+}
+
+/// Installs the global logger.
+pub fn init(format: LogFormat) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut line = json!({
+                "timestamp": buf.timestamp_millis().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+                "pid": std::process::id(),
+            });
+            let _ = CONTEXT.try_with(|context| {
+                let fields = [
+                    ("request_id", context.request_id.clone().map(Value::from)),
+                    ("process", context.process.clone().map(Value::from)),
+                    ("process_pid", context.process_pid.map(Value::from)),
+                ];
+                for (key, value) in fields {
+                    if let Some(value) = value {
+                        line[key] = value;
+                    }
+                }
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+/// The id of the request the current task is handling, if any.
+pub fn request_id() -> Option<String> {
+    CONTEXT
+        .try_with(|context| context.request_id.clone())
+        .ok()
+        .flatten()
+}
+
+/// Runs `future` with its records attributed to `process` (and `pid`, once known), keeping the
+/// request id of the caller.
+pub async fn with_process<F: Future>(process: String, pid: Option<u32>, future: F) -> F::Output {
+    let mut context = CONTEXT.try_with(Context::clone).unwrap_or_default();
+    context.process = Some(process);
+    context.process_pid = pid;
+    CONTEXT.scope(context, future).await
+}
+
+/// Middleware running every request with a request id in its log records and returning the id
+/// in `X-Request-Id`. Register it outermost with
+/// `.wrap(actix_web::middleware::from_fn(logging::assign_request_id))`.
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+    let context = Context {
+        request_id: Some(id.clone()),
+        ..Context::default()
+    };
+    let mut response = CONTEXT.scope(context, next.call(req)).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+/// Whether a client-supplied id is short and plain enough to be logged and echoed back.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+/// A fresh, random request id.
+fn new_request_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(NEXT.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_ids_must_be_short_and_plain() {
+        assert!(is_valid_request_id("abc-123_4.5:6"));
+        assert!(is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN)));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+        assert!(!is_valid_request_id("a b"));
+        assert!(!is_valid_request_id("a\nb"));
+        assert!(!is_valid_request_id("é"));
+    }
+
+    #[test]
+    fn generated_request_ids_are_valid_and_distinct() {
+        let (a, b) = (new_request_id(), new_request_id());
+        assert!(is_valid_request_id(&a));
+        assert_eq!(a.len(), 16);
+        assert_ne!(a, b);
+    }
+}
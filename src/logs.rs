@@ -0,0 +1,335 @@
+//! Per-process log capture.
+//!
+//! Every line a supervised process writes is kept in a bounded in-memory [`ProcessLog`] (the
+//! last `buffer_lines` lines) and, if `logs.file` is set, appended to a log file that is
+//! rotated once it exceeds `max_file_bytes` (`<file>.1` is the newest rotated file, up to
+//! `<file>.<max_files>`). Files are written on a thread of their own, so that capturing a line
+//! never waits on the filesystem. The admin API serves the buffer at `GET /daemons/{name}/logs`, and
+//! [`print_remote`] is the client behind `cdd-ctl logs`.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::supervisor::LogStream;
+
+/// Log capture of one server, configured as `servers.<name>.logs`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LogOptions {
+    /// Lines kept in memory for the log-tail API.
+    pub buffer_lines: usize,
+    /// File the lines are also appended to.
+    pub file: Option<PathBuf>,
+    /// Size after which the file is rotated, in bytes.
+    pub max_file_bytes: u64,
+    /// Rotated files kept besides the current one.
+    pub max_files: u32,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self {
+            buffer_lines: 1000,
+            file: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+impl LogOptions {
+    /// Checks that the log file can be created and rotated.
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if self.max_file_bytes == 0 || self.max_files == 0 {
+            return Err("`max_file_bytes` and `max_files` must be positive".to_string());
+        }
+        match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                Err(format!("`file`: {} is not a directory", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One line of output of a supervised process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// Milliseconds since the Unix epoch when the line was read.
+    pub timestamp_ms: u64,
+    /// Where the line was written.
+    pub stream: LogStream,
+    /// The line, without its terminator.
+    pub line: String,
+}
+
+/// The captured output of one supervised process.
+pub struct ProcessLog {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    file: Option<FileWriter>,
+}
+
+impl ProcessLog {
+    /// An empty log capturing according to `options`.
+    pub fn new(options: &LogOptions) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(options.buffer_lines.min(1024)),
+            capacity: options.buffer_lines,
+            file: options.file.as_ref().and_then(|path| {
+                let file = RotatingFile {
+                    path: path.clone(),
+                    max_bytes: options.max_file_bytes,
+                    max_files: options.max_files,
+                    file: None,
+                    size: 0,
+                };
+                FileWriter::spawn(file)
+                    .map_err(|e| log::error!("Cannot log to {}: {}", path.display(), e))
+                    .ok()
+            }),
+        }
+    }
+
+    /// Records `line`, evicting the oldest one once the buffer is full.
+    pub fn push(&mut self, line: LogLine) {
+        // Fails once the writer has given up on the file, which it has logged.
+        if let Some(file) = &self.file {
+            if file.lines.send(line.clone()).is_err() {
+                self.file = None;
+            }
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The last `n` lines, oldest first.
+    pub fn tail(&self, n: usize) -> Vec<LogLine> {
+        let skip = self.lines.len().saturating_sub(n);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Appends the lines it is sent to a [`RotatingFile`] on a thread of its own, which exits
+/// once the writer is dropped.
+struct FileWriter {
+    lines: mpsc::Sender<LogLine>,
+}
+
+impl FileWriter {
+    fn spawn(mut file: RotatingFile) -> io::Result<Self> {
+        let (lines, received) = mpsc::channel::<LogLine>();
+        std::thread::Builder::new()
+            .name("cdd-ctl-logs".to_string())
+            .spawn(move || {
+                for line in received {
+                    if let Err(e) = file.write(&line) {
+                        log::error!(
+                            "Cannot write to {}, no longer logging to it: {}",
+                            file.path.display(),
+                            e
+                        );
+                        return;
+                    }
+                }
+            })?;
+        Ok(Self { lines })
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn write(&mut self, line: &LogLine) -> io::Result<()> {
+        let text = format!(
+            "{} {} {}\n",
+            line.timestamp_ms,
+            match line.stream {
+                LogStream::Stdout => "stdout",
+                LogStream::Stderr => "stderr",
+            },
+            line.line
+        );
+        if self.file.is_some() && self.size + text.len() as u64 > self.max_bytes {
+            self.file = None;
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(text.as_bytes())?;
+            self.size += text.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Shifts `<file>.1`.. up by one, dropping the oldest, and moves the file to `<file>.1`.
+    fn rotate(&self) -> io::Result<()> {
+        for n in (1..self.max_files).rev() {
+            let from = numbered(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, numbered(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, numbered(&self.path, 1))
+    }
+}
+
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Prints the last `tail` lines of the server `name` from the admin API of the gateway at
+/// `base_url`, and keeps printing new ones if `follow` is set. Lines the process wrote to
+/// stderr go to stderr.
+pub async fn print_remote(
+    base_url: &str,
+    token: Option<&str>,
+    name: &str,
+    tail: usize,
+    follow: bool,
+) -> Result<(), String> {
+    let url = format!("{}/daemons/{}/logs", base_url.trim_end_matches('/'), name);
+    let mut request = reqwest::Client::new()
+        .get(&url)
+        .query(&[("tail", tail.to_string()), ("follow", follow.to_string())]);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("{}: {} {}", url, status, body.trim()));
+    }
+
+    if !follow {
+        let lines: Vec<LogLine> = response.json().await.map_err(|e| e.to_string())?;
+        lines.iter().for_each(print_line);
+        return Ok(());
+    }
+    // Server-sent events, one `log` event per line.
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|l| l.strip_prefix("data: "))
+                .collect();
+            if let Ok(line) = serde_json::from_str::<LogLine>(&data.join("\n")) {
+                print_line(&line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_line(line: &LogLine) {
+    match line.stream {
+        LogStream::Stdout => println!("{}", line.line),
+        LogStream::Stderr => eprintln!("{}", line.line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: u64) -> LogLine {
+        LogLine {
+            timestamp_ms: n,
+            stream: LogStream::Stdout,
+            line: format!("line {}", n),
+        }
+    }
+
+    #[test]
+    fn buffer_keeps_the_last_lines() {
+        let mut log = ProcessLog::new(&LogOptions {
+            buffer_lines: 2,
+            ..LogOptions::default()
+        });
+        (0..3).map(line).for_each(|l| log.push(l));
+        assert_eq!(log.tail(10), [line(1), line(2)]);
+        assert_eq!(log.tail(1), [line(2)]);
+    }
+
+    #[test]
+    fn file_is_rotated_past_its_size() {
+        let dir = std::env::temp_dir().join(format!("cdd-ctl-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cdd-go.log");
+        // "<n> stdout line <n>\n" is 16 bytes for a single digit: two lines per file.
+        let mut log = ProcessLog::new(&LogOptions {
+            buffer_lines: 0,
+            file: Some(path.clone()),
+            max_file_bytes: 32,
+            max_files: 2,
+        });
+        (0..7).map(line).for_each(|l| log.push(l));
+        assert!(log.tail(10).is_empty());
+
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+        // The lines are written in the background.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while read(path.clone()) != "6 stdout line 6\n" && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(read(path.clone()), "6 stdout line 6\n");
+        assert_eq!(
+            read(numbered(&path, 1)),
+            "4 stdout line 4\n5 stdout line 5\n"
+        );
+        assert_eq!(
+            read(numbered(&path, 2)),
+            "2 stdout line 2\n3 stdout line 3\n"
+        );
+        assert!(!numbered(&path, 3).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_needs_a_size_and_a_directory() {
+        let options = |file: &str, max_files| LogOptions {
+            file: Some(PathBuf::from(file)),
+            max_files,
+            ..LogOptions::default()
+        };
+        assert_eq!(options("cdd-go.log", 5).validate(), Ok(()));
+        assert!(options("cdd-go.log", 0).validate().is_err());
+        assert!(options("/nonexistent/cdd-go.log", 5).validate().is_err());
+    }
+}
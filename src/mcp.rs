@@ -0,0 +1,622 @@
+//! Aggregating MCP server in front of every language backend.
+//!
+//! Each server of the [`Supervisor`] is an MCP backend, reached either over newline-delimited
+//! JSON-RPC on the stdio of its supervised `cdd-<lang> mcp` process (a [`StdioClient`]), or
+//! over the Streamable HTTP transport at its `external_address`. Nothing is spawned here: the
+//! supervisor runs and restarts the processes, and a backend is initialized again whenever its
+//! process has been replaced. [`McpAggregator`] presents the backends as a single server:
+//! tools are exposed as `<lang>.<tool>` (e.g. `go.from_openapi`), `initialize` capabilities are
+//! merged and `tools/call` is routed to the backend owning the tool. A backend that is down is
+//! left out of `tools/list` instead of failing it, and is reconnected on a later request.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+
+use crate::jsonrpc::RpcError;
+use crate::stdio::StdioClient;
+use crate::supervisor::Supervisor;
+use crate::telemetry;
+
+/// MCP protocol revision spoken by the aggregator.
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Separates the language prefix from the backend's own tool name.
+const TOOL_SEPARATOR: char = '.';
+/// Deadline for handshakes and listings.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Deadline for `tools/call`, which may run a whole generator.
+const CALL_TIMEOUT: Duration = Duration::from_secs(600);
+/// Time an unreachable external backend is reported as down before it is tried again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// How requests reach a backend.
+#[derive(Clone)]
+enum Channel {
+    /// Newline-delimited JSON-RPC over the stdio of the supervised process.
+    Stdio(Arc<StdioClient>),
+    /// Streamable HTTP `POST`s to a remote MCP endpoint.
+    Http {
+        client: reqwest::Client,
+        url: String,
+        session: Option<String>,
+        alive: Arc<AtomicBool>,
+    },
+}
+
+/// An initialized connection to a backend.
+struct Connection {
+    channel: Channel,
+    initialize: Value,
+}
+
+/// One language backend, initialized lazily and again after its process is replaced.
+pub struct McpBackend {
+    name: String,
+    external_address: Option<String>,
+    supervisor: Arc<Supervisor>,
+    next_id: AtomicU64,
+    connection: Mutex<Option<Connection>>,
+    last_failure: std::sync::Mutex<Option<Instant>>,
+}
+
+impl McpBackend {
+    /// Creates a backend for the server `name` (e.g. `cdd-go`) of `supervisor`, served at
+    /// `external_address` if it is external; nothing is connected yet.
+    pub fn new(
+        name: impl Into<String>,
+        external_address: Option<String>,
+        supervisor: Arc<Supervisor>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            external_address,
+            supervisor,
+            next_id: AtomicU64::new(1),
+            connection: Mutex::new(None),
+            last_failure: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Server name, e.g. `cdd-go`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tool prefix, e.g. `go`.
+    pub fn language(&self) -> &str {
+        self.name.strip_prefix("cdd-").unwrap_or(&self.name)
+    }
+
+    /// Whether the backend currently has a live, initialized connection.
+    pub async fn is_connected(&self) -> bool {
+        self.connection
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|c| c.channel.is_alive())
+    }
+
+    /// The backend's `initialize` result, connecting first if needed.
+    pub async fn initialize_result(&self) -> Result<Value, RpcError> {
+        let mut connection = self.connection.lock().await;
+        self.ensure_connected(&mut connection).await?;
+        Ok(connection
+            .as_ref()
+            .map(|c| c.initialize.clone())
+            .unwrap_or_default())
+    }
+
+    /// Sends `method` and waits up to `timeout` for the backend's reply, passing on the
+    /// current trace in `params._meta`.
+    #[tracing::instrument(
+        name = "mcp.request",
+        skip_all,
+        fields(otel.kind = "client", server = %self.name, rpc.method = %method)
+    )]
+    pub async fn request(
+        &self,
+        method: &str,
+        mut params: Value,
+        timeout: Duration,
+    ) -> Result<Value, RpcError> {
+        telemetry::inject(&mut params);
+        let channel = {
+            let mut connection = self.connection.lock().await;
+            self.ensure_connected(&mut connection).await?;
+            let Some(c) = connection.as_ref() else {
+                return Err(self.unavailable("not connected"));
+            };
+            c.channel.clone()
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        channel
+            .call(&self.name, id, method, params, timeout)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn ensure_connected(&self, connection: &mut Option<Connection>) -> Result<(), RpcError> {
+        if connection.as_ref().is_some_and(|c| c.channel.is_alive()) {
+            return Ok(());
+        }
+        *connection = None;
+
+        let channel = match &self.external_address {
+            // The supervisor hands out a new client for every process it spawns.
+            None => Channel::Stdio(
+                self.supervisor
+                    .client(&self.name)
+                    .map_err(|e| self.unavailable(e))?,
+            ),
+            Some(address) => {
+                // Do not hammer a backend that just failed; report it as down for a while.
+                let recently_failed = self
+                    .last_failure
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .is_some_and(|at| at.elapsed() < RECONNECT_DELAY);
+                if recently_failed {
+                    return Err(self.unavailable("reconnecting"));
+                }
+                Channel::Http {
+                    client: reqwest::Client::new(),
+                    url: if address.contains("://") {
+                        address.clone()
+                    } else {
+                        format!("http://{}", address)
+                    },
+                    session: None,
+                    alive: Arc::new(AtomicBool::new(true)),
+                }
+            }
+        };
+
+        match self.connect(channel).await {
+            Ok(c) => {
+                *connection = Some(c);
+                Ok(())
+            }
+            Err(e) => {
+                *self.last_failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+                log::warn!("MCP backend {} is unavailable: {}", self.name, e.message);
+                Err(e)
+            }
+        }
+    }
+
+    /// Performs the `initialize` handshake over `channel`.
+    async fn connect(&self, mut channel: Channel) -> Result<Connection, RpcError> {
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "cdd-ctl", "version": env!("CARGO_PKG_VERSION") },
+        });
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (initialize, session) = channel
+            .call(&self.name, id, "initialize", params, REQUEST_TIMEOUT)
+            .await?;
+        if let Channel::Http {
+            session: current, ..
+        } = &mut channel
+        {
+            *current = session;
+        }
+        channel
+            .notify(&self.name, "notifications/initialized")
+            .await
+            .map_err(|e| self.unavailable(e))?;
+
+        log::info!("Connected to MCP backend {}", self.name);
+        Ok(Connection {
+            channel,
+            initialize,
+        })
+    }
+
+    fn unavailable(&self, reason: impl std::fmt::Display) -> RpcError {
+        RpcError::internal(format!("Backend {} is unavailable: {}", self.name, reason))
+    }
+}
+
+/// Presents every server of a [`Supervisor`] as one MCP server with `<lang>.<tool>` names.
+pub struct McpAggregator {
+    supervisor: Arc<Supervisor>,
+    backends: std::sync::Mutex<BTreeMap<String, Arc<McpBackend>>>,
+}
+
+impl McpAggregator {
+    /// Creates an aggregator over the servers of `supervisor`, which must run them in MCP mode.
+    pub fn new(supervisor: Arc<Supervisor>) -> Self {
+        Self {
+            supervisor,
+            backends: std::sync::Mutex::default(),
+        }
+    }
+
+    /// One backend per server currently configured in the supervisor, ordered by name. A
+    /// server that was reloaded with a different `external_address` gets a new backend.
+    pub fn backends(&self) -> Vec<Arc<McpBackend>> {
+        let servers = self.supervisor.list();
+        let mut backends = self.backends.lock().unwrap_or_else(|e| e.into_inner());
+        backends.retain(|name, backend| {
+            servers
+                .iter()
+                .any(|s| &s.name == name && s.external_address == backend.external_address)
+        });
+        for server in servers {
+            backends.entry(server.name.clone()).or_insert_with(|| {
+                Arc::new(McpBackend::new(
+                    server.name,
+                    server.external_address,
+                    self.supervisor.clone(),
+                ))
+            });
+        }
+        backends.values().cloned().collect()
+    }
+
+    /// Handles one MCP request from the client.
+    #[tracing::instrument(name = "mcp.handle", skip_all, fields(rpc.method = %method))]
+    pub async fn handle(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(self.initialize(&params).await),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(self.list_tools().await),
+            "tools/call" => self.call_tool(params).await,
+            // Client notifications (`initialized`, `cancelled`, ...) need no action.
+            _ if method.starts_with("notifications/") => Ok(Value::Null),
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    async fn initialize(&self, params: &Value) -> Value {
+        let results = self
+            .each(|backend| async move { backend.initialize_result().await })
+            .await;
+
+        // Union of the backends' capabilities; tools are always offered by the aggregator.
+        let mut capabilities = Map::new();
+        for (_, result) in &results {
+            let Ok(init) = result else { continue };
+            if let Some(caps) = init.get("capabilities").and_then(Value::as_object) {
+                for (key, value) in caps {
+                    match capabilities.get_mut(key) {
+                        Some(Value::Object(existing)) => {
+                            if let Value::Object(value) = value {
+                                for (k, v) in value {
+                                    existing.entry(k.clone()).or_insert_with(|| v.clone());
+                                }
+                            }
+                        }
+                        Some(_) => {}
+                        None => {
+                            capabilities.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        capabilities
+            .entry("tools".to_string())
+            .or_insert_with(|| json!({}));
+
+        let languages: Vec<&str> = results
+            .iter()
+            .filter(|(_, r)| r.is_ok())
+            .map(|(backend, _)| backend.language())
+            .collect();
+        json!({
+            "protocolVersion": params
+                .get("protocolVersion")
+                .and_then(Value::as_str)
+                .unwrap_or(PROTOCOL_VERSION),
+            "capabilities": capabilities,
+            "serverInfo": { "name": "cdd-ctl", "version": env!("CARGO_PKG_VERSION") },
+            "instructions": format!(
+                "Tools are namespaced by language as `<language>{}<tool>`. Available languages: {}.",
+                TOOL_SEPARATOR,
+                languages.join(", ")
+            ),
+        })
+    }
+
+    async fn list_tools(&self) -> Value {
+        let results = self
+            .each(|backend| async move { list_backend_tools(&backend).await })
+            .await;
+        let mut tools = Vec::new();
+        for (backend, result) in results {
+            match result {
+                Ok(backend_tools) => tools.extend(backend_tools),
+                Err(e) => log::warn!(
+                    "Omitting tools of {} from tools/list: {}",
+                    backend.name(),
+                    e.message
+                ),
+            }
+        }
+        json!({ "tools": tools })
+    }
+
+    async fn call_tool(&self, mut params: Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::invalid_params("tools/call requires a `name`"))?;
+        let (language, tool) = name.split_once(TOOL_SEPARATOR).ok_or_else(|| {
+            RpcError::invalid_params(format!(
+                "Tool {} is not namespaced as <language>{}<tool>",
+                name, TOOL_SEPARATOR
+            ))
+        })?;
+        let backend = self
+            .backends()
+            .into_iter()
+            .find(|b| b.language() == language)
+            .ok_or_else(|| RpcError::invalid_params(format!("Unknown language: {}", language)))?;
+        params["name"] = Value::String(tool.to_string());
+        backend.request("tools/call", params, CALL_TIMEOUT).await
+    }
+
+    /// Runs `f` against every backend concurrently, returning results in backend order.
+    async fn each<F, Fut, T>(&self, f: F) -> Vec<(Arc<McpBackend>, Result<T, RpcError>)>
+    where
+        F: Fn(Arc<McpBackend>) -> Fut,
+        Fut: std::future::Future<Output = Result<T, RpcError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let backends = self.backends();
+        let mut set = JoinSet::new();
+        for (idx, backend) in backends.iter().enumerate() {
+            let fut = f(backend.clone());
+            set.spawn(async move { (idx, fut.await) });
+        }
+        let mut results: Vec<Option<Result<T, RpcError>>> =
+            (0..backends.len()).map(|_| None).collect();
+        while let Some(joined) = set.join_next().await {
+            if let Ok((idx, result)) = joined {
+                results[idx] = Some(result);
+            }
+        }
+        backends
+            .into_iter()
+            .zip(results)
+            .map(|(backend, result)| {
+                let result =
+                    result.unwrap_or_else(|| Err(RpcError::internal("backend task panicked")));
+                (backend, result)
+            })
+            .collect()
+    }
+}
+
+/// Collects every page of `tools/list` from `backend`, prefixing the tool names.
+async fn list_backend_tools(backend: &McpBackend) -> Result<Vec<Value>, RpcError> {
+    let mut tools = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let params = match &cursor {
+            Some(cursor) => json!({ "cursor": cursor }),
+            None => json!({}),
+        };
+        let page = backend
+            .request("tools/list", params, REQUEST_TIMEOUT)
+            .await?;
+        for mut tool in page
+            .get("tools")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+        {
+            if let Some(name) = tool.get("name").and_then(Value::as_str) {
+                tool["name"] =
+                    Value::String(format!("{}{}{}", backend.language(), TOOL_SEPARATOR, name));
+                tools.push(tool);
+            }
+        }
+        cursor = page
+            .get("nextCursor")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if cursor.is_none() {
+            return Ok(tools);
+        }
+    }
+}
+
+impl Channel {
+    /// Sends request `id` and waits for its reply, also returning the HTTP session id the
+    /// backend assigned, if any.
+    async fn call(
+        &self,
+        name: &str,
+        id: u64,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<(Value, Option<String>), RpcError> {
+        match self {
+            Channel::Stdio(client) => client
+                .request(method, params, timeout)
+                .await
+                .map(|result| (result, None)),
+            Channel::Http { .. } => {
+                let message =
+                    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+                let (session, body) = self.post(name, &message, timeout).await?;
+                let reply = find_reply(&body, id).ok_or_else(|| {
+                    RpcError::internal(format!("Backend {} sent no reply to {}", name, method))
+                })?;
+                parse_reply(&reply).map(|result| (result, session))
+            }
+        }
+    }
+
+    /// Sends a notification.
+    async fn notify(&self, name: &str, method: &str) -> Result<(), RpcError> {
+        match self {
+            Channel::Stdio(client) => client.notify(method, Value::Null).await,
+            Channel::Http { .. } => {
+                let message = json!({ "jsonrpc": "2.0", "method": method });
+                self.post(name, &message, REQUEST_TIMEOUT).await.map(drop)
+            }
+        }
+    }
+
+    /// Whether the connection can still be used: the process behind a stdio channel is still
+    /// the same, and an HTTP session has not failed.
+    fn is_alive(&self) -> bool {
+        match self {
+            Channel::Stdio(client) => !client.is_closed(),
+            Channel::Http { alive, .. } => alive.load(Ordering::SeqCst),
+        }
+    }
+
+    /// `POST`s `message` to a Streamable HTTP endpoint, returning the session id header and
+    /// the response body (JSON or an event stream).
+    async fn post(
+        &self,
+        name: &str,
+        message: &Value,
+        timeout: Duration,
+    ) -> Result<(Option<String>, String), RpcError> {
+        let Channel::Http {
+            client,
+            url,
+            session,
+            alive,
+        } = self
+        else {
+            return Err(RpcError::internal("not an HTTP backend"));
+        };
+        let mut request = client
+            .post(url)
+            .timeout(timeout)
+            .header(
+                reqwest::header::ACCEPT,
+                "application/json, text/event-stream",
+            )
+            .json(message);
+        if let Some(session) = session {
+            request = request.header(crate::mcp_http::SESSION_HEADER, session);
+        }
+        if let Some(traceparent) = telemetry::traceparent() {
+            request = request.header(telemetry::TRACEPARENT, traceparent);
+        }
+        let failed = |reason: String| {
+            // A transport failure or an expired session requires a new `initialize`.
+            alive.store(false, Ordering::SeqCst);
+            RpcError::internal(format!("Backend {} failed: {}", name, reason))
+        };
+        let response = request.send().await.map_err(|e| failed(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(failed(format!("HTTP {}", response.status())));
+        }
+        let session = response
+            .headers()
+            .get(crate::mcp_http::SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await.map_err(|e| failed(e.to_string()))?;
+        Ok((session, body))
+    }
+}
+
+/// Picks the reply to request `id` out of a JSON or `text/event-stream` response body.
+fn find_reply(body: &str, id: u64) -> Option<Value> {
+    let matches = |value: &Value| value.get("id").and_then(Value::as_u64) == Some(id);
+    if let Ok(value) = serde_json::from_str::<Value>(body) {
+        return match value {
+            Value::Array(batch) => batch.into_iter().find(matches),
+            value => matches(&value).then_some(value),
+        };
+    }
+    body.split("\n\n").find_map(|event| {
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(str::trim_start)
+            .collect();
+        serde_json::from_str::<Value>(&data.join("\n"))
+            .ok()
+            .filter(matches)
+    })
+}
+
+/// Converts a response object into the call's result.
+pub(crate) fn parse_reply(message: &Value) -> Result<Value, RpcError> {
+    match message.get("error") {
+        Some(error) => Err(serde_json::from_value(error.clone())
+            .unwrap_or_else(|_| RpcError::internal(error.to_string()))),
+        None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::ProcessConfig;
+
+    fn external(address: &str) -> ProcessConfig {
+        ProcessConfig {
+            command: None,
+            args: None,
+            external_address: Some(address.to_string()),
+            max_retries: 0,
+            restart_delay_ms: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn backends_follow_the_supervisor() {
+        let supervisor = Arc::new(Supervisor::new(HashMap::from([
+            ("cdd-go".to_string(), external("127.0.0.1:9001")),
+            ("cdd-rust".to_string(), external("127.0.0.1:9002")),
+        ])));
+        let aggregator = McpAggregator::new(supervisor.clone());
+        let before = aggregator.backends();
+        let languages: Vec<&str> = before.iter().map(|b| b.language()).collect();
+        assert_eq!(languages, ["go", "rust"]);
+        assert!(Arc::ptr_eq(&before[0], &aggregator.backends()[0]));
+
+        let servers = HashMap::from([("cdd-go".to_string(), external("127.0.0.1:9003"))]);
+        supervisor.reload(servers, &HashMap::new()).await;
+        let after = aggregator.backends();
+        assert_eq!(after.len(), 1);
+        assert!(!Arc::ptr_eq(&before[0], &after[0]));
+    }
+
+    #[test]
+    fn reply_is_found_in_json_and_event_streams() {
+        let reply = json!({ "jsonrpc": "2.0", "id": 2, "result": {} });
+        assert_eq!(find_reply(&reply.to_string(), 2), Some(reply.clone()));
+        assert_eq!(find_reply(&reply.to_string(), 3), None);
+        assert_eq!(
+            find_reply(
+                &json!([{ "jsonrpc": "2.0", "id": 1 }, reply]).to_string(),
+                2
+            ),
+            Some(reply.clone())
+        );
+        let stream = format!("event: message\ndata: {}\n\n", reply);
+        assert_eq!(find_reply(&stream, 2), Some(reply));
+    }
+
+    #[test]
+    fn error_replies_become_errors() {
+        let error = json!({ "id": 1, "error": { "code": -32601, "message": "nope" } });
+        assert_eq!(
+            parse_reply(&error).unwrap_err().code,
+            crate::jsonrpc::METHOD_NOT_FOUND
+        );
+        assert_eq!(parse_reply(&json!({ "id": 1 })), Ok(Value::Null));
+    }
+}
@@ -0,0 +1,381 @@
+//! MCP over HTTP, mounted on the gateway next to `api::configure`.
+//!
+//! Serves the shared [`McpAggregator`] to remote agents over both HTTP transports of the MCP
+//! specification:
+//!
+//! - Streamable HTTP at `/mcp`: `POST` a JSON-RPC message or batch and receive the reply as
+//!   `application/json` (or as a single `text/event-stream` event when that is the only type
+//!   the client accepts); `DELETE` ends the session issued on `initialize`.
+//! - HTTP+SSE at `/mcp/sse`: the stream announces a `/mcp/messages?sessionId=...` endpoint and
+//!   carries the replies to messages posted there.
+//!
+//! Every route requires a gateway-issued bearer token (see [`crate::auth`]). Session ids come
+//! from the OS random number generator. At most [`MAX_SESSIONS`] sessions of each transport
+//! are open at a time, and a Streamable HTTP session unused for [`SESSION_TTL`] expires.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::http::header;
+use actix_web::web::{self, Bytes};
+use actix_web::{middleware, HttpRequest, HttpResponse};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::auth;
+use crate::jsonrpc;
+use crate::mcp::McpAggregator;
+
+/// Header carrying the Streamable HTTP session id.
+pub const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// Interval between SSE keep-alive comments.
+pub(crate) const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Streamable HTTP sessions unused for this long are forgotten.
+pub const SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Open sessions allowed per transport; further ones are refused with `503`.
+pub const MAX_SESSIONS: usize = 1024;
+
+/// Shared state of the MCP endpoints, registered as `web::Data<McpState>`.
+pub struct McpState {
+    aggregator: Arc<McpAggregator>,
+    sessions: Mutex<Sessions>,
+    streams: Mutex<HashMap<String, mpsc::Sender<Value>>>,
+}
+
+impl McpState {
+    /// Serves `aggregator`, which is shared with the rest of the process.
+    pub fn new(aggregator: Arc<McpAggregator>) -> Self {
+        Self {
+            aggregator,
+            sessions: Mutex::new(Sessions::new(SESSION_TTL, MAX_SESSIONS)),
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The aggregator behind the endpoints.
+    pub fn aggregator(&self) -> &Arc<McpAggregator> {
+        &self.aggregator
+    }
+
+    fn sessions(&self) -> std::sync::MutexGuard<'_, Sessions> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn dispatch(&self, body: &str) -> Option<Value> {
+        jsonrpc::dispatch(body, |request| {
+            let aggregator = self.aggregator.clone();
+            async move { aggregator.handle(&request.method, request.params).await }
+        })
+        .await
+    }
+}
+
+/// Mounts the `/mcp` routes. Requires `web::Data<McpState>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/mcp")
+            .wrap(middleware::from_fn(auth::resolve_caller))
+            .wrap(HttpAuthentication::bearer(auth::validator))
+            .route("", web::post().to(post_message))
+            .route("", web::get().to(open_stream))
+            .route("", web::delete().to(end_session))
+            .route("/sse", web::get().to(open_sse))
+            .route("/messages", web::post().to(post_sse_message)),
+    );
+}
+
+/// `POST /mcp`: one Streamable HTTP round trip.
+async fn post_message(state: web::Data<McpState>, req: HttpRequest, body: String) -> HttpResponse {
+    let session = req
+        .headers()
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    if session
+        .as_ref()
+        .is_some_and(|id| !state.sessions().touch(id, Instant::now()))
+    {
+        return HttpResponse::NotFound().body("Unknown MCP session");
+    }
+
+    let is_initialize = serde_json::from_str::<Value>(&body)
+        .ok()
+        .is_some_and(|v| v.get("method").and_then(Value::as_str) == Some("initialize"));
+    if is_initialize && state.sessions().is_full(Instant::now()) {
+        return HttpResponse::ServiceUnavailable().body("Too many MCP sessions");
+    }
+
+    let Some(reply) = state.dispatch(&body).await else {
+        return HttpResponse::Accepted().finish();
+    };
+
+    let mut response = HttpResponse::Ok();
+    if is_initialize {
+        let id = match new_session_id() {
+            Ok(id) => id,
+            Err(e) => return session_id_failed(e),
+        };
+        // Another client may have taken the last slot while this one was being initialized.
+        if !state.sessions().open(id.clone(), Instant::now()) {
+            return HttpResponse::ServiceUnavailable().body("Too many MCP sessions");
+        }
+        response.insert_header((SESSION_HEADER, id));
+    }
+    if wants_event_stream(&req) {
+        response
+            .content_type("text/event-stream")
+            .body(sse_event("message", &reply.to_string()))
+    } else {
+        response.json(reply)
+    }
+}
+
+/// `GET /mcp`: the aggregator never initiates messages, so no standalone stream is offered.
+async fn open_stream() -> HttpResponse {
+    HttpResponse::MethodNotAllowed()
+        .insert_header((header::ALLOW, "POST, DELETE"))
+        .finish()
+}
+
+/// `DELETE /mcp`: ends a Streamable HTTP session.
+async fn end_session(state: web::Data<McpState>, req: HttpRequest) -> HttpResponse {
+    let removed = req
+        .headers()
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|id| state.sessions().close(id));
+    if removed {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body("Unknown MCP session")
+    }
+}
+
+/// `GET /mcp/sse`: opens an HTTP+SSE session.
+async fn open_sse(state: web::Data<McpState>) -> HttpResponse {
+    let id = match new_session_id() {
+        Ok(id) => id,
+        Err(e) => return session_id_failed(e),
+    };
+    let (tx, rx) = mpsc::channel(64);
+    {
+        let mut streams = state.streams.lock().unwrap_or_else(|e| e.into_inner());
+        if streams.len() >= MAX_SESSIONS {
+            return HttpResponse::ServiceUnavailable().body("Too many MCP sessions");
+        }
+        streams.insert(id.clone(), tx);
+    }
+
+    let stream = SseStream {
+        endpoint: Some(format!("/mcp/messages?sessionId={}", id)),
+        rx,
+        keepalive: tokio::time::interval(KEEPALIVE),
+        _guard: StreamGuard {
+            state: state.into_inner(),
+            id,
+        },
+    };
+    let body = futures_util::stream::unfold(stream, |mut stream| async move {
+        if let Some(endpoint) = stream.endpoint.take() {
+            return Some((
+                Ok::<_, Infallible>(sse_event("endpoint", &endpoint)),
+                stream,
+            ));
+        }
+        tokio::select! {
+            message = stream.rx.recv() => {
+                message.map(|m| (Ok(sse_event("message", &m.to_string())), stream))
+            }
+            _ = stream.keepalive.tick() => Some((Ok(Bytes::from_static(b": keepalive\n\n")), stream)),
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+/// `POST /mcp/messages?sessionId=...`: the reply is delivered on the session's SSE stream.
+async fn post_sse_message(
+    state: web::Data<McpState>,
+    query: web::Query<SessionQuery>,
+    body: String,
+) -> HttpResponse {
+    let sender = state
+        .streams
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&query.session_id)
+        .cloned();
+    let Some(sender) = sender else {
+        return HttpResponse::NotFound().body("Unknown MCP session");
+    };
+    let state = state.into_inner();
+    tokio::spawn(async move {
+        if let Some(reply) = state.dispatch(&body).await {
+            let _ = sender.send(reply).await;
+        }
+    });
+    HttpResponse::Accepted().finish()
+}
+
+struct SseStream {
+    endpoint: Option<String>,
+    rx: mpsc::Receiver<Value>,
+    keepalive: tokio::time::Interval,
+    _guard: StreamGuard,
+}
+
+/// Forgets the SSE session once its stream is dropped (client disconnected).
+struct StreamGuard {
+    state: Arc<McpState>,
+    id: String,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.state
+            .streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+pub(crate) fn sse_event(event: &str, data: &str) -> Bytes {
+    let mut out = format!("event: {}\n", event);
+    for line in data.lines() {
+        out.push_str("data: ");
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push('\n');
+    Bytes::from(out)
+}
+
+/// Whether the client accepts `text/event-stream` but not `application/json`.
+fn wants_event_stream(req: &HttpRequest) -> bool {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    accept.contains("text/event-stream") && !accept.contains("application/json")
+}
+
+/// Streamable HTTP sessions and when each was last used.
+struct Sessions {
+    ttl: Duration,
+    max: usize,
+    last_used: HashMap<String, Instant>,
+}
+
+impl Sessions {
+    fn new(ttl: Duration, max: usize) -> Self {
+        Self {
+            ttl,
+            max,
+            last_used: HashMap::new(),
+        }
+    }
+
+    /// Whether no session can be opened at `now`.
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.expire(now);
+        self.last_used.len() >= self.max
+    }
+
+    /// Opens session `id`, unless there are already `max` of them.
+    fn open(&mut self, id: String, now: Instant) -> bool {
+        if self.is_full(now) {
+            return false;
+        }
+        self.last_used.insert(id, now);
+        true
+    }
+
+    /// Marks session `id` as used at `now`, returning whether it is open.
+    fn touch(&mut self, id: &str, now: Instant) -> bool {
+        self.expire(now);
+        match self.last_used.get_mut(id) {
+            Some(last_used) => {
+                *last_used = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ends session `id`, returning whether it was open.
+    fn close(&mut self, id: &str) -> bool {
+        self.last_used.remove(id).is_some()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.last_used
+            .retain(|_, last_used| now.saturating_duration_since(*last_used) < ttl);
+    }
+}
+
+/// An unguessable session id: 128 bits from the OS random number generator.
+fn new_session_id() -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(hex::encode(bytes))
+}
+
+fn session_id_failed(e: getrandom::Error) -> HttpResponse {
+    log::error!("Failed to generate an MCP session id: {}", e);
+    HttpResponse::InternalServerError().body("Failed to generate an MCP session id")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_ids_are_random_hex() {
+        let a = new_session_id().unwrap();
+        assert_eq!(a.len(), 32);
+        assert!(a.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(a, new_session_id().unwrap());
+    }
+
+    #[test]
+    fn sessions_expire_when_unused() {
+        let start = Instant::now();
+        let mut sessions = Sessions::new(Duration::from_secs(60), 10);
+        assert!(sessions.open("a".to_string(), start));
+        assert!(sessions.touch("a", start + Duration::from_secs(59)));
+        assert!(sessions.touch("a", start + Duration::from_secs(118)));
+        assert!(!sessions.touch("a", start + Duration::from_secs(178)));
+    }
+
+    #[test]
+    fn sessions_are_capped() {
+        let start = Instant::now();
+        let mut sessions = Sessions::new(Duration::from_secs(60), 2);
+        assert!(sessions.open("a".to_string(), start));
+        assert!(sessions.open("b".to_string(), start));
+        assert!(!sessions.open("c".to_string(), start));
+        assert!(sessions.close("a"));
+        assert!(!sessions.close("a"));
+        assert!(sessions.open("c".to_string(), start));
+        // Expired sessions free their slots.
+        assert!(sessions.open("d".to_string(), start + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,496 @@
+//! Prometheus metrics, served at `GET /metrics` in the text exposition format.
+//!
+//! - `cdd_http_requests_total` and `cdd_http_request_duration_seconds`, per method, route
+//!   pattern and status, recorded by the [`track_requests`] middleware;
+//! - `cdd_daemon_*`: state, readiness, restarts, uptime and retries against `max_retries` of
+//!   every supervised server, read from the [`Supervisor`] at scrape time;
+//! - `cdd_toolchain_runs_total` and `cdd_toolchain_run_duration_seconds`, per language,
+//!   command, `from_openapi` target and outcome, recorded by the
+//!   [`Executor`](crate::Executor);
+//! - `cdd_wasm_cache_requests_total`, hits and misses of the in-memory and on-disk WASM module
+//!   caches.
+//!
+//! Label values that come from callers are kept to known sets, anything else being counted as
+//! [`OTHER`], so that requests cannot create series at will. Like `/livez`, the endpoint is
+//! unauthenticated so that it can be scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+
+use crate::supervisor::{DaemonState, DaemonStatus, Supervisor};
+use crate::toolchain::ToolchainRegistry;
+
+/// Upper bounds of the duration histogram buckets, in seconds.
+pub const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Route label of requests that matched no route, so that unknown paths do not each get a
+/// series.
+pub(crate) const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Label value of an HTTP method, language, command or target outside the known ones.
+pub const OTHER: &str = "other";
+
+/// HTTP methods labelled as themselves.
+const HTTP_METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS", "CONNECT", "TRACE",
+];
+
+/// Toolchain commands labelled as themselves.
+const COMMANDS: &[&str] = &["from_openapi", "to_openapi", "to_docs_json"];
+
+/// Outcome of a toolchain run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RunOutcome {
+    /// Exited with status `0`.
+    Success,
+    /// Exited with another status or was killed.
+    Failure,
+    /// Could not be started, or hit a WASM limit.
+    Error,
+}
+
+impl RunOutcome {
+    fn name(self) -> &'static str {
+        match self {
+            RunOutcome::Success => "success",
+            RunOutcome::Failure => "failure",
+            RunOutcome::Error => "error",
+        }
+    }
+}
+
+/// Cache consulted for a WASM module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CacheLayer {
+    /// Modules compiled by this process.
+    Memory,
+    /// Precompiled artifacts on disk.
+    Disk,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; DURATION_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RunKey {
+    language: String,
+    command: String,
+    target: String,
+}
+
+/// Process-wide metric values.
+#[derive(Default)]
+pub struct Metrics {
+    http_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    http_durations: Mutex<BTreeMap<(String, String), Histogram>>,
+    runs: Mutex<BTreeMap<(RunKey, RunOutcome), u64>>,
+    run_durations: Mutex<BTreeMap<RunKey, Histogram>>,
+    wasm_cache: Mutex<BTreeMap<(CacheLayer, bool), u64>>,
+}
+
+impl Metrics {
+    /// The metrics of this process.
+    pub fn global() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(Metrics::default)
+    }
+
+    /// Records an HTTP request to the route `pattern`.
+    pub fn observe_request(&self, method: &str, pattern: &str, status: u16, elapsed: Duration) {
+        let method = known(method, HTTP_METHODS);
+        *lock(&self.http_requests)
+            .entry((method.to_string(), pattern.to_string(), status))
+            .or_default() += 1;
+        lock(&self.http_durations)
+            .entry((method.to_string(), pattern.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a run of the toolchain `language` with `args`, e.g.
+    /// `["from_openapi", "sdk", ...]`.
+    pub fn observe_run(
+        &self,
+        language: &str,
+        args: &[String],
+        outcome: RunOutcome,
+        elapsed: Duration,
+    ) {
+        let toolchain = ToolchainRegistry::builtin().get(language);
+        let command = args.first().map(String::as_str).unwrap_or_default();
+        let target = match (command, args.get(1)) {
+            ("from_openapi", Some(target)) => match toolchain {
+                Some(t) if t.targets.contains(&target.as_str()) => target.as_str(),
+                _ => OTHER,
+            },
+            _ => "",
+        };
+        let key = RunKey {
+            language: toolchain.map_or(OTHER, |t| t.name).to_string(),
+            command: known(command, COMMANDS).to_string(),
+            target: target.to_string(),
+        };
+        *lock(&self.runs).entry((key.clone(), outcome)).or_default() += 1;
+        lock(&self.run_durations)
+            .entry(key)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a lookup of a WASM module in a cache.
+    pub fn observe_wasm_cache(&self, layer: CacheLayer, hit: bool) {
+        *lock(&self.wasm_cache).entry((layer, hit)).or_default() += 1;
+    }
+
+    /// The metrics, and those of `supervisor`'s servers, in the Prometheus text format.
+    pub fn render(&self, supervisor: &Supervisor) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "cdd_http_requests_total",
+            "counter",
+            "HTTP requests handled",
+        );
+        for ((method, route, status), count) in lock(&self.http_requests).iter() {
+            let status = status.to_string();
+            let labels = labels(&[
+                ("method", method.as_str()),
+                ("route", route.as_str()),
+                ("status", status.as_str()),
+            ]);
+            let _ = writeln!(out, "cdd_http_requests_total{} {}", labels, count);
+        }
+        header(
+            &mut out,
+            "cdd_http_request_duration_seconds",
+            "histogram",
+            "Time to produce an HTTP response",
+        );
+        for ((method, route), histogram) in lock(&self.http_durations).iter() {
+            write_histogram(
+                &mut out,
+                "cdd_http_request_duration_seconds",
+                &[("method", method.as_str()), ("route", route.as_str())],
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "cdd_toolchain_runs_total",
+            "counter",
+            "Toolchain runs (generations and conversions)",
+        );
+        for ((key, outcome), count) in lock(&self.runs).iter() {
+            let labels = labels(&[
+                ("language", key.language.as_str()),
+                ("command", key.command.as_str()),
+                ("target", key.target.as_str()),
+                ("outcome", outcome.name()),
+            ]);
+            let _ = writeln!(out, "cdd_toolchain_runs_total{} {}", labels, count);
+        }
+        header(
+            &mut out,
+            "cdd_toolchain_run_duration_seconds",
+            "histogram",
+            "Duration of toolchain runs",
+        );
+        for (key, histogram) in lock(&self.run_durations).iter() {
+            write_histogram(
+                &mut out,
+                "cdd_toolchain_run_duration_seconds",
+                &[
+                    ("language", key.language.as_str()),
+                    ("command", key.command.as_str()),
+                    ("target", key.target.as_str()),
+                ],
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "cdd_wasm_cache_requests_total",
+            "counter",
+            "Lookups of compiled WASM modules",
+        );
+        for ((layer, hit), count) in lock(&self.wasm_cache).iter() {
+            let layer = match layer {
+                CacheLayer::Memory => "memory",
+                CacheLayer::Disk => "disk",
+            };
+            let result = if *hit { "hit" } else { "miss" };
+            let labels = labels(&[("cache", layer), ("result", result)]);
+            let _ = writeln!(out, "cdd_wasm_cache_requests_total{} {}", labels, count);
+        }
+
+        render_daemons(&mut out, supervisor);
+        out
+    }
+}
+
+fn render_daemons(out: &mut String, supervisor: &Supervisor) {
+    let daemons = supervisor.list();
+    let gauges: [(&str, &str, &str, fn(&DaemonStatus) -> u64); 6] = [
+        (
+            "cdd_daemon_ready",
+            "gauge",
+            "Whether the server is ready (1) or not (0)",
+            |d| u64::from(d.ready),
+        ),
+        (
+            "cdd_daemon_restarts_total",
+            "counter",
+            "Restarts since the supervisor started",
+            |d| u64::from(d.restarts),
+        ),
+        (
+            "cdd_daemon_uptime_seconds",
+            "gauge",
+            "Seconds the current process has been running",
+            |d| d.uptime_secs.unwrap_or_default(),
+        ),
+        (
+            "cdd_daemon_retries",
+            "gauge",
+            "Consecutive failed starts counted against max_retries",
+            |d| u64::from(d.retries),
+        ),
+        (
+            "cdd_daemon_max_retries",
+            "gauge",
+            "Configured max_retries",
+            |d| u64::from(d.max_retries),
+        ),
+        (
+            "cdd_daemon_consecutive_health_failures",
+            "gauge",
+            "Failed health-check probes in a row",
+            |d| {
+                d.health
+                    .as_ref()
+                    .map_or(0, |h| u64::from(h.consecutive_failures))
+            },
+        ),
+    ];
+    for (name, kind, help, value) in gauges {
+        header(out, name, kind, help);
+        for daemon in &daemons {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                name,
+                labels(&[("server", daemon.name.as_str())]),
+                value(daemon)
+            );
+        }
+    }
+    header(
+        out,
+        "cdd_daemon_state",
+        "gauge",
+        "Lifecycle state of the server (1 for the current one)",
+    );
+    for daemon in &daemons {
+        for state in DaemonState::ALL {
+            let _ = writeln!(
+                out,
+                "cdd_daemon_state{} {}",
+                labels(&[("server", daemon.name.as_str()), ("state", state.name())]),
+                u8::from(daemon.state == state)
+            );
+        }
+    }
+}
+
+/// Middleware recording every request in [`Metrics::global`]; install with
+/// `.wrap(actix_web::middleware::from_fn(metrics::track_requests))`.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let pattern = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let result = next.call(req).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.error_response().status(),
+    };
+    Metrics::global().observe_request(&method, &pattern, status.as_u16(), started.elapsed());
+    result
+}
+
+/// Mounts `GET /metrics`. Requires `web::Data<Supervisor>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/metrics", web::get().to(get_metrics));
+}
+
+/// `GET /metrics`
+async fn get_metrics(supervisor: web::Data<Supervisor>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(Metrics::global().render(&supervisor))
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histogram(out: &mut String, name: &str, base: &[(&str, &str)], histogram: &Histogram) {
+    for (count, bound) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+        let le = bound.to_string();
+        let mut bucket_labels = base.to_vec();
+        bucket_labels.push(("le", le.as_str()));
+        let _ = writeln!(out, "{}_bucket{} {}", name, labels(&bucket_labels), count);
+    }
+    let mut inf_labels = base.to_vec();
+    inf_labels.push(("le", "+Inf"));
+    let _ = writeln!(
+        out,
+        "{}_bucket{} {}",
+        name,
+        labels(&inf_labels),
+        histogram.count
+    );
+    let _ = writeln!(out, "{}_sum{} {}", name, labels(base), histogram.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, labels(base), histogram.count);
+}
+
+/// `{name="value",...}`, with values escaped.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// `value` if it is one of `known`, [`OTHER`] otherwise.
+fn known<'a>(value: &'a str, known: &[&str]) -> &'a str {
+    if known.contains(&value) {
+        value
+    } else {
+        OTHER
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(
+            labels(&[("a", "x"), ("b", "say \"hi\"\\\n")]),
+            r#"{a="x",b="say \"hi\"\\\n"}"#
+        );
+    }
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.0078125);
+        histogram.observe(1.0);
+        histogram.observe(1000.0);
+        let mut out = String::new();
+        write_histogram(&mut out, "t", &[("k", "v")], &histogram);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], r#"t_bucket{k="v",le="0.005"} 0"#);
+        assert_eq!(lines[1], r#"t_bucket{k="v",le="0.01"} 1"#);
+        assert!(lines.contains(&r#"t_bucket{k="v",le="1"} 2"#));
+        assert!(lines.contains(&r#"t_bucket{k="v",le="300"} 2"#));
+        assert!(lines.contains(&r#"t_bucket{k="v",le="+Inf"} 3"#));
+        assert!(lines.contains(&r#"t_sum{k="v"} 1001.0078125"#));
+        assert_eq!(lines.last(), Some(&r#"t_count{k="v"} 3"#));
+    }
+
+    #[test]
+    fn unknown_methods_are_other() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/metrics", 200, Duration::ZERO);
+        metrics.observe_request("BREW", "/metrics", 405, Duration::ZERO);
+        let requests = lock(&metrics.http_requests);
+        let methods: Vec<&str> = requests.keys().map(|(m, _, _)| m.as_str()).collect();
+        assert_eq!(methods, ["GET", "other"]);
+    }
+
+    #[test]
+    fn only_known_languages_commands_and_targets_are_labelled() {
+        let metrics = Metrics::default();
+        let runs = [
+            (
+                "cdd-go",
+                args(&["from_openapi", "to_sdk", "-i", "spec.json"]),
+            ),
+            ("cdd-go", args(&["from_openapi", "to_anything"])),
+            ("cdd-nope", args(&["from_openapi", "to_sdk"])),
+            ("cdd-go", args(&["--version"])),
+        ];
+        for (language, args) in &runs {
+            metrics.observe_run(language, args, RunOutcome::Success, Duration::ZERO);
+        }
+        let supervisor = Supervisor::new(HashMap::new());
+        let out = metrics.render(&supervisor);
+        for series in [
+            r#"{language="cdd-go",command="from_openapi",target="to_sdk",outcome="success"} 1"#,
+            r#"{language="cdd-go",command="from_openapi",target="other",outcome="success"} 1"#,
+            r#"{language="other",command="from_openapi",target="other",outcome="success"} 1"#,
+            r#"{language="cdd-go",command="other",target="",outcome="success"} 1"#,
+        ] {
+            assert!(
+                out.contains(&format!("cdd_toolchain_runs_total{}", series)),
+                "{}",
+                series
+            );
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! OpenRPC description of the JSON-RPC interface of `cdd-rpc`.
+//!
+//! The document is built from the method registry in [`crate::rpc`], so it cannot drift from
+//! what `/rpc` serves. It is returned by `rpc.discover` and written by `dump_openapi --document
+//! openrpc`.
+
+use serde_json::{json, Map, Value};
+
+use crate::rpc::{ErrorInfo, MethodInfo, ERRORS, METHODS};
+
+/// Version of the OpenRPC specification the document follows.
+pub const OPENRPC_VERSION: &str = "1.3.2";
+
+/// The OpenRPC document describing every method in [`METHODS`].
+pub fn document() -> Value {
+    let errors: Map<String, Value> = ERRORS
+        .iter()
+        .map(|error| (error.name.to_string(), error_object(error)))
+        .collect();
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "cdd-rpc",
+            "description": "JSON-RPC 2.0 interface to the cdd-* toolchains and their supervisor",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "name": "cdd-rpc", "url": "/rpc" }],
+        "methods": METHODS.iter().map(method).collect::<Vec<_>>(),
+        "components": {
+            "schemas": schemas(),
+            "errors": errors,
+        },
+    })
+}
+
+fn method(info: &MethodInfo) -> Value {
+    let params: Vec<Value> = info
+        .params
+        .iter()
+        .map(|param| {
+            json!({
+                "name": param.name,
+                "description": param.description,
+                "required": param.required,
+                "schema": param.schema.to_json(),
+            })
+        })
+        .collect();
+    let errors: Vec<Value> = info
+        .errors
+        .iter()
+        .map(|error| json!({ "$ref": format!("#/components/errors/{}", error.name) }))
+        .collect();
+    let mut method = json!({
+        "name": info.name,
+        "summary": info.summary,
+        "paramStructure": "either",
+        "params": params,
+        "result": { "name": info.result, "schema": info.result_schema.to_json() },
+    });
+    if !errors.is_empty() {
+        method["errors"] = Value::Array(errors);
+    }
+    method
+}
+
+fn error_object(error: &ErrorInfo) -> Value {
+    json!({ "code": error.code, "message": error.message })
+}
+
+fn schemas() -> Value {
+    let optional_integer = json!({ "type": ["integer", "null"], "minimum": 0 });
+    json!({
+        "InputFile": {
+            "type": "object",
+            "required": ["path", "contents"],
+            "properties": {
+                "path": { "type": "string", "description": "Relative, `/`-separated path" },
+                "contents": { "type": "string" },
+            },
+        },
+        "GeneratedFile": {
+            "type": "object",
+            "required": ["path", "size", "encoding", "contents"],
+            "properties": {
+                "path": { "type": "string" },
+                "size": { "type": "integer", "minimum": 0 },
+                "encoding": { "type": "string", "enum": ["utf8", "hex"] },
+                "contents": { "type": "string" },
+            },
+        },
+        "Generation": {
+            "type": "object",
+            "required": ["result", "files"],
+            "properties": {
+                "result": { "description": "Result returned by the toolchain server" },
+                "files": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/GeneratedFile" },
+                },
+            },
+        },
+        "WasmLimits": {
+            "type": "object",
+            "description": "Lowers the configured limits for one job; unset fields keep them",
+            "properties": {
+                "fuel": optional_integer,
+                "max_memory_bytes": optional_integer,
+                "timeout_ms": optional_integer,
+            },
+        },
+        "DaemonStatus": {
+            "type": "object",
+            "required": ["name", "state", "ready", "retries", "max_retries", "restarts", "circuit"],
+            "properties": {
+                "name": { "type": "string" },
+                "state": {
+                    "type": "string",
+                    "enum": [
+                        "waiting", "starting", "running", "backoff", "stopped", "failed", "external",
+                    ],
+                },
+                "ready": { "type": "boolean" },
+                "pid": optional_integer,
+                "started_at": optional_integer,
+                "uptime_secs": optional_integer,
+                "retries": { "type": "integer", "minimum": 0 },
+                "max_retries": { "type": "integer", "minimum": 0 },
+                "restarts": { "type": "integer", "minimum": 0 },
+                "last_exit_code": { "type": ["integer", "null"] },
+                "command": { "type": ["string", "null"] },
+                "external_address": { "type": ["string", "null"] },
+                "health": {
+                    "type": ["object", "null"],
+                    "properties": {
+                        "healthy": { "type": ["boolean", "null"] },
+                        "consecutive_failures": { "type": "integer", "minimum": 0 },
+                        "last_error": { "type": ["string", "null"] },
+                        "checked_at": optional_integer,
+                    },
+                },
+                "circuit": { "type": "string", "enum": ["closed", "open", "half_open"] },
+                "next_attempt_at": optional_integer,
+            },
+        },
+    })
+}
@@ -0,0 +1,336 @@
+//! Environment, working directory, user and resource limits of spawned servers.
+//!
+//! [`apply`] prepares the command of every process the supervisor spawns from its
+//! [`ServerOptions`]. The child inherits the gateway's environment, overridden by the variables
+//! of `env_file` (re-read at each spawn, so edits apply on the next restart) and then by
+//! `env`. On Unix it can run as another `user` (looked up with `getpwnam_r`), and
+//! `memory_limit` caps its address space (`RLIMIT_AS`). On Linux it can be placed in a cgroup
+//! v2 directory, `cgroup`, whose `cpu.max` enforces `cpu_limit`, a share of CPU time: the
+//! process is throttled, not killed, when it would use more.
+//!
+//! Each child leads its own process group, so that a Ctrl-C in the terminal reaches only the
+//! gateway, and [`terminate`] stops the child together with anything it spawned: `SIGTERM` to
+//! the group, then `SIGKILL` once `stop_grace_ms` has passed.
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::process::{Child, Command};
+
+use crate::config::ServerOptions;
+
+/// Time a process is given to exit after `SIGTERM` when no `stop_grace_ms` is configured.
+pub const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// Period of `cpu.max`, in microseconds.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// A share of CPU time in CPUs: `0.5` is half of one CPU, `2` two whole CPUs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "f64")]
+pub struct CpuLimit {
+    millicpus: u32,
+}
+
+impl TryFrom<f64> for CpuLimit {
+    type Error = String;
+
+    fn try_from(cpus: f64) -> Result<Self, String> {
+        // The kernel refuses quotas under 1ms per period.
+        if !(0.01..=1_000_000.0).contains(&cpus) {
+            return Err(format!(
+                "`cpu_limit` must be between 0.01 and 1000000 CPUs, got {}",
+                cpus
+            ));
+        }
+        Ok(Self {
+            millicpus: (cpus * 1000.0).round() as u32,
+        })
+    }
+}
+
+impl CpuLimit {
+    /// The `cpu.max` line granting this share.
+    fn cpu_max(self) -> String {
+        let quota = u64::from(self.millicpus) * CPU_PERIOD_US / 1000;
+        format!("{} {}", quota, CPU_PERIOD_US)
+    }
+}
+
+/// Checks that the settings of `options` can be applied on this platform.
+pub fn validate(options: &ServerOptions) -> Result<(), String> {
+    if let Some(key) = options
+        .env
+        .keys()
+        .find(|key| key.is_empty() || key.contains(['=', '\0']))
+    {
+        return Err(format!("`env`: invalid variable name {:?}", key));
+    }
+    if let Some(path) = &options.env_file {
+        read_env_file(path).map_err(|e| format!("`env_file`: {}", e))?;
+    }
+    if let Some(cwd) = &options.cwd {
+        if !cwd.is_dir() {
+            return Err(format!("`cwd`: {} is not a directory", cwd.display()));
+        }
+    }
+    if options.memory_limit == Some(0) {
+        return Err("`memory_limit` must be positive".to_string());
+    }
+    if cfg!(not(unix)) && (options.user.is_some() || options.memory_limit.is_some()) {
+        return Err("`user` and `memory_limit` are only supported on Unix".to_string());
+    }
+    if cfg!(not(target_os = "linux")) && (options.cgroup.is_some() || options.cpu_limit.is_some()) {
+        return Err("`cgroup` and `cpu_limit` are only supported on Linux".to_string());
+    }
+    if options.cpu_limit.is_some() && options.cgroup.is_none() {
+        return Err("`cpu_limit` requires a `cgroup`".to_string());
+    }
+    if let Some(cgroup) = &options.cgroup {
+        let parent = cgroup.parent().unwrap_or(cgroup);
+        if !parent.join("cgroup.controllers").is_file() {
+            return Err(format!(
+                "`cgroup`: {} is not in a cgroup v2 hierarchy",
+                cgroup.display()
+            ));
+        }
+    }
+    #[cfg(unix)]
+    if let Some(user) = &options.user {
+        lookup_user(user).map_err(|e| format!("`user`: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Applies the environment, working directory, user and limits of `options` to `command`, and
+/// makes the child the leader of a new process group. The `cgroup` is created if needed and
+/// given the `cpu_limit`; [`attach`] moves the spawned child into it.
+pub fn apply(command: &mut Command, options: &ServerOptions) -> io::Result<()> {
+    if let Some(path) = &options.env_file {
+        let vars =
+            read_env_file(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        command.envs(vars);
+    }
+    command.envs(&options.env);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    if let Some(cgroup) = &options.cgroup {
+        if !cgroup.is_dir() {
+            std::fs::create_dir(cgroup)?;
+        }
+        let cpu_max = options
+            .cpu_limit
+            .map(CpuLimit::cpu_max)
+            .unwrap_or_else(|| format!("max {}", CPU_PERIOD_US));
+        std::fs::write(cgroup.join("cpu.max"), cpu_max)?;
+    }
+    #[cfg(unix)]
+    {
+        command.process_group(0);
+        if let Some(user) = &options.user {
+            let (uid, gid) =
+                lookup_user(user).map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+            command.uid(uid).gid(gid);
+        }
+        if let Some(limit) = options.memory_limit {
+            // `rlim_t` is narrower than `u64` on some targets.
+            #[allow(clippy::unnecessary_cast)]
+            let rlimit = libc::rlimit {
+                rlim_cur: limit as libc::rlim_t,
+                rlim_max: limit as libc::rlim_t,
+            };
+            // SAFETY: the closure runs in the forked child before `exec` and only calls
+            // `setrlimit`, which is async-signal-safe, on a value copied in beforehand.
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setrlimit(libc::RLIMIT_AS, &rlimit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves the spawned process `pid` into the `cgroup` of `options`, if any.
+///
+/// This happens in the gateway rather than in the child before `exec`, since a child that
+/// switched `user` can no longer leave the gateway's cgroup.
+pub fn attach(pid: u32, options: &ServerOptions) -> io::Result<()> {
+    match &options.cgroup {
+        Some(cgroup) => std::fs::write(cgroup.join("cgroup.procs"), pid.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Stops `child`, returning whether it exited within `grace`.
+///
+/// On Unix, `SIGTERM` goes to the child's process group (or to the child alone if it does not
+/// lead one) and `SIGKILL` follows once `grace` has passed. Elsewhere the child is killed
+/// right away.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub async fn terminate(child: &mut Child, grace: Duration) -> bool {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let pid = pid as libc::pid_t;
+        // SAFETY: plain syscalls on a child that has not been reaped yet, so `pid` cannot have
+        // been reused.
+        let target = unsafe {
+            let target = if libc::getpgid(pid) == pid { -pid } else { pid };
+            libc::kill(target, libc::SIGTERM);
+            target
+        };
+        if tokio::time::timeout(grace, child.wait()).await.is_ok() {
+            return true;
+        }
+        // SAFETY: as above; the child is still running.
+        unsafe { libc::kill(target, libc::SIGKILL) };
+        let _ = child.wait().await;
+        return false;
+    }
+    let _ = child.kill().await;
+    true
+}
+
+/// The variables of a `.env`-style file.
+fn read_env_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    dotenvy::from_path_iter(path)
+        .and_then(|vars| vars.collect())
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+}
+
+/// Resolves `user` (a name or uid from the user database, or `uid:gid`) to a uid and gid.
+#[cfg(unix)]
+fn lookup_user(user: &str) -> Result<(u32, u32), String> {
+    if let Some((uid, gid)) = user.split_once(':') {
+        return match (uid.parse(), gid.parse()) {
+            (Ok(uid), Ok(gid)) => Ok((uid, gid)),
+            _ => Err(format!("expected a name, uid or uid:gid, got {:?}", user)),
+        };
+    }
+    let name = std::ffi::CString::new(user).map_err(|_| format!("invalid user {:?}", user))?;
+    // SAFETY: `name` is a valid C string, and the other arguments come from `passwd_entry`.
+    let mut entry = passwd_entry(|pwd, buf, len, result| unsafe {
+        libc::getpwnam_r(name.as_ptr(), pwd, buf, len, result)
+    });
+    let uid = user.parse::<libc::uid_t>().ok();
+    if let (Ok(None), Some(uid)) = (&entry, uid) {
+        // SAFETY: as above.
+        entry = passwd_entry(|pwd, buf, len, result| unsafe {
+            libc::getpwuid_r(uid, pwd, buf, len, result)
+        });
+    }
+    match (entry, uid) {
+        (Ok(Some(ids)), _) => Ok(ids),
+        // A uid without an entry keeps its own number as the group.
+        (Ok(None), Some(uid)) => Ok((uid, uid)),
+        (Ok(None), None) => Err(format!("unknown user {:?}", user)),
+        (Err(e), _) => Err(format!("cannot look up {:?}: {}", user, e)),
+    }
+}
+
+/// Calls a reentrant `getpw*_r` function, growing its buffer until the entry fits, and returns
+/// the uid and gid of the entry it found.
+#[cfg(unix)]
+fn passwd_entry(
+    get: impl Fn(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+) -> io::Result<Option<(u32, u32)>> {
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        // SAFETY: an all-zero `passwd` is a valid value for an out-parameter.
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        match get(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+            0 if result.is_null() => return Ok(None),
+            0 => return Ok(Some((pwd.pw_uid, pwd.pw_gid))),
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            // Some libcs report a missing entry as one of these instead of a null result.
+            libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => return Ok(None),
+            code => return Err(io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_limit_becomes_a_quota_per_period() {
+        let limit = CpuLimit::try_from(0.5).unwrap();
+        assert_eq!(limit.cpu_max(), "50000 100000");
+        let limit: CpuLimit = serde_json::from_value(serde_json::json!(2)).unwrap();
+        assert_eq!(limit.cpu_max(), "200000 100000");
+        assert!(CpuLimit::try_from(0.001).is_err());
+        assert!(CpuLimit::try_from(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn cpu_limit_requires_a_cgroup() {
+        let options = ServerOptions {
+            cpu_limit: Some(CpuLimit::try_from(1.0).unwrap()),
+            ..ServerOptions::default()
+        };
+        assert!(validate(&options).is_err());
+    }
+
+    #[test]
+    fn memory_limit_must_be_positive() {
+        let options = ServerOptions {
+            memory_limit: Some(0),
+            ..ServerOptions::default()
+        };
+        assert!(validate(&options).is_err());
+    }
+
+    /// The name, uid and gid of the user running the tests, if it has an entry.
+    #[cfg(unix)]
+    fn current_user() -> Option<(String, u32, u32)> {
+        let mut buf: Vec<libc::c_char> = vec![0; 1 << 16];
+        // SAFETY: as in `passwd_entry`; `pw_name` points into `buf`, read before it is dropped.
+        unsafe {
+            let mut pwd: libc::passwd = std::mem::zeroed();
+            let mut result = std::ptr::null_mut();
+            let code = libc::getpwuid_r(
+                libc::getuid(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            );
+            if code != 0 || result.is_null() {
+                return None;
+            }
+            let name = std::ffi::CStr::from_ptr(pwd.pw_name).to_str().ok()?;
+            Some((name.to_string(), pwd.pw_uid, pwd.pw_gid))
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn users_are_looked_up_in_the_user_database() {
+        // SAFETY: `getuid` cannot fail.
+        let uid = unsafe { libc::getuid() };
+        match current_user() {
+            Some((name, uid, gid)) => {
+                assert_eq!(lookup_user(&name), Ok((uid, gid)));
+                assert_eq!(lookup_user(&uid.to_string()), Ok((uid, gid)));
+            }
+            None => assert_eq!(lookup_user(&uid.to_string()), Ok((uid, uid))),
+        }
+        assert_eq!(lookup_user("1000:1001"), Ok((1000, 1001)));
+        assert_eq!(lookup_user("4000000000"), Ok((4000000000, 4000000000)));
+        assert!(lookup_user("no-such-user-here").is_err());
+        assert!(lookup_user("1:x").is_err());
+    }
+}
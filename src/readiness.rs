@@ -0,0 +1,151 @@
+//! Startup ordering and readiness of supervised servers.
+//!
+//! A spawned server is *ready* once it is running and, if it has a [`ReadyWhen`], once that
+//! condition has held: a line of its output matches a regex, its port accepts connections, or
+//! a JSON-RPC ping succeeds. A server is only spawned once every server in its `depends_on` is
+//! ready (see [`Supervisor`]); `external_address` servers always count as ready.
+//!
+//! - `GET /livez`: liveness of the gateway itself, `200 OK` as long as it serves requests.
+//! - `GET /readyz`: `200 OK` once every server not marked `optional` is ready, `503 Service
+//!   Unavailable` with the servers still waited for otherwise.
+//!
+//! Both are unauthenticated, for load balancers and orchestrators.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::health::{HealthCheck, Probe};
+use crate::supervisor::{DaemonEvent, DaemonEventKind, Supervisor};
+
+/// Time between two attempts of a `port` or `rpc_ping` condition.
+pub const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Milliseconds one attempt of a `port` or `rpc_ping` condition may take.
+const READY_PROBE_TIMEOUT_MS: u64 = 2_000;
+
+/// When a spawned server starts counting as ready, configured as `servers.<name>.ready_when`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReadyWhen {
+    /// A line of stdout or stderr matches `pattern`.
+    LogLine {
+        /// Regular expression, e.g. `"listening on"`.
+        pattern: String,
+    },
+    /// `host:port` accepts TCP connections.
+    Port {
+        /// Host to connect to.
+        #[serde(default = "default_host")]
+        host: String,
+        /// Listen port of the server.
+        port: u16,
+    },
+    /// A JSON-RPC request to `url` gets a response, even an error.
+    RpcPing {
+        /// Endpoint, e.g. `http://127.0.0.1:9091/`.
+        url: String,
+        /// Method to call.
+        #[serde(default = "default_method")]
+        method: String,
+    },
+}
+
+impl ReadyWhen {
+    /// Checks that the condition can ever hold.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ReadyWhen::LogLine { pattern } => regex::Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("invalid `pattern`: {}", e)),
+            ReadyWhen::Port { port, .. } if *port == 0 => {
+                Err("`port` must be positive".to_string())
+            }
+            ReadyWhen::Port { .. } => Ok(()),
+            ReadyWhen::RpcPing { url, .. }
+                if url.starts_with("http://") || url.starts_with("https://") =>
+            {
+                Ok(())
+            }
+            ReadyWhen::RpcPing { url, .. } => {
+                Err(format!("`url` must be an http(s) URL, got {:?}", url))
+            }
+        }
+    }
+
+    /// Resolves once the condition holds for the server `name`. `events` must have been
+    /// subscribed before the process was spawned, so that no output line is missed.
+    pub async fn wait(&self, name: &str, mut events: broadcast::Receiver<DaemonEvent>) {
+        let probe = match self {
+            ReadyWhen::LogLine { pattern } => {
+                let Ok(regex) = regex::Regex::new(pattern) else {
+                    return std::future::pending().await;
+                };
+                loop {
+                    match events.recv().await {
+                        Ok(DaemonEvent {
+                            name: source,
+                            kind: DaemonEventKind::Log { line, .. },
+                        }) if source == name && regex.is_match(&line) => return,
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(missed)) => {
+                            log::warn!("Missed {} events while waiting for {}", missed, name);
+                        }
+                        Err(RecvError::Closed) => return std::future::pending().await,
+                    }
+                }
+            }
+            ReadyWhen::Port { host, port } => Probe::Tcp {
+                host: host.clone(),
+                port: *port,
+            },
+            ReadyWhen::RpcPing { url, method } => Probe::JsonRpc {
+                url: Some(url.clone()),
+                method: method.clone(),
+            },
+        };
+        let check = HealthCheck {
+            probe,
+            interval_ms: READY_POLL_INTERVAL.as_millis() as u64,
+            timeout_ms: READY_PROBE_TIMEOUT_MS,
+            failure_threshold: 1,
+        };
+        while let Err(e) = check.probe(None).await {
+            log::debug!("{} is not ready yet: {}", name, e);
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Mounts `/livez` and `/readyz`. Requires `web::Data<Supervisor>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/livez", web::get().to(livez))
+        .route("/readyz", web::get().to(readyz));
+}
+
+/// `GET /livez`
+async fn livez() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// `GET /readyz`
+async fn readyz(supervisor: web::Data<Supervisor>) -> HttpResponse {
+    let waiting = supervisor.unready();
+    if waiting.is_empty() {
+        HttpResponse::Ok().json(json!({ "status": "ready" }))
+    } else {
+        HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "not_ready", "waiting": waiting }))
+    }
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_method() -> String {
+    "ping".to_string()
+}
@@ -0,0 +1,141 @@
+//! Hot reload of the configured `servers`.
+//!
+//! [`watch`] re-reads the configuration whenever the file passed via `--config` changes (its
+//! modification time is polled every [`POLL_INTERVAL`]) or the process receives `SIGHUP`, and
+//! hands the new `servers` to [`Supervisor::reload`], which only starts, stops or restarts the
+//! processes that were added, removed or changed. A configuration that fails to load or to
+//! validate is rejected and the running set is kept.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+
+use crate::config::{validate_servers, AppConfig, CtlConfig};
+use crate::supervisor::Supervisor;
+use crate::ProcessConfig;
+
+/// How often the configuration file is checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Extensions tried when `--config` names a file without one, as the `config` crate does.
+const EXTENSIONS: &[&str] = &["json", "yaml", "yml", "toml", "json5", "ini", "ron"];
+
+/// Watches `config` and `SIGHUP` for the lifetime of the process, reloading `supervisor`.
+///
+/// `defaults` stands in for an empty `servers` map, as it does at startup.
+pub fn watch(
+    config: Option<String>,
+    supervisor: Arc<Supervisor>,
+    defaults: HashMap<String, ProcessConfig>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let file = config.as_deref().and_then(resolve);
+        let mut last_modified = file.as_deref().and_then(modified);
+        let mut hangup = Hangup::new();
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            let trigger = tokio::select! {
+                _ = hangup.recv() => "SIGHUP",
+                _ = poll.tick() => {
+                    let current = file.as_deref().and_then(modified);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    "a configuration change"
+                }
+            };
+            log::info!("Reloading servers after {}", trigger);
+            reload(config.as_deref(), &supervisor, &defaults).await;
+        }
+    })
+}
+
+/// Loads and validates `servers` and their options from `config` and applies them to
+/// `supervisor`.
+pub async fn reload(
+    config: Option<&str>,
+    supervisor: &Supervisor,
+    defaults: &HashMap<String, ProcessConfig>,
+) {
+    let loaded = AppConfig::load(config)
+        .map_err(|e| e.to_string())
+        .and_then(|app| Ok((app, CtlConfig::load(config).map_err(|e| e.to_string())?)));
+    let (mut servers, options) = match loaded {
+        Ok((app_config, ctl_config)) => (app_config.servers, ctl_config.servers),
+        Err(e) => {
+            log::error!("Rejecting new configuration, keeping the old one: {}", e);
+            return;
+        }
+    };
+    if servers.is_empty() {
+        servers.extend(defaults.clone());
+    }
+    if let Err(e) = validate_servers(&servers, &options) {
+        log::error!("Rejecting new configuration, keeping the old one: {}", e);
+        return;
+    }
+    let summary = supervisor.reload(servers, &options).await;
+    if summary.is_empty() {
+        log::info!("Servers unchanged");
+    } else {
+        log::info!(
+            "Servers reloaded: added {:?}, removed {:?}, changed {:?}",
+            summary.added,
+            summary.removed,
+            summary.changed
+        );
+    }
+}
+
+/// The file `config` refers to, trying the supported extensions if it has none.
+fn resolve(config: &str) -> Option<PathBuf> {
+    let path = Path::new(config);
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `SIGHUP`, where the platform has it.
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .map_err(|e| log::warn!("Cannot listen for SIGHUP: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        {
+            Self {}
+        }
+    }
+
+    /// Resolves on the next `SIGHUP`; never, if it cannot be received.
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
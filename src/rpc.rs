@@ -0,0 +1,735 @@
+//! JSON-RPC 2.0 methods served by `cdd-rpc` and `cdd-rpc-wasm`.
+//!
+//! `cdd.*` methods write their inputs to a fresh [`Sandbox`] and hand the job to the
+//! supervised server of the requested language: `cdd.from_openapi` becomes a `from_openapi`
+//! request over the server's stdio, with `params` naming the sandbox's `input` and `output`
+//! paths and carrying the current trace in `_meta`. The files the server writes to the
+//! sandbox are returned alongside its result. Toolchains that run as WASM are instead given
+//! the request in a `serve_json_rpc` module of their own that sees only the job's sandbox (see
+//! [`RpcContext::with_executor`]). `daemon.*` methods inspect and control the
+//! processes of the [`Supervisor`]. Every method is listed in [`METHODS`], from which
+//! [`crate::openrpc`] builds the document served at `rpc.discover`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::Caller;
+use crate::executor::{ExecError, ExecOutput, Executor, LimitKind, SERVE_REQUEST_ID};
+use crate::jsonrpc::{self, RpcError};
+use crate::mcp::{parse_reply, McpAggregator};
+use crate::metrics::{Metrics, RunOutcome};
+use crate::openrpc;
+use crate::sandbox::Sandbox;
+use crate::shutdown::Job;
+use crate::supervisor::{Supervisor, SupervisorError};
+use crate::telemetry;
+use crate::toolchain::{executable_name, Runtime};
+use crate::wasm::WasmLimits;
+
+/// The toolchain reported that the job failed.
+pub const GENERATION_FAILED: i64 = -32000;
+/// A WASM toolchain ran into its fuel, memory or timeout limit.
+pub const LIMIT_EXCEEDED: i64 = -32001;
+/// The daemon does not exist, is not running or cannot be controlled.
+pub const DAEMON_ERROR: i64 = -32002;
+/// The method requires the administrator role.
+pub const FORBIDDEN: i64 = -32003;
+
+/// Deadline for a job handed to a server, which may run a whole generator.
+const CALL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Shape of a parameter or result, rendered as JSON Schema by [`Schema::to_json`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// A string.
+    String,
+    /// An array of `{ path, contents }` input files.
+    InputFiles,
+    /// Result and generated files of a toolchain job.
+    Generation,
+    /// [`WasmLimits`] of a job.
+    WasmLimits,
+    /// Any JSON value.
+    Any,
+    /// A [`DaemonStatus`](crate::supervisor::DaemonStatus).
+    DaemonStatus,
+    /// An array of [`DaemonStatus`](crate::supervisor::DaemonStatus).
+    DaemonStatuses,
+}
+
+impl Schema {
+    /// JSON Schema of this shape; named types refer to `#/components/schemas/...`.
+    pub fn to_json(self) -> Value {
+        match self {
+            Schema::String => json!({ "type": "string" }),
+            Schema::InputFiles => json!({
+                "type": "array",
+                "items": { "$ref": "#/components/schemas/InputFile" },
+            }),
+            Schema::Generation => json!({ "$ref": "#/components/schemas/Generation" }),
+            Schema::WasmLimits => json!({ "$ref": "#/components/schemas/WasmLimits" }),
+            Schema::Any => json!({}),
+            Schema::DaemonStatus => json!({ "$ref": "#/components/schemas/DaemonStatus" }),
+            Schema::DaemonStatuses => json!({
+                "type": "array",
+                "items": { "$ref": "#/components/schemas/DaemonStatus" },
+            }),
+        }
+    }
+}
+
+/// A parameter of an RPC method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamInfo {
+    /// Parameter name.
+    pub name: &'static str,
+    /// Whether the parameter must be given.
+    pub required: bool,
+    /// What the parameter holds.
+    pub description: &'static str,
+    /// Shape of the parameter.
+    pub schema: Schema,
+}
+
+/// An application-defined error a method can return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorInfo {
+    /// Error code.
+    pub code: i64,
+    /// Name of the error in the OpenRPC document.
+    pub name: &'static str,
+    /// When the error is returned.
+    pub message: &'static str,
+}
+
+/// An RPC method served at `/rpc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodInfo {
+    /// Method name, e.g. `cdd.from_openapi`.
+    pub name: &'static str,
+    /// One-line description.
+    pub summary: &'static str,
+    /// Parameters, in positional order.
+    pub params: &'static [ParamInfo],
+    /// Name of the result.
+    pub result: &'static str,
+    /// Shape of the result.
+    pub result_schema: Schema,
+    /// Application-defined errors besides the standard JSON-RPC ones.
+    pub errors: &'static [ErrorInfo],
+}
+
+const fn param(
+    name: &'static str,
+    required: bool,
+    description: &'static str,
+    schema: Schema,
+) -> ParamInfo {
+    ParamInfo {
+        name,
+        required,
+        description,
+        schema,
+    }
+}
+
+const LANGUAGE: ParamInfo = param(
+    "language",
+    true,
+    "Toolchain, e.g. `go` or `cdd-go`",
+    Schema::String,
+);
+const SPEC: ParamInfo = param("spec", true, "The OpenAPI document", Schema::String);
+const SPEC_NAME: ParamInfo = param(
+    "spec_name",
+    false,
+    "File name the document is written as (default `openapi.json`)",
+    Schema::String,
+);
+const LIMITS: ParamInfo = param(
+    "limits",
+    false,
+    "WASM resource limits for this job, capped by the configured ones",
+    Schema::WasmLimits,
+);
+/// Every application-defined error code.
+pub const ERRORS: &[ErrorInfo] = &[
+    ErrorInfo {
+        code: GENERATION_FAILED,
+        name: "GenerationFailed",
+        message: "The toolchain reported that the job failed",
+    },
+    ErrorInfo {
+        code: LIMIT_EXCEEDED,
+        name: "LimitExceeded",
+        message: "The WASM toolchain exceeded its fuel, memory or timeout limit",
+    },
+    ErrorInfo {
+        code: DAEMON_ERROR,
+        name: "DaemonError",
+        message: "The daemon is unknown, not running or external",
+    },
+    ErrorInfo {
+        code: FORBIDDEN,
+        name: "Forbidden",
+        message: "The caller is not an administrator",
+    },
+];
+
+const TOOLCHAIN_ERRORS: &[ErrorInfo] = &[ERRORS[0], ERRORS[1], ERRORS[2]];
+
+/// Every method served by [`RpcContext::call`], besides `rpc.discover`.
+pub const METHODS: &[MethodInfo] = &[
+    MethodInfo {
+        name: "cdd.from_openapi",
+        summary: "Generate code from an OpenAPI document and return the generated files",
+        params: &[
+            LANGUAGE,
+            param(
+                "target",
+                true,
+                "Generation target, e.g. `to_sdk`",
+                Schema::String,
+            ),
+            SPEC,
+            SPEC_NAME,
+            LIMITS,
+        ],
+        result: "generation",
+        result_schema: Schema::Generation,
+        errors: TOOLCHAIN_ERRORS,
+    },
+    MethodInfo {
+        name: "cdd.to_openapi",
+        summary: "Derive an OpenAPI document from source files",
+        params: &[
+            LANGUAGE,
+            param(
+                "files",
+                true,
+                "Source files, relative to the workspace",
+                Schema::InputFiles,
+            ),
+            LIMITS,
+        ],
+        result: "generation",
+        result_schema: Schema::Generation,
+        errors: TOOLCHAIN_ERRORS,
+    },
+    MethodInfo {
+        name: "cdd.to_docs_json",
+        summary: "Render the JSON documentation of an OpenAPI document in memory",
+        params: &[LANGUAGE, SPEC, SPEC_NAME, LIMITS],
+        result: "docs",
+        result_schema: Schema::Any,
+        errors: TOOLCHAIN_ERRORS,
+    },
+    MethodInfo {
+        name: "daemon.list",
+        summary: "Status of every supervised process",
+        params: &[],
+        result: "daemons",
+        result_schema: Schema::DaemonStatuses,
+        errors: &[ERRORS[3]],
+    },
+    MethodInfo {
+        name: "daemon.restart",
+        summary: "Restart a supervised process and return its status",
+        params: &[param(
+            "name",
+            true,
+            "Server name, e.g. `cdd-go`",
+            Schema::String,
+        )],
+        result: "daemon",
+        result_schema: Schema::DaemonStatus,
+        errors: &[ERRORS[2], ERRORS[3]],
+    },
+];
+
+/// State shared by the RPC endpoints, registered as `web::Data<RpcContext>`.
+pub struct RpcContext {
+    supervisor: Arc<Supervisor>,
+    mcp: Option<Arc<McpAggregator>>,
+    executor: Option<Executor>,
+}
+
+impl RpcContext {
+    /// Hands `cdd.*` jobs to the `serve_json_rpc` servers of `supervisor`, which also serves
+    /// `daemon.*`.
+    pub fn new(supervisor: Arc<Supervisor>) -> Self {
+        Self {
+            supervisor,
+            mcp: None,
+            executor: None,
+        }
+    }
+
+    /// Hands `cdd.*` jobs to the servers as MCP `tools/call`s through `aggregator` instead, for
+    /// binaries whose servers speak MCP.
+    pub fn with_mcp(mut self, aggregator: Arc<McpAggregator>) -> Self {
+        self.mcp = Some(aggregator);
+        self
+    }
+
+    /// Runs the `cdd.*` jobs of toolchains that `executor` resolves to WASM in a
+    /// `serve_json_rpc` module of their own, confined to the job's sandbox (see
+    /// [`Executor::serve_in`]), rather than handing them to a server shared by every job.
+    pub fn with_executor(mut self, executor: Executor) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// The supervisor behind the `daemon.*` methods.
+    pub fn supervisor(&self) -> &Arc<Supervisor> {
+        &self.supervisor
+    }
+
+    /// Runs `method` with `params` (an object, a positional array or `null`) on behalf of
+    /// `caller`; the `daemon.*` methods require [`Caller::is_admin`].
+    #[tracing::instrument(name = "rpc.call", skip_all, fields(rpc.method = %method))]
+    pub async fn call(
+        &self,
+        caller: &Caller,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "cdd.from_openapi" => self.from_openapi(parse(params)?).await,
+            "cdd.to_openapi" => self.to_openapi(parse(params)?).await,
+            "cdd.to_docs_json" => self.to_docs_json(parse(params)?).await,
+            "daemon.list" => {
+                require_admin(caller)?;
+                Ok(json!(self.supervisor.list()))
+            }
+            "rpc.discover" => Ok(openrpc::document()),
+            "daemon.restart" => {
+                require_admin(caller)?;
+                let NameParams { name } = parse(params)?;
+                self.supervisor.restart(&name).map_err(daemon_error)?;
+                Ok(json!(self.supervisor.status(&name)))
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    async fn from_openapi(&self, params: FromOpenApiParams) -> Result<Value, RpcError> {
+        let sandbox = new_sandbox()?;
+        sandbox
+            .add_input(&params.spec_name, params.spec.as_bytes())
+            .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+        let job = |runtime| {
+            json!({
+                "target": params.target,
+                "input": sandbox.input_path(runtime, &params.spec_name),
+                "output": sandbox.output_path(runtime),
+            })
+        };
+        let result = self
+            .run(
+                &params.language,
+                "from_openapi",
+                &sandbox,
+                params.limits,
+                job,
+            )
+            .await?;
+        files_result(result, &sandbox)
+    }
+
+    async fn to_openapi(&self, params: ToOpenApiParams) -> Result<Value, RpcError> {
+        let sandbox = new_sandbox()?;
+        for file in &params.files {
+            sandbox
+                .add_input(&file.path, file.contents.as_bytes())
+                .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+        }
+        let job = |runtime| {
+            json!({
+                "input": sandbox.workspace_path(runtime),
+                "output": format!("{}/openapi.json", sandbox.output_path(runtime)),
+            })
+        };
+        let result = self
+            .run(&params.language, "to_openapi", &sandbox, params.limits, job)
+            .await?;
+        files_result(result, &sandbox)
+    }
+
+    async fn to_docs_json(&self, params: DocsParams) -> Result<Value, RpcError> {
+        let sandbox = new_sandbox()?;
+        sandbox
+            .add_input(&params.spec_name, params.spec.as_bytes())
+            .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+        let job = |runtime| json!({ "input": sandbox.input_path(runtime, &params.spec_name) });
+        self.run(
+            &params.language,
+            "to_docs_json",
+            &sandbox,
+            params.limits,
+            job,
+        )
+        .await
+    }
+
+    /// Hands `method` with the sandboxed `job`, built for the runtime that will see its paths,
+    /// to the server of `language` and waits for its result. `limits` tighten the configured
+    /// [`WasmLimits`] of a job run in a module of its own; running servers keep their own. The call counts as a toolchain run in [`Metrics::global`] and as an in-flight
+    /// [`Job`] for graceful shutdown.
+    #[tracing::instrument(
+        name = "toolchain.run",
+        skip_all,
+        fields(otel.kind = "client", language = %language, command = %method)
+    )]
+    async fn run(
+        &self,
+        language: &str,
+        method: &str,
+        sandbox: &Sandbox,
+        limits: WasmLimits,
+        job: impl Fn(Runtime) -> Value,
+    ) -> Result<Value, RpcError> {
+        let _job = Job::start();
+        let started = Instant::now();
+        let name = executable_name(language);
+        let host_job = job(Runtime::Native);
+        let mut labels = vec![method.to_string()];
+        labels.extend(
+            host_job
+                .get("target")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        );
+        let result = match (self.isolated(&name), &self.mcp) {
+            (Some(executor), _) => serve(executor, &name, method, sandbox, limits, job).await,
+            (None, Some(aggregator)) => call_tool(aggregator, &name, method, host_job).await,
+            (None, None) => self.request(&name, method, host_job).await,
+        };
+        let outcome = match &result {
+            Ok(_) => RunOutcome::Success,
+            Err(e) if e.code == DAEMON_ERROR || e.code == jsonrpc::INTERNAL_ERROR => {
+                RunOutcome::Error
+            }
+            Err(_) => RunOutcome::Failure,
+        };
+        Metrics::global().observe_run(&name, &labels, outcome, started.elapsed());
+        result
+    }
+
+    /// The executor to run the jobs of `name` with, if it runs them as WASM.
+    fn isolated(&self, name: &str) -> Option<&Executor> {
+        self.executor
+            .as_ref()
+            .filter(|executor| matches!(executor.resolve(name), Ok(Runtime::Wasm)))
+    }
+
+    /// Sends `method` to the running `serve_json_rpc` process of `name` over its stdio,
+    /// passing on the current trace in `params._meta`.
+    async fn request(
+        &self,
+        name: &str,
+        method: &str,
+        mut params: Value,
+    ) -> Result<Value, RpcError> {
+        let client = self.supervisor.client(name).map_err(|e| match e {
+            SupervisorError::External(_) => RpcError::new(
+                DAEMON_ERROR,
+                format!(
+                    "{} is an external server and cannot be handed sandboxed jobs",
+                    name
+                ),
+            ),
+            e => daemon_error(e),
+        })?;
+        telemetry::inject(&mut params);
+        client.request(method, params, CALL_TIMEOUT).await
+    }
+}
+
+/// Runs `method` with the sandboxed `job` in a `serve_json_rpc` module of `name` of its own,
+/// passing on the current trace in `params._meta`, and returns the module's reply.
+async fn serve(
+    executor: &Executor,
+    name: &str,
+    method: &str,
+    sandbox: &Sandbox,
+    limits: WasmLimits,
+    job: impl Fn(Runtime) -> Value,
+) -> Result<Value, RpcError> {
+    let params = |runtime| {
+        let mut params = job(runtime);
+        telemetry::inject(&mut params);
+        params
+    };
+    let output = executor
+        .serve_in(name, sandbox, method, params, limits)
+        .await
+        .map_err(|e| exec_error(name, e))?;
+    match served_reply(&output) {
+        Some(reply) => parse_reply(&reply),
+        None => Err(RpcError::new(
+            GENERATION_FAILED,
+            format!("{} exited without answering {}", name, method),
+        )
+        .with_data(json!({
+            "exit_code": output.code,
+            "stdout": String::from_utf8_lossy(&output.stdout),
+            "stderr": String::from_utf8_lossy(&output.stderr),
+        }))),
+    }
+}
+
+/// The reply to [`Executor::serve_in`]'s request among the lines the server wrote.
+fn served_reply(output: &ExecOutput) -> Option<Value> {
+    output.stdout.split(|b| *b == b'\n').find_map(|line| {
+        serde_json::from_slice::<Value>(line)
+            .ok()
+            .filter(|message| message.get("id").and_then(Value::as_u64) == Some(SERVE_REQUEST_ID))
+    })
+}
+
+/// Calls the MCP tool `method` of the server `name` with `arguments`, returning its structured
+/// content (or the whole tool result if it has none).
+async fn call_tool(
+    aggregator: &McpAggregator,
+    name: &str,
+    method: &str,
+    arguments: Value,
+) -> Result<Value, RpcError> {
+    let language = name.strip_prefix("cdd-").unwrap_or(name);
+    let params = json!({ "name": format!("{}.{}", language, method), "arguments": arguments });
+    let result = aggregator.handle("tools/call", params).await?;
+    if result.get("isError").and_then(Value::as_bool) == Some(true) {
+        return Err(RpcError::new(
+            GENERATION_FAILED,
+            format!("{} reported an error from {}", name, method),
+        )
+        .with_data(result));
+    }
+    Ok(match result.get("structuredContent") {
+        Some(content) => content.clone(),
+        None => result,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FromOpenApiParams {
+    language: String,
+    target: String,
+    spec: String,
+    #[serde(default = "default_spec_name")]
+    spec_name: String,
+    #[serde(default)]
+    limits: WasmLimits,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToOpenApiParams {
+    language: String,
+    files: Vec<InputFile>,
+    #[serde(default)]
+    limits: WasmLimits,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputFile {
+    path: String,
+    contents: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DocsParams {
+    language: String,
+    spec: String,
+    #[serde(default = "default_spec_name")]
+    spec_name: String,
+    #[serde(default)]
+    limits: WasmLimits,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NameParams {
+    name: String,
+}
+
+fn default_spec_name() -> String {
+    "openapi.json".to_string()
+}
+
+/// Deserializes by-name or positional `params`; omitted params count as `{}`.
+fn parse<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(e.to_string()))
+}
+
+fn new_sandbox() -> Result<Sandbox, RpcError> {
+    Sandbox::new().map_err(|e| RpcError::internal(e.to_string()))
+}
+
+/// `-32001` for the toolchain `name` having run into `limit`, with its `stderr` up to then.
+pub fn limit_exceeded(name: &str, limit: LimitKind, stderr: &str) -> RpcError {
+    RpcError::new(
+        LIMIT_EXCEEDED,
+        format!("{} exceeded its {} limit", name, limit),
+    )
+    .with_data(json!({ "limit": limit.to_string(), "stderr": stderr }))
+}
+
+fn exec_error(name: &str, e: ExecError) -> RpcError {
+    match e {
+        ExecError::NotFound { .. } | ExecError::Unsupported { .. } => {
+            RpcError::invalid_params(e.to_string())
+        }
+        ExecError::LimitExceeded { limit, stderr } => {
+            limit_exceeded(name, limit, &String::from_utf8_lossy(&stderr))
+        }
+        e => RpcError::internal(e.to_string()),
+    }
+}
+
+/// The server's `result`, with the files it wrote to the sandbox's `out/`.
+fn files_result(result: Value, sandbox: &Sandbox) -> Result<Value, RpcError> {
+    let files = sandbox
+        .collect()
+        .map_err(|e| RpcError::internal(e.to_string()))?;
+    Ok(json!({ "result": result, "files": files }))
+}
+
+fn require_admin(caller: &Caller) -> Result<(), RpcError> {
+    if caller.is_admin() {
+        Ok(())
+    } else {
+        Err(RpcError::new(FORBIDDEN, "Administrator role required"))
+    }
+}
+
+fn daemon_error(e: SupervisorError) -> RpcError {
+    RpcError::new(DAEMON_ERROR, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn context() -> RpcContext {
+        RpcContext::new(Arc::new(Supervisor::new(HashMap::new())))
+    }
+
+    #[test]
+    fn extra_params_are_rejected() {
+        let params = json!({
+            "language": "go",
+            "target": "to_sdk",
+            "spec": "{}",
+            "args": ["--config", "/etc/passwd"],
+        });
+        let error = parse::<FromOpenApiParams>(params).err().unwrap();
+        assert_eq!(error.code, jsonrpc::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn requested_limits_are_parsed() {
+        let params = json!({
+            "language": "go",
+            "spec": "{}",
+            "limits": { "fuel": 1000, "timeout_ms": 5000 },
+        });
+        let params = parse::<DocsParams>(params).unwrap();
+        assert_eq!(params.limits.fuel, Some(1000));
+        assert_eq!(params.limits.max_memory_bytes, None);
+        assert_eq!(params.limits.timeout_ms, Some(5000));
+    }
+
+    #[test]
+    fn limit_hits_have_their_own_error_code() {
+        let e = ExecError::LimitExceeded {
+            limit: LimitKind::Memory,
+            stderr: b"out of memory".to_vec(),
+        };
+        let error = exec_error("cdd-go", e);
+        assert_eq!(error.code, LIMIT_EXCEEDED);
+        assert_eq!(
+            error.data,
+            Some(json!({ "limit": "memory", "stderr": "out of memory" }))
+        );
+        for kind in LimitKind::ALL {
+            assert_eq!(LimitKind::from_exit_code(kind.exit_code()), Some(kind));
+            assert_eq!(LimitKind::from_name(&kind.to_string()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn methods_take_no_command_line_arguments() {
+        for method in METHODS {
+            assert!(
+                method.params.iter().all(|p| p.name != "args"),
+                "{}",
+                method.name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn jobs_for_unknown_servers_fail() {
+        let caller = Caller::new("dev1", false);
+        let params = json!({ "language": "go", "spec": "{}" });
+        let error = context()
+            .call(&caller, "cdd.to_docs_json", params)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, DAEMON_ERROR);
+    }
+
+    #[tokio::test]
+    async fn sandbox_escapes_are_invalid_params() {
+        let caller = Caller::new("dev1", false);
+        let params = json!({ "language": "go", "files": [{ "path": "../x", "contents": "" }] });
+        let error = context()
+            .call(&caller, "cdd.to_openapi", params)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, jsonrpc::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn served_replies_are_found_among_the_output() {
+        let output = ExecOutput {
+            code: Some(0),
+            stdout: b"starting\n{\"jsonrpc\":\"2.0\",\"method\":\"log\"}\n{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"ok\":true}}\n".to_vec(),
+            stderr: Vec::new(),
+        };
+        let reply = served_reply(&output).unwrap();
+        assert_eq!(parse_reply(&reply), Ok(json!({ "ok": true })));
+        assert_eq!(served_reply(&ExecOutput::default()), None);
+    }
+
+    #[tokio::test]
+    async fn daemon_methods_require_an_administrator() {
+        let context = context();
+        for (method, params) in [
+            ("daemon.list", Value::Null),
+            ("daemon.restart", json!({ "name": "cdd-go" })),
+        ] {
+            let error = context
+                .call(&Caller::new("dev1", false), method, params)
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, FORBIDDEN, "{}", method);
+        }
+        let daemons = context
+            .call(&Caller::new("root", true), "daemon.list", Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(daemons, json!([]));
+    }
+}
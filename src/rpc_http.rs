@@ -0,0 +1,84 @@
+//! JSON-RPC 2.0 over HTTP, mounted by `cdd-rpc` and `cdd-rpc-wasm`.
+//!
+//! `POST /rpc` takes a request or a batch and answers with the reply as `application/json`, or
+//! `204 No Content` when the payload held only notifications. A lone request whose toolchain ran
+//! into a WASM limit is answered with `413 Payload Too Large` (memory) or `504 Gateway Timeout`
+//! (fuel, timeout) instead of `200 OK`, so that plain HTTP clients can tell. Every route requires a
+//! gateway-issued bearer token (see [`crate::auth`]).
+
+use actix_web::http::StatusCode;
+use actix_web::{middleware, web, HttpResponse};
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+use crate::auth::{self, Caller};
+use serde_json::Value;
+
+use crate::executor::LimitKind;
+use crate::jsonrpc;
+use crate::rpc::{RpcContext, LIMIT_EXCEEDED};
+
+/// Mounts the `/rpc` routes. Requires `web::Data<RpcContext>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/rpc")
+            .wrap(middleware::from_fn(auth::resolve_caller))
+            .wrap(HttpAuthentication::bearer(auth::validator))
+            .route("", web::post().to(post_rpc)),
+    );
+}
+
+/// `POST /rpc`: one JSON-RPC round trip.
+async fn post_rpc(
+    context: web::Data<RpcContext>,
+    caller: web::ReqData<Caller>,
+    body: String,
+) -> HttpResponse {
+    let caller = caller.into_inner();
+    let reply = jsonrpc::dispatch(&body, |request| {
+        let context = context.clone();
+        let caller = caller.clone();
+        async move { context.call(&caller, &request.method, request.params).await }
+    })
+    .await;
+    match reply {
+        Some(reply) => HttpResponse::build(status(&reply)).json(reply),
+        None => HttpResponse::NoContent().finish(),
+    }
+}
+
+/// Status of `reply`: that of the limit a lone request ran into (see
+/// [`LimitKind::status_code`]), `200 OK` otherwise.
+fn status(reply: &Value) -> StatusCode {
+    let error = reply.get("error");
+    if error.and_then(|e| e.get("code")).and_then(Value::as_i64) != Some(LIMIT_EXCEEDED) {
+        return StatusCode::OK;
+    }
+    error
+        .and_then(|e| e.pointer("/data/limit"))
+        .and_then(Value::as_str)
+        .and_then(LimitKind::from_name)
+        .map_or(StatusCode::OK, LimitKind::status_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::RpcError;
+    use crate::rpc::limit_exceeded;
+    use serde_json::json;
+
+    #[test]
+    fn lone_limit_errors_set_the_status() {
+        let reply = |error: RpcError| jsonrpc::response(json!(1), Err(error));
+        let memory = reply(limit_exceeded("cdd-go", LimitKind::Memory, ""));
+        assert_eq!(status(&memory), StatusCode::PAYLOAD_TOO_LARGE);
+        let fuel = reply(limit_exceeded("cdd-go", LimitKind::Fuel, ""));
+        assert_eq!(status(&fuel), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(status(&json!([memory])), StatusCode::OK);
+        assert_eq!(status(&reply(RpcError::internal("x"))), StatusCode::OK);
+        assert_eq!(
+            status(&jsonrpc::response(json!(1), Ok(json!({})))),
+            StatusCode::OK
+        );
+    }
+}
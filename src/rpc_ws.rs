@@ -0,0 +1,129 @@
+//! JSON-RPC 2.0 over WebSocket, with server-push daemon events.
+//!
+//! `GET /ws` upgrades to a WebSocket on which every text frame is a JSON-RPC request or batch,
+//! served by the same [`RpcContext`] as `POST /rpc`; replies are sent as they complete, so a
+//! long generation does not hold up other calls. Administrators are also pushed every
+//! [`DaemonEvent`] of the supervisor as a `daemon.<event>` notification (`daemon.started`,
+//! `daemon.ready`, `daemon.exited`, `daemon.backoff`, `daemon.circuit`, `daemon.unhealthy`,
+//! `daemon.stopped`, `daemon.failed`, `daemon.log`) whose params are the event itself; like
+//! `daemon.list`, the events and the servers' output are not shown to other callers.
+//!
+//! Browsers cannot set headers on a WebSocket handshake, so the bearer token may also be given
+//! as `?access_token=...` (see [`auth::token_from_query`]).
+
+use std::sync::Arc;
+
+use actix_web::{middleware, web, HttpRequest, HttpResponse};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use actix_ws::{Message, MessageStream, Session};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::auth::{self, Caller};
+use crate::jsonrpc;
+use crate::rpc::RpcContext;
+use crate::supervisor::DaemonEvent;
+
+/// Mounts `/ws`. Requires `web::Data<RpcContext>` in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/ws")
+            .wrap(middleware::from_fn(auth::resolve_caller))
+            .wrap(HttpAuthentication::bearer(auth::validator))
+            .wrap(middleware::from_fn(auth::token_from_query))
+            .route(web::get().to(open_socket)),
+    );
+}
+
+/// `GET /ws`: upgrades an authenticated request to a WebSocket.
+async fn open_socket(
+    context: web::Data<RpcContext>,
+    caller: web::ReqData<Caller>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(serve(
+        context.into_inner(),
+        caller.into_inner(),
+        session,
+        messages,
+    ));
+    Ok(response)
+}
+
+/// Serves one connection until either side closes it.
+async fn serve(
+    context: Arc<RpcContext>,
+    caller: Caller,
+    mut session: Session,
+    mut messages: MessageStream,
+) {
+    let mut events = caller.is_admin().then(|| context.supervisor().subscribe());
+    let caller = Arc::new(caller);
+    loop {
+        tokio::select! {
+            message = messages.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let context = context.clone();
+                    let caller = caller.clone();
+                    let mut session = session.clone();
+                    actix_web::rt::spawn(async move {
+                        if let Some(reply) = dispatch(&context, &caller, &text).await {
+                            let _ = session.text(reply.to_string()).await;
+                        }
+                    });
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+            event = next_event(&mut events) => match event {
+                Ok(event) => {
+                    if session.text(notification(&event).to_string()).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("WebSocket client fell behind; dropped {} daemon events", missed);
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+async fn dispatch(context: &RpcContext, caller: &Caller, payload: &str) -> Option<Value> {
+    jsonrpc::dispatch(payload, |request| async move {
+        context.call(caller, &request.method, request.params).await
+    })
+    .await
+}
+
+/// The next daemon event, or never without a subscription.
+async fn next_event(
+    events: &mut Option<broadcast::Receiver<DaemonEvent>>,
+) -> Result<DaemonEvent, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn notification(event: &DaemonEvent) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": format!("daemon.{}", event.kind.name()),
+        "params": event,
+    })
+}
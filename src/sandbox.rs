@@ -0,0 +1,295 @@
+//! Isolated per-job filesystem for generator runs.
+//!
+//! Server-side generation must never hand a toolchain the host's working directory. A
+//! [`Sandbox`] is a fresh temporary directory holding a `workspace/` with only the job's
+//! inputs and an empty `out/`; WASM modules see them as `/workspace` and `/out` and nothing
+//! else. After the run, [`Sandbox::collect`] returns whatever was written to `out/` and the
+//! directory is removed when the sandbox is dropped.
+//!
+//! Every sandbox lives under [`jobs_dir`], so that the host paths of a job can be handed to a
+//! supervised native server. A WASM module is only ever given the [`Sandbox::preopens`] of its
+//! own job, never the whole directory. The directory has a random name and is only accessible
+//! to the current user, so other local users can neither predict nor read it.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::toolchain::Runtime;
+use crate::wasm::Preopen;
+
+/// Guest path of the directory holding the job's inputs.
+pub const WORKSPACE_GUEST_DIR: &str = "/workspace";
+/// Guest path of the directory generators write into.
+pub const OUT_GUEST_DIR: &str = "/out";
+
+static JOBS_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Directory holding the sandbox of every job of this process, created on first use in the
+/// system temporary directory with a random name and mode `0700`, and removed by
+/// [`remove_jobs_dir`].
+pub fn jobs_dir() -> io::Result<PathBuf> {
+    let mut jobs_dir = JOBS_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(dir) = &*jobs_dir {
+        return Ok(dir.clone());
+    }
+    let dir = create_private_dir(&std::env::temp_dir())?;
+    *jobs_dir = Some(dir.clone());
+    Ok(dir)
+}
+
+/// Removes [`jobs_dir`] along with any sandbox left in it; called on shutdown.
+pub fn remove_jobs_dir() -> io::Result<()> {
+    let dir = JOBS_DIR.lock().unwrap_or_else(|e| e.into_inner()).take();
+    match dir {
+        Some(dir) => fs::remove_dir_all(dir),
+        None => Ok(()),
+    }
+}
+
+/// Creates a randomly named `cdd-ctl-jobs-<hex>` directory in `parent` that only the current
+/// user can access, failing rather than reusing a directory that already exists.
+fn create_private_dir(parent: &Path) -> io::Result<PathBuf> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(io::Error::other)?;
+    let dir = parent.join(format!("cdd-ctl-jobs-{}", hex::encode(bytes)));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir)?;
+    Ok(dir)
+}
+
+/// How [`GeneratedFile::contents`] is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    /// The file is valid UTF-8 and stored verbatim.
+    Utf8,
+    /// The file is binary and stored as lowercase hex.
+    Hex,
+}
+
+/// A file written by a generator into `/out`.
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedFile {
+    /// Path relative to `/out`, always `/`-separated.
+    pub path: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Encoding of `contents`.
+    pub encoding: FileEncoding,
+    /// File contents.
+    pub contents: String,
+}
+
+/// A temporary `workspace/` + `out/` directory pair owned by one job.
+#[derive(Debug)]
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// Creates an empty sandbox under [`jobs_dir`].
+    pub fn new() -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let base = jobs_dir()?;
+        loop {
+            let root = base.join(format!("job-{}", NEXT.fetch_add(1, Ordering::Relaxed)));
+            match fs::create_dir(&root) {
+                Ok(()) => {
+                    let sandbox = Self { root };
+                    fs::create_dir(sandbox.workspace_dir())?;
+                    fs::create_dir(sandbox.out_dir())?;
+                    return Ok(sandbox);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Host directory mounted at [`WORKSPACE_GUEST_DIR`].
+    pub fn workspace_dir(&self) -> PathBuf {
+        self.root.join("workspace")
+    }
+
+    /// Host directory mounted at [`OUT_GUEST_DIR`].
+    pub fn out_dir(&self) -> PathBuf {
+        self.root.join("out")
+    }
+
+    /// Writes an input file into the workspace.
+    ///
+    /// `name` must be a plain relative path; absolute paths and `..` are rejected.
+    pub fn add_input(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let relative = Path::new(name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid sandbox input name: {:?}", name),
+            ));
+        }
+        let path = self.workspace_dir().join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+    }
+
+    /// Path of the workspace input `name` as seen by a toolchain running under `runtime`.
+    pub fn input_path(&self, runtime: Runtime, name: &str) -> String {
+        match runtime {
+            Runtime::Wasm => format!("{}/{}", WORKSPACE_GUEST_DIR, name),
+            _ => self
+                .workspace_dir()
+                .join(name)
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// Path of the workspace directory as seen by a toolchain running under `runtime`.
+    pub fn workspace_path(&self, runtime: Runtime) -> String {
+        match runtime {
+            Runtime::Wasm => WORKSPACE_GUEST_DIR.to_string(),
+            _ => self.workspace_dir().to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Path of the output directory as seen by a toolchain running under `runtime`.
+    pub fn output_path(&self, runtime: Runtime) -> String {
+        match runtime {
+            Runtime::Wasm => OUT_GUEST_DIR.to_string(),
+            _ => self.out_dir().to_string_lossy().into_owned(),
+        }
+    }
+
+    /// The only directories a WASM module running in this sandbox can see.
+    pub fn preopens(&self) -> Vec<Preopen> {
+        vec![
+            Preopen::new(self.workspace_dir(), WORKSPACE_GUEST_DIR),
+            Preopen::new(self.out_dir(), OUT_GUEST_DIR),
+        ]
+    }
+
+    /// Reads every regular file written to `out/`, sorted by path.
+    ///
+    /// Symlinks are skipped so that a generator cannot use them to read host files.
+    pub fn collect(&self) -> io::Result<Vec<GeneratedFile>> {
+        let out = self.out_dir();
+        let mut files = Vec::new();
+        let mut pending = vec![out.clone()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let path = entry.path();
+                if file_type.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                if !file_type.is_file() {
+                    continue;
+                }
+                let bytes = fs::read(&path)?;
+                let relative = path
+                    .strip_prefix(&out)
+                    .unwrap_or(&path)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let size = bytes.len() as u64;
+                let (encoding, contents) = match String::from_utf8(bytes) {
+                    Ok(text) => (FileEncoding::Utf8, text),
+                    Err(e) => (FileEncoding::Hex, hex::encode(e.into_bytes())),
+                };
+                files.push(GeneratedFile {
+                    path: relative,
+                    size,
+                    encoding,
+                    contents,
+                });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.root) {
+            log::warn!("Failed to remove sandbox {}: {}", self.root.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sandboxes_live_under_the_jobs_dir() {
+        let sandbox = Sandbox::new().unwrap();
+        assert!(sandbox.workspace_dir().starts_with(jobs_dir().unwrap()));
+        assert!(sandbox.out_dir().is_dir());
+    }
+
+    #[test]
+    fn jobs_dirs_are_private_and_unpredictable() {
+        let parent = std::env::temp_dir().join(format!("cdd-ctl-private-{}", std::process::id()));
+        fs::create_dir_all(&parent).unwrap();
+        let first = create_private_dir(&parent).unwrap();
+        let second = create_private_dir(&parent).unwrap();
+        assert_ne!(first, second);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn inputs_cannot_escape_the_workspace() {
+        let sandbox = Sandbox::new().unwrap();
+        for name in ["", "../spec.json", "/etc/passwd", "a/../../b"] {
+            assert!(sandbox.add_input(name, b"{}").is_err(), "{:?}", name);
+        }
+        sandbox.add_input("nested/spec.json", b"{}").unwrap();
+        assert!(sandbox.workspace_dir().join("nested/spec.json").is_file());
+    }
+
+    #[test]
+    fn collect_returns_sorted_output_files() {
+        let sandbox = Sandbox::new().unwrap();
+        fs::create_dir(sandbox.out_dir().join("src")).unwrap();
+        fs::write(sandbox.out_dir().join("src/lib.rs"), "fn main() {}").unwrap();
+        fs::write(sandbox.out_dir().join("blob.bin"), [0xff, 0x00]).unwrap();
+        let files = sandbox.collect().unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["blob.bin", "src/lib.rs"]);
+        assert_eq!(files[0].encoding, FileEncoding::Hex);
+        assert_eq!(files[0].contents, "ff00");
+        assert_eq!(files[1].encoding, FileEncoding::Utf8);
+    }
+
+    #[test]
+    fn dropping_removes_the_sandbox() {
+        let sandbox = Sandbox::new().unwrap();
+        let root = sandbox.workspace_dir();
+        drop(sandbox);
+        assert!(!root.exists());
+    }
+}
@@ -0,0 +1,166 @@
+//! Graceful shutdown of the server binaries.
+//!
+//! On `SIGTERM` or `SIGINT` (Ctrl-C), [`serve`]:
+//!
+//! - stops accepting connections;
+//! - waits up to `shutdown.drain_timeout_ms` for in-flight work to finish: requests being
+//!   handled (counted by the [`track_jobs`] middleware) and toolchain runs, including those
+//!   started over a WebSocket. A second signal stops waiting.
+//!
+//! The binary then stops the supervised processes: each gets `SIGTERM` to its process group,
+//! and `SIGKILL` after its `stop_grace_ms` (see [`crate::process::terminate`]). It exits with
+//! [`exit_code`]: `0` if everything finished in time, `1` if work was abandoned or a process
+//! had to be killed.
+
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Server, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use serde::Deserialize;
+use tokio::sync::watch;
+
+/// Shutdown settings, configured as `shutdown` in the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Time in-flight work is given to finish once a signal is received, in milliseconds.
+    pub drain_timeout_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_ms: 30_000,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    /// [`ShutdownConfig::drain_timeout_ms`] as a duration.
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_millis(self.drain_timeout_ms)
+    }
+
+    /// Seconds to pass to `HttpServer::shutdown_timeout`: a little longer than the drain, so
+    /// that work abandoned by the drain is not mistaken for finished when actix drops it.
+    pub fn server_timeout_secs(&self) -> u64 {
+        self.drain_timeout_ms.div_ceil(1000) + 1
+    }
+}
+
+/// A unit of in-flight work, counted until dropped.
+pub struct Job(());
+
+impl Job {
+    /// Counts a job as in flight.
+    pub fn start() -> Self {
+        in_flight().send_modify(|jobs| *jobs += 1);
+        Job(())
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        in_flight().send_modify(|jobs| *jobs -= 1);
+    }
+}
+
+/// Number of jobs currently in flight.
+pub fn jobs() -> usize {
+    *in_flight().borrow()
+}
+
+fn in_flight() -> &'static watch::Sender<usize> {
+    static IN_FLIGHT: OnceLock<watch::Sender<usize>> = OnceLock::new();
+    IN_FLIGHT.get_or_init(|| watch::Sender::new(0))
+}
+
+/// Middleware counting every request as a [`Job`] until its response is ready. Register it
+/// with `.wrap(actix_web::middleware::from_fn(shutdown::track_jobs))`.
+pub async fn track_jobs(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let _job = Job::start();
+    next.call(req).await
+}
+
+/// Runs `server` until a termination signal, then stops it and drains in-flight jobs. Returns
+/// whether they all finished within `config.drain_timeout_ms`.
+///
+/// Build the server with `.disable_signals()`, so that signals are left to this function, and
+/// `.shutdown_timeout(config.server_timeout_secs())`.
+pub async fn serve(server: Server, config: &ShutdownConfig) -> std::io::Result<bool> {
+    let handle = server.handle();
+    let mut server = std::pin::pin!(server);
+    tokio::select! {
+        result = &mut server => return result.map(|()| true),
+        signal = termination() => log::info!("Received {}; shutting down", signal),
+    }
+
+    // Stops accepting connections; open ones are closed as soon as they are idle. The server
+    // carries out the stop while it is polled, alongside the drain.
+    let stop = handle.stop(true);
+    let (result, (), drained) = tokio::join!(server, stop, drain(config.drain_timeout()));
+    result?;
+    Ok(drained)
+}
+
+/// Waits up to `timeout` for the in-flight jobs to finish, returning whether they did.
+async fn drain(timeout: Duration) -> bool {
+    if jobs() > 0 {
+        log::info!("Waiting up to {:?} for {} jobs to finish", timeout, jobs());
+    }
+    let mut receiver = in_flight().subscribe();
+    let drained = tokio::select! {
+        result = tokio::time::timeout(timeout, receiver.wait_for(|jobs| *jobs == 0)) => {
+            result.is_ok()
+        }
+        signal = termination() => {
+            log::warn!("Received {} again; not waiting any longer", signal);
+            false
+        }
+    };
+    if !drained {
+        log::warn!("Abandoning {} unfinished jobs", jobs());
+    }
+    drained
+}
+
+/// `0` after a `clean` shutdown, `1` otherwise.
+pub fn exit_code(clean: bool) -> ExitCode {
+    if clean {
+        log::info!("Shut down cleanly");
+        ExitCode::SUCCESS
+    } else {
+        log::warn!("Shut down with unfinished work or killed processes");
+        ExitCode::FAILURE
+    }
+}
+
+/// Resolves with the name of the next `SIGTERM` or `SIGINT`.
+async fn termination() -> &'static str {
+    #[cfg(unix)]
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            return tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = interrupt() => "SIGINT",
+            };
+        }
+        Err(e) => log::warn!("Cannot listen for SIGTERM: {}", e),
+    }
+    interrupt().await;
+    "SIGINT"
+}
+
+/// Resolves on the next Ctrl-C; never, if it cannot be received.
+async fn interrupt() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("Cannot listen for SIGINT: {}", e);
+        std::future::pending::<()>().await
+    }
+}
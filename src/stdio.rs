@@ -0,0 +1,363 @@
+//! JSON-RPC 2.0 client over the stdio of a supervised process.
+//!
+//! The [`Supervisor`](crate::supervisor::Supervisor) keeps the stdin of every process it spawns
+//! and wraps it in a [`StdioClient`] for as long as the process runs, so that the process is
+//! never handed an EOF it would take as a request to exit. Requests are written to stdin as
+//! newline-delimited JSON by a task of their own, so that no caller (least of all the one reading
+//! the process's stdout) waits for the process to read its input. Every line the process prints on stdout is first offered to
+//! [`StdioClient::dispatch`]:
+//!
+//! - replies are handed to the request waiting for them;
+//! - requests from the process are answered with `-32601`, and its notifications are dropped;
+//! - anything else is left to be logged as output.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::jsonrpc::{self, RpcError};
+
+type Waiter = oneshot::Sender<Result<Value, RpcError>>;
+
+/// Requests and notifications sent to one running process.
+pub struct StdioClient {
+    name: String,
+    next_id: AtomicU64,
+    /// Lines for the task writing to stdin; sending fails once it has stopped.
+    lines: mpsc::UnboundedSender<Vec<u8>>,
+    /// Requests waiting for a reply; `None` once the process is gone.
+    pending: std::sync::Mutex<Option<HashMap<u64, Waiter>>>,
+}
+
+impl StdioClient {
+    /// Creates a client writing to `stdin`, the input of the process of server `name`. Must be
+    /// called within a Tokio runtime, on which the writing task is spawned.
+    pub fn new(name: impl Into<String>, stdin: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let name = name.into();
+        let (lines, received) = mpsc::unbounded_channel();
+        tokio::spawn(write_lines(name.clone(), stdin, received));
+        Self {
+            name,
+            next_id: AtomicU64::new(1),
+            lines,
+            pending: std::sync::Mutex::new(Some(HashMap::new())),
+        }
+    }
+
+    /// Server name, e.g. `cdd-go`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the process has gone away. A restarted server gets a new client, so any session
+    /// state (such as an MCP `initialize`) set up through this one has to be set up again.
+    pub fn is_closed(&self) -> bool {
+        self.pending().is_none()
+    }
+
+    /// Sends `method` and waits up to `timeout` for the reply.
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.pending().as_mut() {
+            Some(pending) => {
+                pending.insert(id, tx);
+            }
+            None => return Err(self.closed()),
+        }
+        let message = message(Some(id), method, params);
+        if let Err(e) = self.write(&message) {
+            self.forget(id);
+            return Err(RpcError::internal(format!(
+                "Failed to write to {}: {}",
+                self.name, e
+            )));
+        }
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(self.closed()),
+            Err(_) => {
+                self.forget(id);
+                Err(RpcError::internal(format!(
+                    "{} did not answer {} within {:?}",
+                    self.name, method, timeout
+                )))
+            }
+        }
+    }
+
+    /// Sends the notification `method`.
+    pub async fn notify(&self, method: &str, params: Value) -> Result<(), RpcError> {
+        if self.is_closed() {
+            return Err(self.closed());
+        }
+        self.write(&message(None, method, params))
+            .map_err(|e| RpcError::internal(format!("Failed to write to {}: {}", self.name, e)))
+    }
+
+    /// Handles `line` if it is a JSON-RPC message, returning whether it was one. Other lines
+    /// are ordinary output. Never waits for the process.
+    pub fn dispatch(&self, line: &str) -> bool {
+        let Ok(Value::Object(message)) = serde_json::from_str::<Value>(line) else {
+            return false;
+        };
+        if message.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return false;
+        }
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            // Requests from the process (sampling, roots, ...) are not supported; answer them
+            // so that it does not wait forever.
+            if let Some(id) = message.get("id") {
+                let reply = jsonrpc::response(id.clone(), Err(RpcError::method_not_found(method)));
+                if let Err(e) = self.write(&reply) {
+                    log::debug!("Failed to answer {} from {}: {}", method, self.name, e);
+                }
+            }
+            return true;
+        }
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            return false;
+        };
+        let result = match message.get("error") {
+            Some(error) => Err(serde_json::from_value(error.clone())
+                .unwrap_or_else(|_| RpcError::internal(error.to_string()))),
+            None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        if let Some(waiter) = self.forget(id) {
+            let _ = waiter.send(result);
+        }
+        true
+    }
+
+    /// Marks the process as gone, failing every request still waiting for a reply.
+    pub fn close(&self) {
+        self.pending().take();
+    }
+
+    /// Like [`StdioClient::close`], failing the requests still waiting with `error` instead,
+    /// for a process whose exit explains why it never answered.
+    pub fn fail(&self, error: RpcError) {
+        for (_, waiter) in self.pending().take().into_iter().flatten() {
+            let _ = waiter.send(Err(error.clone()));
+        }
+    }
+
+    /// Queues `message` for the process.
+    fn write(&self, message: &Value) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.lines
+            .send(line)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "stdin is closed"))
+    }
+
+    fn forget(&self, id: u64) -> Option<Waiter> {
+        self.pending()
+            .as_mut()
+            .and_then(|pending| pending.remove(&id))
+    }
+
+    fn closed(&self) -> RpcError {
+        RpcError::internal(format!("{} is not running", self.name))
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, Option<HashMap<u64, Waiter>>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Writes every line received to `stdin`, until the client is dropped or a write fails.
+async fn write_lines(
+    name: String,
+    mut stdin: impl AsyncWrite + Unpin,
+    mut lines: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    while let Some(line) = lines.recv().await {
+        let written = match stdin.write_all(&line).await {
+            Ok(()) => stdin.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            log::debug!("Failed to write to {}: {}", name, e);
+            return;
+        }
+    }
+}
+
+/// A request (with `id`) or notification; null `params` are left out.
+fn message(id: Option<u64>, method: &str, params: Value) -> Value {
+    let mut message = json!({ "jsonrpc": "2.0", "method": method });
+    if let Some(id) = id {
+        message["id"] = json!(id);
+    }
+    if !params.is_null() {
+        message["params"] = params;
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    /// A client whose writes can be read back as lines.
+    fn client() -> (
+        std::sync::Arc<StdioClient>,
+        tokio::io::Lines<BufReader<tokio::io::DuplexStream>>,
+    ) {
+        let (stdin, written) = tokio::io::duplex(4096);
+        let client = std::sync::Arc::new(StdioClient::new("cdd-go", stdin));
+        (client, BufReader::new(written).lines())
+    }
+
+    #[tokio::test]
+    async fn reply_resolves_request() {
+        let (client, mut written) = client();
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .request("ping", json!({}), Duration::from_secs(5))
+                    .await
+            }
+        });
+        let sent: Value =
+            serde_json::from_str(&written.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(sent["method"], "ping");
+        let reply = json!({ "jsonrpc": "2.0", "id": sent["id"], "result": "pong" });
+        assert!(client.dispatch(&reply.to_string()));
+        assert_eq!(request.await.unwrap(), Ok(json!("pong")));
+    }
+
+    #[tokio::test]
+    async fn error_reply_is_returned() {
+        let (client, mut written) = client();
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .request("x", Value::Null, Duration::from_secs(5))
+                    .await
+            }
+        });
+        let sent: Value =
+            serde_json::from_str(&written.next_line().await.unwrap().unwrap()).unwrap();
+        let reply = json!({
+            "jsonrpc": "2.0",
+            "id": sent["id"],
+            "error": { "code": -32602, "message": "bad" },
+        });
+        client.dispatch(&reply.to_string());
+        assert_eq!(
+            request.await.unwrap().unwrap_err().code,
+            jsonrpc::INVALID_PARAMS
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_from_the_process_are_refused() {
+        let (client, mut written) = client();
+        let request = json!({ "jsonrpc": "2.0", "id": 7, "method": "roots/list" });
+        assert!(client.dispatch(&request.to_string()));
+        let reply: Value =
+            serde_json::from_str(&written.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["error"]["code"], jsonrpc::METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn answering_never_waits_for_the_process_to_read() {
+        // Nobody reads what is written, so stdin is full after the first reply.
+        let (stdin, _unread) = tokio::io::duplex(16);
+        let client = StdioClient::new("cdd-go", stdin);
+        for id in 0..100 {
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": "roots/list" });
+            assert!(client.dispatch(&request.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn other_output_is_not_consumed() {
+        let (client, _written) = client();
+        assert!(!client.dispatch("listening on 9093"));
+        assert!(!client.dispatch(r#"{"level":"info"}"#));
+    }
+
+    #[tokio::test]
+    async fn close_fails_pending_and_later_requests() {
+        let (client, _written) = client();
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .request("x", Value::Null, Duration::from_secs(5))
+                    .await
+            }
+        });
+        while client.pending().as_ref().is_some_and(HashMap::is_empty) {
+            tokio::task::yield_now().await;
+        }
+        client.close();
+        assert!(request.await.unwrap().is_err());
+        assert!(client.is_closed());
+        assert!(client
+            .request("x", Value::Null, Duration::from_secs(5))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fail_hands_pending_requests_the_error() {
+        let (client, _written) = client();
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .request("x", Value::Null, Duration::from_secs(5))
+                    .await
+            }
+        });
+        while client.pending().as_ref().is_some_and(HashMap::is_empty) {
+            tokio::task::yield_now().await;
+        }
+        let error = RpcError::new(-32001, "cdd-go exceeded its fuel limit");
+        client.fail(error.clone());
+        assert_eq!(request.await.unwrap(), Err(error));
+        assert!(client.is_closed());
+    }
+
+    #[tokio::test]
+    async fn timeout_forgets_request() {
+        let (client, _written) = client();
+        let result = client
+            .request("x", Value::Null, Duration::from_millis(10))
+            .await;
+        assert!(result.is_err());
+        assert!(client.pending().as_ref().is_some_and(HashMap::is_empty));
+    }
+
+    #[tokio::test]
+    async fn null_params_are_left_out() {
+        let (client, mut written) = client();
+        client
+            .notify("notifications/initialized", Value::Null)
+            .await
+            .unwrap();
+        let sent: Value =
+            serde_json::from_str(&written.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(
+            sent,
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })
+        );
+    }
+}
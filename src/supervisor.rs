@@ -0,0 +1,1424 @@
+//! Supervisor for the configured `servers`.
+//!
+//! Plays the role of `cdd_engine`'s `ProcessManager` (one task per process, tagged log
+//! forwarding, restart after `restart_delay_ms` up to `max_retries`, with the counter reset
+//! once a process has stayed up for [`STABLE_UPTIME`]) while keeping each process's state
+//! observable and controllable, so that the RPC and admin surfaces can list, restart, stop and
+//! start individual daemons. Lifecycle changes and output lines are also published as
+//! [`DaemonEvent`]s to every [`Supervisor::subscribe`]r.
+//!
+//! Each server's [`ServerOptions`] add what `ProcessConfig` cannot express, starting with a
+//! [`HealthCheck`]: a running process that fails it `failure_threshold` times in a row is
+//! killed and restarted like one that exited. A [`Backoff`](crate::backoff::Backoff) shapes
+//! the delay between restarts, and a [`CircuitBreaker`](crate::backoff::CircuitBreaker) retries
+//! a server that exhausted `max_retries` after a cool-down instead of leaving it failed.
+//!
+//! A server is only spawned once every server in its `depends_on` is ready, and counts as
+//! ready itself once running and past its [`ReadyWhen`](crate::readiness::ReadyWhen), if any.
+//!
+//! The supervisor owns the stdin of every process it spawns: [`Supervisor::client`] sends
+//! JSON-RPC requests over it, and the replies are picked out of the process's stdout before
+//! the remaining lines are logged (see [`crate::stdio`]).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::backoff::CircuitState;
+use crate::config::ServerOptions;
+use crate::executor::LimitKind;
+use crate::health::{HealthCheck, HealthStatus};
+use crate::logging;
+use crate::logs::{LogLine, ProcessLog};
+use crate::process;
+use crate::rpc;
+use crate::stdio::StdioClient;
+use crate::ProcessConfig;
+
+/// Uptime after which a process is considered healthy and its retry counter is reset.
+pub const STABLE_UPTIME: Duration = Duration::from_secs(30);
+
+/// Lifecycle state of a supervised process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaemonState {
+    /// Waiting for the servers in its `depends_on` to be ready.
+    Waiting,
+    /// Being spawned.
+    Starting,
+    /// Spawned and not yet exited.
+    Running,
+    /// Exited and waiting out the restart delay.
+    Backoff,
+    /// Stopped on request.
+    Stopped,
+    /// Gave up after exhausting `max_retries`, for good or until the circuit breaker's
+    /// cool-down has passed.
+    Failed,
+    /// Served elsewhere (`external_address`); nothing is spawned.
+    External,
+}
+
+impl DaemonState {
+    /// Every state, in lifecycle order.
+    pub const ALL: [DaemonState; 7] = [
+        DaemonState::Waiting,
+        DaemonState::Starting,
+        DaemonState::Running,
+        DaemonState::Backoff,
+        DaemonState::Stopped,
+        DaemonState::Failed,
+        DaemonState::External,
+    ];
+
+    /// Lowercase name of the state, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            DaemonState::Waiting => "waiting",
+            DaemonState::Starting => "starting",
+            DaemonState::Running => "running",
+            DaemonState::Backoff => "backoff",
+            DaemonState::Stopped => "stopped",
+            DaemonState::Failed => "failed",
+            DaemonState::External => "external",
+        }
+    }
+}
+
+/// Point-in-time view of a supervised process.
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonStatus {
+    /// Server name, e.g. `cdd-go`.
+    pub name: String,
+    /// Current state.
+    pub state: DaemonState,
+    /// Whether the server is running and has met its `ready_when`; external servers always are.
+    pub ready: bool,
+    /// OS process id while running.
+    pub pid: Option<u32>,
+    /// Seconds since the Unix epoch when the current process was spawned.
+    pub started_at: Option<u64>,
+    /// Seconds the current process has been running.
+    pub uptime_secs: Option<u64>,
+    /// Consecutive failed starts counted against `max_retries`.
+    pub retries: u32,
+    /// Configured `max_retries`.
+    pub max_retries: u32,
+    /// Total number of restarts since the supervisor started.
+    pub restarts: u32,
+    /// Exit code of the previous process, if it exited normally.
+    pub last_exit_code: Option<i32>,
+    /// Program being supervised.
+    pub command: Option<String>,
+    /// Address of an externally managed server.
+    pub external_address: Option<String>,
+    /// Latest health-check results, if a check is configured.
+    pub health: Option<HealthStatus>,
+    /// Circuit breaker state; always `closed` without a `circuit_breaker`.
+    pub circuit: CircuitState,
+    /// Seconds since the Unix epoch of the next scheduled restart, while waiting for one.
+    pub next_attempt_at: Option<u64>,
+}
+
+/// Events buffered per subscriber before the slowest one starts missing them.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Something that happened to a supervised process.
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonEvent {
+    /// Server name.
+    pub name: String,
+    /// What happened.
+    #[serde(flatten)]
+    pub kind: DaemonEventKind,
+}
+
+/// Stream a log line was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    /// Standard output.
+    Stdout,
+    /// Standard error.
+    Stderr,
+}
+
+/// The payload of a [`DaemonEvent`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum DaemonEventKind {
+    /// A process was spawned.
+    Started {
+        /// OS process id.
+        pid: Option<u32>,
+        /// Total number of restarts so far.
+        restarts: u32,
+    },
+    /// A process met its `ready_when`, or was spawned if it has none.
+    Ready,
+    /// A process exited on its own.
+    Exited {
+        /// Exit code, if it exited normally.
+        code: Option<i32>,
+        /// Consecutive failed starts, including this one.
+        retries: u32,
+        /// Configured `max_retries`.
+        max_retries: u32,
+    },
+    /// A restart was scheduled.
+    Backoff {
+        /// Consecutive failed starts so far.
+        retries: u32,
+        /// Configured `max_retries`.
+        max_retries: u32,
+        /// Milliseconds until the restart.
+        delay_ms: u64,
+    },
+    /// The circuit breaker changed state.
+    Circuit {
+        /// The new state.
+        state: CircuitState,
+    },
+    /// A process was stopped on request.
+    Stopped,
+    /// A health check failed `failure_threshold` times in a row.
+    Unhealthy {
+        /// Consecutive failed probes.
+        failures: u32,
+        /// Error of the last probe.
+        error: String,
+    },
+    /// The supervisor gave up after `max_retries`.
+    Failed {
+        /// Consecutive failed starts.
+        retries: u32,
+    },
+    /// A process wrote a line of output.
+    Log {
+        /// Milliseconds since the Unix epoch when the line was read.
+        timestamp_ms: u64,
+        /// Where the line was written.
+        stream: LogStream,
+        /// The line, without its terminator.
+        line: String,
+    },
+}
+
+impl DaemonEventKind {
+    /// Lowercase name of the event, e.g. `backoff`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DaemonEventKind::Started { .. } => "started",
+            DaemonEventKind::Ready => "ready",
+            DaemonEventKind::Exited { .. } => "exited",
+            DaemonEventKind::Backoff { .. } => "backoff",
+            DaemonEventKind::Circuit { .. } => "circuit",
+            DaemonEventKind::Stopped => "stopped",
+            DaemonEventKind::Unhealthy { .. } => "unhealthy",
+            DaemonEventKind::Failed { .. } => "failed",
+            DaemonEventKind::Log { .. } => "log",
+        }
+    }
+}
+
+/// Error controlling a daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorError {
+    /// No server with this name is configured.
+    UnknownDaemon(String),
+    /// The server is external and cannot be controlled.
+    External(String),
+    /// The server has no running process.
+    NotRunning(String),
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupervisorError::UnknownDaemon(name) => write!(f, "unknown daemon: {}", name),
+            SupervisorError::External(name) => {
+                write!(f, "{} is an external server and cannot be controlled", name)
+            }
+            SupervisorError::NotRunning(name) => write!(f, "{} is not running", name),
+        }
+    }
+}
+
+impl std::error::Error for SupervisorError {}
+
+impl actix_web::ResponseError for SupervisorError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            SupervisorError::UnknownDaemon(_) => actix_web::http::StatusCode::NOT_FOUND,
+            SupervisorError::External(_) => actix_web::http::StatusCode::CONFLICT,
+            SupervisorError::NotRunning(_) => actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Start,
+    Stop,
+    Restart,
+    Shutdown,
+}
+
+/// Result of [`Supervisor::reload`], each list ordered by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReloadSummary {
+    /// Servers that were not configured before and have been started.
+    pub added: Vec<String>,
+    /// Servers that are no longer configured and have been stopped.
+    pub removed: Vec<String>,
+    /// Servers whose configuration changed and that have been restarted with it.
+    pub changed: Vec<String>,
+}
+
+impl ReloadSummary {
+    /// Whether the reload left every process untouched.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+struct Daemon {
+    config: ProcessConfig,
+    options: ServerOptions,
+    status: Arc<Mutex<DaemonStatus>>,
+    logs: Arc<Mutex<ProcessLog>>,
+    client: Arc<Mutex<Option<Arc<StdioClient>>>>,
+    control: mpsc::UnboundedSender<Control>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<Control>>>,
+    task: Mutex<Option<JoinHandle<bool>>>,
+}
+
+impl Daemon {
+    fn new(name: &str, config: ProcessConfig, options: ServerOptions) -> Self {
+        let (control, receiver) = mpsc::unbounded_channel();
+        let status = DaemonStatus {
+            name: name.to_string(),
+            state: if config.external_address.is_some() {
+                DaemonState::External
+            } else {
+                DaemonState::Stopped
+            },
+            ready: config.external_address.is_some(),
+            pid: None,
+            started_at: None,
+            uptime_secs: None,
+            retries: 0,
+            max_retries: config.max_retries,
+            restarts: 0,
+            last_exit_code: None,
+            command: config.command.clone(),
+            external_address: config.external_address.clone(),
+            health: options
+                .health_check
+                .as_ref()
+                .map(|_| HealthStatus::default()),
+            circuit: CircuitState::Closed,
+            next_attempt_at: None,
+        };
+        Self {
+            logs: Arc::new(Mutex::new(ProcessLog::new(&options.logs))),
+            client: Arc::default(),
+            config,
+            options,
+            status: Arc::new(Mutex::new(status)),
+            control,
+            receiver: Mutex::new(Some(receiver)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Spawns the supervision task, unless already supervised. External servers are ready
+    /// right away and only get one if they have a health check to run.
+    fn launch(
+        &self,
+        name: &str,
+        events: &broadcast::Sender<DaemonEvent>,
+        ready: &Arc<watch::Sender<BTreeSet<String>>>,
+    ) {
+        let external = self.config.external_address.clone();
+        if external.is_some() {
+            ready.send_if_modified(|names| names.insert(name.to_string()));
+            if self.options.health_check.is_none() {
+                return;
+            }
+        }
+        let Some(receiver) = lock(&self.receiver).take() else {
+            return;
+        };
+        let monitor = Monitor {
+            name: name.to_string(),
+            status: self.status.clone(),
+            logs: self.logs.clone(),
+            client: self.client.clone(),
+            events: events.clone(),
+            ready: ready.clone(),
+        };
+        let task = match (external, self.options.health_check.clone()) {
+            (Some(address), Some(check)) => {
+                tokio::spawn(logging::with_process(name.to_string(), None, async move {
+                    watch_external(monitor, address, check, receiver).await;
+                    true
+                }))
+            }
+            _ => tokio::spawn(logging::with_process(
+                name.to_string(),
+                None,
+                supervise(monitor, self.config.clone(), self.options.clone(), receiver),
+            )),
+        };
+        *lock(&self.task) = Some(task);
+    }
+
+    /// Asks the supervision task to stop its process, without waiting.
+    fn request_shutdown(&self) {
+        let _ = self.control.send(Control::Shutdown);
+    }
+
+    /// Waits for the supervision task to finish, returning whether the process stopped within
+    /// its grace period.
+    async fn join(&self) -> bool {
+        let task = lock(&self.task).take();
+        match task {
+            Some(task) => task.await.unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+/// Spawns, restarts and reports on the configured processes.
+pub struct Supervisor {
+    daemons: RwLock<BTreeMap<String, Arc<Daemon>>>,
+    events: broadcast::Sender<DaemonEvent>,
+    ready: Arc<watch::Sender<BTreeSet<String>>>,
+    started: AtomicBool,
+}
+
+impl Supervisor {
+    /// Creates a supervisor for `servers`; nothing is spawned until [`Supervisor::start_all`].
+    pub fn new(servers: HashMap<String, ProcessConfig>) -> Self {
+        let daemons = servers
+            .into_iter()
+            .map(|(name, config)| {
+                let daemon = Arc::new(Daemon::new(&name, config, ServerOptions::default()));
+                (name, daemon)
+            })
+            .collect();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            daemons: RwLock::new(daemons),
+            events,
+            ready: Arc::new(watch::Sender::new(BTreeSet::new())),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Applies the per-server `options` (keyed by server name); call before
+    /// [`Supervisor::start_all`].
+    pub fn with_options(mut self, options: &HashMap<String, ServerOptions>) -> Self {
+        let daemons = self.daemons.get_mut().unwrap_or_else(|e| e.into_inner());
+        for (name, daemon) in daemons.iter_mut() {
+            let options = options.get(name).cloned().unwrap_or_default();
+            *daemon = Arc::new(Daemon::new(name, daemon.config.clone(), options));
+        }
+        self
+    }
+
+    /// Starts supervising every process.
+    pub async fn start_all(&self) -> std::io::Result<()> {
+        self.started.store(true, Ordering::SeqCst);
+        for (name, daemon) in self.daemons().iter() {
+            daemon.launch(name, &self.events, &self.ready);
+        }
+        Ok(())
+    }
+
+    /// Stops every process and waits for the supervision tasks to finish. Each process gets
+    /// `SIGTERM` and is killed if it is still running after its `stop_grace_ms`; returns
+    /// whether all of them exited in time.
+    pub async fn stop_all(&self) -> bool {
+        self.started.store(false, Ordering::SeqCst);
+        let daemons: Vec<Arc<Daemon>> = self.daemons().values().cloned().collect();
+        for daemon in &daemons {
+            daemon.request_shutdown();
+        }
+        let mut graceful = true;
+        for daemon in &daemons {
+            graceful &= daemon.join().await;
+        }
+        graceful
+    }
+
+    /// Replaces the configured servers with `servers` (and their `options`), touching only the
+    /// processes whose definition was added, removed or changed.
+    ///
+    /// Removed and changed processes are stopped first; added and changed ones are then
+    /// started if the supervisor is running.
+    pub async fn reload(
+        &self,
+        servers: HashMap<String, ProcessConfig>,
+        options: &HashMap<String, ServerOptions>,
+    ) -> ReloadSummary {
+        let mut summary = ReloadSummary::default();
+        let mut retired = Vec::new();
+        let mut fresh = Vec::new();
+        {
+            let mut daemons = write(&self.daemons);
+            let gone: Vec<String> = daemons
+                .keys()
+                .filter(|name| !servers.contains_key(*name))
+                .cloned()
+                .collect();
+            for name in gone {
+                retired.extend(daemons.remove(&name));
+                summary.removed.push(name);
+            }
+            for (name, config) in servers {
+                let options = options.get(&name).cloned().unwrap_or_default();
+                match daemons.get(&name) {
+                    Some(daemon)
+                        if same_config(&daemon.config, &config) && daemon.options == options =>
+                    {
+                        continue
+                    }
+                    Some(_) => summary.changed.push(name.clone()),
+                    None => summary.added.push(name.clone()),
+                }
+                let daemon = Arc::new(Daemon::new(&name, config, options));
+                retired.extend(daemons.insert(name.clone(), daemon.clone()));
+                fresh.push((name, daemon));
+            }
+        }
+        summary.added.sort();
+        summary.changed.sort();
+
+        for daemon in &retired {
+            daemon.request_shutdown();
+        }
+        for daemon in &retired {
+            daemon.join().await;
+            let name = lock(&daemon.status).name.clone();
+            self.ready.send_if_modified(|names| names.remove(&name));
+        }
+        if self.started.load(Ordering::SeqCst) {
+            for (name, daemon) in &fresh {
+                daemon.launch(name, &self.events, &self.ready);
+            }
+        }
+        summary
+    }
+
+    /// Status of every configured process, ordered by name.
+    pub fn list(&self) -> Vec<DaemonStatus> {
+        self.daemons()
+            .values()
+            .map(|daemon| snapshot(&daemon.status))
+            .collect()
+    }
+
+    /// Status of the process `name`.
+    pub fn status(&self, name: &str) -> Option<DaemonStatus> {
+        self.daemons()
+            .get(name)
+            .map(|daemon| snapshot(&daemon.status))
+    }
+
+    /// The last `tail` lines captured from `name`, oldest first.
+    pub fn logs(&self, name: &str, tail: usize) -> Result<Vec<LogLine>, SupervisorError> {
+        Ok(lock(&self.process_log(name)?).tail(tail))
+    }
+
+    /// The last `tail` lines captured from `name`, and a subscription to the [`DaemonEvent`]s
+    /// published after them, so that following the log neither misses nor repeats a line.
+    pub fn follow_logs(
+        &self,
+        name: &str,
+        tail: usize,
+    ) -> Result<(Vec<LogLine>, broadcast::Receiver<DaemonEvent>), SupervisorError> {
+        let logs = self.process_log(name)?;
+        let logs = lock(&logs);
+        Ok((logs.tail(tail), self.events.subscribe()))
+    }
+
+    /// A JSON-RPC client talking to the running process of `name` over its stdio.
+    ///
+    /// Each process gets a new client; requests still waiting when it exits fail.
+    pub fn client(&self, name: &str) -> Result<Arc<StdioClient>, SupervisorError> {
+        let daemons = self.daemons();
+        let daemon = daemons
+            .get(name)
+            .ok_or_else(|| SupervisorError::UnknownDaemon(name.to_string()))?;
+        if daemon.config.external_address.is_some() {
+            return Err(SupervisorError::External(name.to_string()));
+        }
+        lock(&daemon.client)
+            .clone()
+            .filter(|client| !client.is_closed())
+            .ok_or_else(|| SupervisorError::NotRunning(name.to_string()))
+    }
+
+    fn process_log(&self, name: &str) -> Result<Arc<Mutex<ProcessLog>>, SupervisorError> {
+        let daemons = self.daemons();
+        let daemon = daemons
+            .get(name)
+            .ok_or_else(|| SupervisorError::UnknownDaemon(name.to_string()))?;
+        if daemon.config.external_address.is_some() {
+            return Err(SupervisorError::External(name.to_string()));
+        }
+        Ok(daemon.logs.clone())
+    }
+
+    /// Servers not marked `optional` that are not ready, ordered by name.
+    pub fn unready(&self) -> Vec<String> {
+        let ready = self.ready.borrow();
+        self.daemons()
+            .iter()
+            .filter(|(name, daemon)| !daemon.options.optional && !ready.contains(*name))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Receives every [`DaemonEvent`] published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    /// Kills and respawns `name`, resetting its retry counter.
+    pub fn restart(&self, name: &str) -> Result<(), SupervisorError> {
+        self.send(name, Control::Restart)
+    }
+
+    /// Stops `name` until it is started again.
+    pub fn stop(&self, name: &str) -> Result<(), SupervisorError> {
+        self.send(name, Control::Stop)
+    }
+
+    /// Starts `name` if it is stopped or failed.
+    pub fn start(&self, name: &str) -> Result<(), SupervisorError> {
+        self.send(name, Control::Start)
+    }
+
+    fn send(&self, name: &str, control: Control) -> Result<(), SupervisorError> {
+        let daemons = self.daemons();
+        let daemon = daemons
+            .get(name)
+            .ok_or_else(|| SupervisorError::UnknownDaemon(name.to_string()))?;
+        if daemon.config.external_address.is_some() {
+            return Err(SupervisorError::External(name.to_string()));
+        }
+        let _ = daemon.control.send(control);
+        Ok(())
+    }
+
+    fn daemons(&self) -> RwLockReadGuard<'_, BTreeMap<String, Arc<Daemon>>> {
+        self.daemons.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether two definitions would run the same process.
+fn same_config(a: &ProcessConfig, b: &ProcessConfig) -> bool {
+    a.command == b.command
+        && a.args == b.args
+        && a.external_address == b.external_address
+        && a.max_retries == b.max_retries
+        && a.restart_delay_ms == b.restart_delay_ms
+}
+
+/// Where a supervision task records its process's status and readiness and publishes its
+/// events.
+#[derive(Clone)]
+struct Monitor {
+    name: String,
+    status: Arc<Mutex<DaemonStatus>>,
+    logs: Arc<Mutex<ProcessLog>>,
+    client: Arc<Mutex<Option<Arc<StdioClient>>>>,
+    events: broadcast::Sender<DaemonEvent>,
+    ready: Arc<watch::Sender<BTreeSet<String>>>,
+}
+
+impl Monitor {
+    fn update(&self, f: impl FnOnce(&mut DaemonStatus)) {
+        f(&mut lock(&self.status));
+    }
+
+    fn emit(&self, kind: DaemonEventKind) {
+        // Nobody listening is fine.
+        let _ = self.events.send(DaemonEvent {
+            name: self.name.clone(),
+            kind,
+        });
+    }
+
+    fn set_ready(&self, ready: bool) {
+        self.update(|s| s.ready = ready);
+        self.ready.send_if_modified(|names| {
+            if ready {
+                names.insert(self.name.clone())
+            } else {
+                names.remove(&self.name)
+            }
+        });
+    }
+
+    /// Moves the circuit to `state`, publishing the change if there is one.
+    fn set_circuit(&self, state: CircuitState) {
+        let previous = std::mem::replace(&mut lock(&self.status).circuit, state);
+        if previous != state {
+            self.emit(DaemonEventKind::Circuit { state });
+        }
+    }
+}
+
+/// How a supervised process ended.
+enum Outcome {
+    Exited(Option<i32>),
+    Stopped,
+    Restart,
+    /// `graceful` if the process exited within its stop grace period.
+    Shutdown {
+        graceful: bool,
+    },
+}
+
+/// Runs the process of `monitor` until shutdown, restarting it according to `config` and the
+/// backoff and circuit breaker of `options`. Returns whether the process, if it was running at
+/// shutdown, exited within its stop grace period.
+async fn supervise(
+    monitor: Monitor,
+    config: ProcessConfig,
+    options: ServerOptions,
+    mut control: mpsc::UnboundedReceiver<Control>,
+) -> bool {
+    let name = monitor.name.clone();
+    let mut wanted = true;
+    loop {
+        if !wanted {
+            match control.recv().await {
+                Some(Control::Start) | Some(Control::Restart) => {
+                    monitor.update(|s| s.retries = 0);
+                    wanted = true;
+                }
+                Some(Control::Stop) => continue,
+                Some(Control::Shutdown) | None => return true,
+            }
+        }
+
+        match wait_for_dependencies(&monitor, &options.depends_on, &mut control).await {
+            None => {}
+            Some(Control::Stop) => {
+                log::info!("Stopped {}", name);
+                monitor.update(|s| s.state = DaemonState::Stopped);
+                monitor.emit(DaemonEventKind::Stopped);
+                wanted = false;
+                continue;
+            }
+            Some(_) => {
+                monitor.update(|s| s.state = DaemonState::Stopped);
+                return true;
+            }
+        }
+
+        monitor.update(|s| s.state = DaemonState::Starting);
+        // Subscribed before spawning, so that a `log_line` condition sees the first lines.
+        let events = options
+            .ready_when
+            .as_ref()
+            .map(|_| monitor.events.subscribe());
+        let started = Instant::now();
+        let outcome = match spawn(&monitor, &config, &options) {
+            Ok(child) => run(&monitor, child, &options, events, &mut control).await,
+            Err(e) => {
+                log::error!("Failed to spawn {}: {}", name, e);
+                Outcome::Exited(None)
+            }
+        };
+        monitor.set_ready(false);
+        if let Some(client) = lock(&monitor.client).take() {
+            match exceeded_limit(&config, &outcome) {
+                Some(limit) => client.fail(rpc::limit_exceeded(&name, limit, "")),
+                None => client.close(),
+            }
+        }
+        monitor.update(|s| {
+            s.pid = None;
+            s.started_at = None;
+        });
+
+        match outcome {
+            Outcome::Shutdown { graceful } => {
+                monitor.update(|s| s.state = DaemonState::Stopped);
+                monitor.emit(DaemonEventKind::Stopped);
+                return graceful;
+            }
+            Outcome::Stopped => {
+                log::info!("Stopped {}", name);
+                monitor.update(|s| s.state = DaemonState::Stopped);
+                monitor.set_circuit(CircuitState::Closed);
+                monitor.emit(DaemonEventKind::Stopped);
+                wanted = false;
+            }
+            Outcome::Restart => {
+                log::info!("Restarting {} on request", name);
+                monitor.update(|s| {
+                    s.retries = 0;
+                    s.restarts += 1;
+                });
+                monitor.set_circuit(CircuitState::Closed);
+            }
+            Outcome::Exited(code) => {
+                let retries = {
+                    let mut s = lock(&monitor.status);
+                    s.last_exit_code = code;
+                    if started.elapsed() >= STABLE_UPTIME {
+                        s.retries = 0;
+                    }
+                    s.retries += 1;
+                    s.retries
+                };
+                monitor.emit(DaemonEventKind::Exited {
+                    code,
+                    retries,
+                    max_retries: config.max_retries,
+                });
+                let exhausted = retries > config.max_retries;
+                let delay = if exhausted {
+                    let Some(breaker) = &options.circuit_breaker else {
+                        log::error!(
+                            "{} exited with {:?}; giving up after {} retries",
+                            name,
+                            code,
+                            config.max_retries
+                        );
+                        monitor.update(|s| s.state = DaemonState::Failed);
+                        monitor.emit(DaemonEventKind::Failed { retries });
+                        wanted = false;
+                        continue;
+                    };
+                    let cool_down = Duration::from_millis(breaker.cool_down_ms);
+                    log::error!(
+                        "{} exited with {:?} after {} retries; opening its circuit for {:?}",
+                        name,
+                        code,
+                        config.max_retries,
+                        cool_down
+                    );
+                    monitor.update(|s| s.state = DaemonState::Failed);
+                    monitor.emit(DaemonEventKind::Failed { retries });
+                    monitor.set_circuit(CircuitState::Open);
+                    cool_down
+                } else {
+                    let delay = options.backoff.delay(config.restart_delay_ms, retries);
+                    log::warn!(
+                        "{} exited with {:?}; restarting in {:?} (attempt {}/{})",
+                        name,
+                        code,
+                        delay,
+                        retries,
+                        config.max_retries
+                    );
+                    monitor.update(|s| s.state = DaemonState::Backoff);
+                    monitor.emit(DaemonEventKind::Backoff {
+                        retries,
+                        max_retries: config.max_retries,
+                        delay_ms: delay.as_millis() as u64,
+                    });
+                    delay
+                };
+
+                monitor.update(|s| s.next_attempt_at = Some(unix_now() + delay.as_secs()));
+                let request = tokio::select! {
+                    _ = tokio::time::sleep(delay) => None,
+                    request = control.recv() => Some(request),
+                };
+                monitor.update(|s| s.next_attempt_at = None);
+                match request {
+                    None if exhausted => {
+                        log::info!("Cool-down of {} is over; trying it once more", name);
+                        monitor.set_circuit(CircuitState::HalfOpen);
+                    }
+                    None => {}
+                    Some(Some(Control::Stop)) => {
+                        monitor.update(|s| s.state = DaemonState::Stopped);
+                        monitor.set_circuit(CircuitState::Closed);
+                        monitor.emit(DaemonEventKind::Stopped);
+                        wanted = false;
+                        continue;
+                    }
+                    Some(Some(Control::Shutdown)) | Some(None) => {
+                        monitor.update(|s| s.state = DaemonState::Stopped);
+                        return true;
+                    }
+                    Some(Some(Control::Start)) | Some(Some(Control::Restart)) => {
+                        monitor.update(|s| s.retries = 0);
+                        monitor.set_circuit(CircuitState::Closed);
+                    }
+                }
+                monitor.update(|s| s.restarts += 1);
+            }
+        }
+    }
+}
+
+/// The limit a `wasm_run` server ran into, if it exited with that limit's exit code.
+fn exceeded_limit(config: &ProcessConfig, outcome: &Outcome) -> Option<LimitKind> {
+    let wasm_run = config
+        .args
+        .as_ref()
+        .and_then(|args| args.first())
+        .is_some_and(|arg| arg == "wasm_run");
+    match outcome {
+        Outcome::Exited(Some(code)) if wasm_run => LimitKind::from_exit_code(*code),
+        _ => None,
+    }
+}
+
+/// Waits until every server in `dependencies` is ready. Returns early with a `Stop` or
+/// `Shutdown` request; start and restart requests keep waiting.
+async fn wait_for_dependencies(
+    monitor: &Monitor,
+    dependencies: &[String],
+    control: &mut mpsc::UnboundedReceiver<Control>,
+) -> Option<Control> {
+    let mut ready = monitor.ready.subscribe();
+    let unmet: Vec<&str> = {
+        let names = ready.borrow();
+        dependencies
+            .iter()
+            .filter(|dependency| !names.contains(*dependency))
+            .map(String::as_str)
+            .collect()
+    };
+    if unmet.is_empty() {
+        return None;
+    }
+    log::info!(
+        "{} waits for {} to be ready",
+        monitor.name,
+        unmet.join(", ")
+    );
+    monitor.update(|s| s.state = DaemonState::Waiting);
+    loop {
+        let request = tokio::select! {
+            _ = ready.wait_for(|names| dependencies.iter().all(|d| names.contains(d))) => {
+                return None;
+            }
+            request = control.recv() => request,
+        };
+        match request {
+            Some(Control::Start) | Some(Control::Restart) => continue,
+            Some(request) => return Some(request),
+            None => return Some(Control::Shutdown),
+        }
+    }
+}
+
+/// Waits for `child` to exit, to fail its health check or for a control request that ends it,
+/// marking it ready once it meets its `ready_when` (read from `events`).
+///
+/// A process killed for failing its health check is reported as having exited, so that it
+/// goes through the same restart logic. One that stays up for [`STABLE_UPTIME`] closes a
+/// half-open circuit.
+async fn run(
+    monitor: &Monitor,
+    mut child: Child,
+    options: &ServerOptions,
+    events: Option<broadcast::Receiver<DaemonEvent>>,
+    control: &mut mpsc::UnboundedReceiver<Control>,
+) -> Outcome {
+    let pid = child.id();
+    log::info!("Started {} (pid {})", monitor.name, pid.unwrap_or_default());
+    let restarts = {
+        let mut s = lock(&monitor.status);
+        s.state = DaemonState::Running;
+        s.pid = pid;
+        s.started_at = Some(unix_now());
+        if s.health.is_some() {
+            s.health = Some(HealthStatus::default());
+        }
+        s.restarts
+    };
+    monitor.emit(DaemonEventKind::Started { pid, restarts });
+
+    let health = check_health(monitor, options.health_check.as_ref(), None);
+    tokio::pin!(health);
+    let stable = tokio::time::sleep(STABLE_UPTIME);
+    tokio::pin!(stable);
+    let mut settled = false;
+    let ready = async {
+        if let (Some(ready_when), Some(events)) = (&options.ready_when, events) {
+            ready_when.wait(&monitor.name, events).await;
+        }
+    };
+    tokio::pin!(ready);
+    let mut is_ready = false;
+    let outcome = loop {
+        let request = tokio::select! {
+            exit = child.wait() => return Outcome::Exited(exit.ok().and_then(|s| s.code())),
+            _ = &mut ready, if !is_ready => {
+                is_ready = true;
+                log::info!("{} is ready", monitor.name);
+                monitor.set_ready(true);
+                monitor.emit(DaemonEventKind::Ready);
+                continue;
+            }
+            _ = &mut stable, if !settled => {
+                settled = true;
+                if lock(&monitor.status).circuit == CircuitState::HalfOpen {
+                    log::info!("{} recovered; closing its circuit", monitor.name);
+                    monitor.update(|s| s.retries = 0);
+                    monitor.set_circuit(CircuitState::Closed);
+                }
+                continue;
+            }
+            (failures, error) = &mut health => {
+                log::warn!(
+                    "{} failed {} health checks in a row ({}); killing it",
+                    monitor.name,
+                    failures,
+                    error
+                );
+                monitor.emit(DaemonEventKind::Unhealthy { failures, error });
+                break Outcome::Exited(None);
+            }
+            request = control.recv() => request,
+        };
+        match request {
+            Some(Control::Start) => continue,
+            Some(Control::Stop) => break Outcome::Stopped,
+            Some(Control::Restart) => break Outcome::Restart,
+            Some(Control::Shutdown) | None => break Outcome::Shutdown { graceful: true },
+        }
+    };
+    let grace = options
+        .stop_grace_ms
+        .map_or(process::DEFAULT_STOP_GRACE, Duration::from_millis);
+    let graceful = process::terminate(&mut child, grace).await;
+    if !graceful {
+        log::warn!(
+            "{} did not exit within {:?}; killed it",
+            monitor.name,
+            grace
+        );
+    }
+    match outcome {
+        Outcome::Shutdown { .. } => Outcome::Shutdown { graceful },
+        outcome => outcome,
+    }
+}
+
+/// Probes an `external_address` server with `check` until shutdown, reporting the results in
+/// its status; external servers cannot be restarted.
+async fn watch_external(
+    monitor: Monitor,
+    address: String,
+    check: HealthCheck,
+    mut control: mpsc::UnboundedReceiver<Control>,
+) {
+    loop {
+        tokio::select! {
+            (failures, error) = check_health(&monitor, Some(&check), Some(&address)) => {
+                log::warn!(
+                    "External server {} failed {} health checks in a row ({})",
+                    monitor.name,
+                    failures,
+                    error
+                );
+                monitor.emit(DaemonEventKind::Unhealthy { failures, error });
+            }
+            request = control.recv() => {
+                if matches!(request, Some(Control::Shutdown) | None) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Probes with `check` every interval, recording the results, and resolves with the failure
+/// count and last error once `failure_threshold` consecutive probes have failed. Never
+/// resolves without a check.
+async fn check_health(
+    monitor: &Monitor,
+    check: Option<&HealthCheck>,
+    external_address: Option<&str>,
+) -> (u32, String) {
+    let Some(check) = check else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(Duration::from_millis(check.interval_ms)).await;
+        let result = check.probe(external_address).await;
+        let mut failures = 0;
+        monitor.update(|s| {
+            failures = s
+                .health
+                .get_or_insert_with(HealthStatus::default)
+                .record(&result, unix_now());
+        });
+        if let Err(error) = result {
+            log::debug!("Health check of {} failed: {}", monitor.name, error);
+            if check.reached_threshold(failures) {
+                return (failures, error);
+            }
+        }
+    }
+}
+
+fn spawn(
+    monitor: &Monitor,
+    config: &ProcessConfig,
+    options: &ServerOptions,
+) -> std::io::Result<Child> {
+    let program = config.command.as_deref().unwrap_or(&monitor.name);
+    let mut command = Command::new(program);
+    command
+        .args(config.args.iter().flatten())
+        // Kept open for as long as the process runs: servers speaking over stdio exit on EOF.
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    process::apply(&mut command, options)?;
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    // Dropping the child on error kills it.
+    if let Some(pid) = pid {
+        process::attach(pid, options)?;
+    }
+    let client = child
+        .stdin
+        .take()
+        .map(|stdin| Arc::new(StdioClient::new(monitor.name.clone(), stdin)));
+    *lock(&monitor.client) = client.clone();
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(logging::with_process(
+            monitor.name.clone(),
+            pid,
+            forward_lines(monitor.clone(), stdout, LogStream::Stdout, client),
+        ));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(logging::with_process(
+            monitor.name.clone(),
+            pid,
+            forward_lines(monitor.clone(), stderr, LogStream::Stderr, None),
+        ));
+    }
+    Ok(child)
+}
+
+/// Logs, captures and publishes every line of `stream`, tagged with the process name. Lines
+/// that `client` takes as JSON-RPC messages are only logged at debug level. The client is left
+/// to `supervise` to close, once the exit status tells why the process is gone.
+async fn forward_lines(
+    monitor: Monitor,
+    stream: impl AsyncRead + Unpin,
+    source: LogStream,
+    client: Option<Arc<StdioClient>>,
+) {
+    let level = match source {
+        LogStream::Stdout => log::Level::Info,
+        LogStream::Stderr => log::Level::Warn,
+    };
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(client) = &client {
+            if client.dispatch(&line) {
+                log::debug!("[{}] {}", monitor.name, line);
+                continue;
+            }
+        }
+        log::log!(level, "[{}] {}", monitor.name, line);
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        // Published under the lock, so that `Supervisor::follow_logs` sees each line once.
+        let mut logs = lock(&monitor.logs);
+        logs.push(LogLine {
+            timestamp_ms,
+            stream: source,
+            line: line.clone(),
+        });
+        monitor.emit(DaemonEventKind::Log {
+            timestamp_ms,
+            stream: source,
+            line,
+        });
+    }
+}
+
+fn snapshot(status: &Mutex<DaemonStatus>) -> DaemonStatus {
+    let mut status = lock(status).clone();
+    status.uptime_secs = status
+        .started_at
+        .map(|started| unix_now().saturating_sub(started));
+    status
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn external(address: &str) -> ProcessConfig {
+        ProcessConfig {
+            command: None,
+            args: None,
+            external_address: Some(address.to_string()),
+            max_retries: 0,
+            restart_delay_ms: 0,
+        }
+    }
+
+    fn command(command: &str, args: &[&str]) -> ProcessConfig {
+        ProcessConfig {
+            command: Some(command.to_string()),
+            args: Some(args.iter().map(|a| a.to_string()).collect()),
+            external_address: None,
+            max_retries: 0,
+            restart_delay_ms: 0,
+        }
+    }
+
+    fn names(supervisor: &Supervisor) -> Vec<String> {
+        supervisor.list().into_iter().map(|s| s.name).collect()
+    }
+
+    /// Polls the status of `name` until `done` holds for it.
+    async fn wait_for(
+        supervisor: &Supervisor,
+        name: &str,
+        done: impl Fn(&DaemonStatus) -> bool,
+    ) -> DaemonStatus {
+        let poll = async {
+            loop {
+                if let Some(status) = supervisor.status(name).filter(|s| done(s)) {
+                    return status;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), poll)
+            .await
+            .unwrap()
+    }
+
+    /// Names of the events of `name` up to and including the first `last` one, leaving out
+    /// `ready` and `log`, which may come before or after an immediate exit.
+    async fn events_until(
+        events: &mut broadcast::Receiver<DaemonEvent>,
+        name: &str,
+        last: &str,
+    ) -> Vec<&'static str> {
+        let mut seen = Vec::new();
+        let receive = async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.name != name
+                    || matches!(
+                        event.kind,
+                        DaemonEventKind::Ready | DaemonEventKind::Log { .. }
+                    )
+                {
+                    continue;
+                }
+                seen.push(event.kind.name());
+                if event.kind.name() == last {
+                    return;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), receive)
+            .await
+            .unwrap();
+        seen
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failing_processes_go_from_waiting_to_failed() {
+        let options = HashMap::from([(
+            "cdd-go".to_string(),
+            ServerOptions {
+                depends_on: vec!["cdd-dep".to_string()],
+                ..ServerOptions::default()
+            },
+        )]);
+        let mut failing = command("sh", &["-c", "exit 3"]);
+        failing.max_retries = 1;
+        failing.restart_delay_ms = 10;
+        let supervisor = Supervisor::new(HashMap::from([("cdd-go".to_string(), failing.clone())]))
+            .with_options(&options);
+        let mut events = supervisor.subscribe();
+        supervisor.start_all().await.unwrap();
+        wait_for(&supervisor, "cdd-go", |s| s.state == DaemonState::Waiting).await;
+
+        // The dependency is ready as soon as it runs, which lets `cdd-go` start.
+        let servers = HashMap::from([
+            ("cdd-go".to_string(), failing),
+            ("cdd-dep".to_string(), command("sleep", &["30"])),
+        ]);
+        supervisor.reload(servers, &options).await;
+        assert_eq!(
+            events_until(&mut events, "cdd-go", "failed").await,
+            ["started", "exited", "backoff", "started", "exited", "failed"]
+        );
+        let status = supervisor.status("cdd-go").unwrap();
+        assert_eq!(status.state, DaemonState::Failed);
+        assert_eq!(status.retries, 2);
+        assert_eq!(status.restarts, 1);
+        assert_eq!(status.last_exit_code, Some(3));
+        assert!(!status.ready);
+        assert_eq!(supervisor.unready(), ["cdd-go"]);
+        assert!(supervisor.stop_all().await);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn processes_are_restarted_stopped_and_started_on_request() {
+        let supervisor = Supervisor::new(HashMap::from([(
+            "cdd-go".to_string(),
+            command("sleep", &["30"]),
+        )]));
+        supervisor.start_all().await.unwrap();
+        let running = |s: &DaemonStatus| s.state == DaemonState::Running && s.pid.is_some();
+        let first = wait_for(&supervisor, "cdd-go", running).await;
+        assert!(supervisor.client("cdd-go").is_ok());
+
+        supervisor.restart("cdd-go").unwrap();
+        let restarted = wait_for(&supervisor, "cdd-go", |s| s.restarts == 1 && running(s)).await;
+        assert_ne!(restarted.pid, first.pid);
+        assert_eq!(restarted.retries, 0);
+
+        supervisor.stop("cdd-go").unwrap();
+        let stopped = wait_for(&supervisor, "cdd-go", |s| s.state == DaemonState::Stopped).await;
+        assert_eq!(stopped.pid, None);
+        assert_eq!(
+            supervisor.client("cdd-go").err(),
+            Some(SupervisorError::NotRunning("cdd-go".to_string()))
+        );
+
+        supervisor.start("cdd-go").unwrap();
+        wait_for(&supervisor, "cdd-go", running).await;
+        assert!(supervisor.stop_all().await);
+        assert_eq!(
+            supervisor.status("cdd-go").unwrap().state,
+            DaemonState::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn external_servers_are_ready_and_cannot_be_controlled() {
+        let supervisor = Supervisor::new(HashMap::from([(
+            "cdd-go".to_string(),
+            external("127.0.0.1:9001"),
+        )]));
+        supervisor.start_all().await.unwrap();
+        let status = supervisor.status("cdd-go").unwrap();
+        assert_eq!(status.state, DaemonState::External);
+        assert!(status.ready);
+        assert!(supervisor.unready().is_empty());
+
+        let external = Err(SupervisorError::External("cdd-go".to_string()));
+        assert_eq!(supervisor.restart("cdd-go"), external);
+        assert_eq!(supervisor.stop("cdd-go"), external);
+        assert_eq!(supervisor.start("cdd-go"), external);
+        assert_eq!(supervisor.client("cdd-go").err(), external.clone().err());
+        assert_eq!(supervisor.logs("cdd-go", 10), external.map(|()| Vec::new()));
+        assert_eq!(
+            supervisor.restart("cdd-rust"),
+            Err(SupervisorError::UnknownDaemon("cdd-rust".to_string()))
+        );
+        assert!(supervisor.stop_all().await);
+    }
+
+    #[test]
+    fn only_wasm_run_exit_codes_name_a_limit() {
+        let wasm = command(
+            "cdd-ctl-wasm",
+            &["wasm_run", "cdd-go", "--", "serve_json_rpc"],
+        );
+        let fuel = Outcome::Exited(Some(LimitKind::Fuel.exit_code()));
+        assert_eq!(exceeded_limit(&wasm, &fuel), Some(LimitKind::Fuel));
+        assert_eq!(exceeded_limit(&wasm, &Outcome::Exited(Some(1))), None);
+        assert_eq!(exceeded_limit(&wasm, &Outcome::Restart), None);
+        let native = command("cdd-go", &["serve_json_rpc"]);
+        assert_eq!(exceeded_limit(&native, &fuel), None);
+    }
+
+    #[tokio::test]
+    async fn reload_reports_added_removed_and_changed_servers() {
+        let supervisor = Supervisor::new(HashMap::from([
+            ("cdd-go".to_string(), external("127.0.0.1:9001")),
+            ("cdd-rust".to_string(), external("127.0.0.1:9002")),
+            ("cdd-ts".to_string(), external("127.0.0.1:9003")),
+        ]));
+        let servers = HashMap::from([
+            ("cdd-go".to_string(), external("127.0.0.1:9001")),
+            ("cdd-rust".to_string(), external("127.0.0.1:9012")),
+            ("cdd-python".to_string(), external("127.0.0.1:9004")),
+            ("cdd-java".to_string(), external("127.0.0.1:9005")),
+        ]);
+        let summary = supervisor.reload(servers, &HashMap::new()).await;
+        assert_eq!(
+            summary,
+            ReloadSummary {
+                added: vec!["cdd-java".to_string(), "cdd-python".to_string()],
+                removed: vec!["cdd-ts".to_string()],
+                changed: vec!["cdd-rust".to_string()],
+            }
+        );
+        assert_eq!(
+            names(&supervisor),
+            ["cdd-go", "cdd-java", "cdd-python", "cdd-rust"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_restarts_servers_whose_options_changed() {
+        let servers = HashMap::from([("cdd-go".to_string(), external("127.0.0.1:9001"))]);
+        let supervisor = Supervisor::new(servers.clone());
+        assert!(supervisor
+            .reload(servers.clone(), &HashMap::new())
+            .await
+            .is_empty());
+
+        let options = HashMap::from([(
+            "cdd-go".to_string(),
+            ServerOptions {
+                optional: true,
+                ..ServerOptions::default()
+            },
+        )]);
+        let summary = supervisor.reload(servers.clone(), &options).await;
+        assert_eq!(summary.changed, ["cdd-go"]);
+        assert!(supervisor.reload(servers, &options).await.is_empty());
+    }
+}
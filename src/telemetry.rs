@@ -0,0 +1,308 @@
+//! Distributed tracing with OpenTelemetry.
+//!
+//! Spans are recorded with [`tracing`] at every layer a request crosses, and exported according
+//! to the `tracing` section of the [`CtlConfig`](crate::CtlConfig):
+//!
+//! - `http.request`: the gateway request, opened by the [`trace_requests`] middleware, which
+//!   continues the trace of an incoming W3C `traceparent` header;
+//! - `rpc.call` and `mcp.handle`: dispatch of a JSON-RPC or MCP method;
+//! - `toolchain.run`: a one-shot `cdd-<lang>` run, which is given the trace as the `TRACEPARENT`
+//!   environment variable;
+//! - `mcp.request`: a request to an MCP backend, which carries the trace as
+//!   `params._meta.traceparent` (and as a `traceparent` header over HTTP).
+//!
+//! Spans go to an OTLP/HTTP collector, or as JSON lines to stdout or a file for offline
+//! debugging.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use futures_util::future::BoxFuture;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{SpanId, TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Layer;
+
+use crate::metrics::UNMATCHED_ROUTE;
+
+/// Name of the W3C trace context header and of the field carrying it in JSON-RPC `_meta`.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Environment variable carrying the trace context into one-shot toolchain runs.
+pub const TRACEPARENT_VAR: &str = "TRACEPARENT";
+
+/// Where spans are exported, configured as `tracing` in the config file:
+///
+/// ```json
+/// "tracing": { "exporter": "otlp", "endpoint": "http://otel-collector:4318" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    /// Destination of the spans.
+    pub exporter: TraceExporter,
+    /// Base URL of the collector's OTLP/HTTP receiver, for `otlp`.
+    pub endpoint: String,
+    /// File the spans are appended to, for `file`.
+    pub file: Option<PathBuf>,
+    /// `service.name` of the spans; the binary's name by default.
+    pub service_name: Option<String>,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            exporter: TraceExporter::default(),
+            endpoint: "http://localhost:4318".to_string(),
+            file: None,
+            service_name: None,
+        }
+    }
+}
+
+/// Destination of the spans.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceExporter {
+    /// No tracing.
+    #[default]
+    None,
+    /// OTLP over HTTP (protobuf) to `endpoint`.
+    Otlp,
+    /// One JSON object per span on stdout.
+    Stdout,
+    /// One JSON object per span, appended to `file`.
+    File,
+}
+
+impl TracingConfig {
+    /// Checks that the exporter has what it needs.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.exporter {
+            TraceExporter::Otlp
+                if !(self.endpoint.starts_with("http://")
+                    || self.endpoint.starts_with("https://")) =>
+            {
+                Err(format!(
+                    "`endpoint` must be an http(s) URL, got {:?}",
+                    self.endpoint
+                ))
+            }
+            TraceExporter::File if self.file.is_none() => {
+                Err("the `file` exporter requires `file`".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Flushes and stops the exporter when dropped; keep it alive until the process exits.
+pub struct TracingGuard {
+    provider: TracerProvider,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            log::warn!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Installs the global [`tracing`] subscriber exporting the spans of `service` as configured,
+/// or does nothing for [`TraceExporter::None`].
+pub fn init(config: &TracingConfig, service: &str) -> Result<Option<TracingGuard>, String> {
+    config.validate()?;
+    let resource = Resource::new([KeyValue::new(
+        "service.name",
+        config
+            .service_name
+            .clone()
+            .unwrap_or_else(|| service.to_string()),
+    )]);
+    let builder = TracerProvider::builder().with_resource(resource);
+    let provider = match config.exporter {
+        TraceExporter::None => return Ok(None),
+        TraceExporter::Otlp => {
+            let endpoint = config.endpoint.trim_end_matches('/');
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{}/v1/traces", endpoint))
+                .build()
+                .map_err(|e| format!("cannot export to {}: {}", endpoint, e))?;
+            // The gateway runs on a single-threaded runtime, which the exporter must not block.
+            builder
+                .with_batch_exporter(exporter, runtime::TokioCurrentThread)
+                .build()
+        }
+        TraceExporter::Stdout => builder
+            .with_simple_exporter(JsonLines::new(io::stdout()))
+            .build(),
+        TraceExporter::File => {
+            let path = config.file.as_ref().ok_or("missing `file`")?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+            builder.with_simple_exporter(JsonLines::new(file)).build()
+        }
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        .with_filter(Targets::new().with_target("cdd_ctl", tracing::Level::INFO));
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .map_err(|e| e.to_string())?;
+    Ok(Some(TracingGuard { provider }))
+}
+
+/// The W3C `traceparent` of the current span, if a trace is being recorded.
+pub fn traceparent() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let mut carrier: HashMap<String, String> = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+    carrier.remove(TRACEPARENT)
+}
+
+/// Adds the current [`traceparent`] to the `_meta` of JSON-RPC `params`, if they are an object
+/// or absent.
+pub fn inject(params: &mut Value) {
+    let Some(traceparent) = traceparent() else {
+        return;
+    };
+    if params.is_null() {
+        *params = Value::Object(Map::new());
+    }
+    if let Some(params) = params.as_object_mut() {
+        let meta = params
+            .entry("_meta")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Some(meta) = meta.as_object_mut() {
+            meta.insert(TRACEPARENT.to_string(), Value::String(traceparent));
+        }
+    }
+}
+
+/// Middleware opening the `http.request` span of every request, as a child of the trace in
+/// its `traceparent` header if there is one. Register it outermost with
+/// `.wrap(actix_web::middleware::from_fn(telemetry::trace_requests))`.
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&Headers(req.headers())));
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let span = tracing::info_span!(
+        "http.request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        http.route = %route,
+        http.response.status_code = tracing::field::Empty,
+    );
+    span.set_parent(parent);
+    let result = next.call(req).instrument(span.clone()).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.error_response().status(),
+    };
+    span.record("http.response.status_code", status.as_u16());
+    result
+}
+
+struct Headers<'a>(&'a HeaderMap);
+
+impl Extractor for Headers<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Writes every finished span as a line of JSON.
+struct JsonLines {
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl JsonLines {
+    fn new(writer: impl Write + Send + Sync + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+        }
+    }
+
+    fn write(&mut self, span: &SpanData) -> io::Result<()> {
+        let context = &span.span_context;
+        let attributes: Map<String, Value> = span
+            .attributes
+            .iter()
+            .map(|kv| {
+                (
+                    kv.key.as_str().to_string(),
+                    Value::String(kv.value.as_str().into()),
+                )
+            })
+            .collect();
+        let line = json!({
+            "name": span.name,
+            "trace_id": context.trace_id().to_string(),
+            "span_id": context.span_id().to_string(),
+            "parent_span_id": (span.parent_span_id != SpanId::INVALID)
+                .then(|| span.parent_span_id.to_string()),
+            "start_time_unix_nano": unix_nanos(span.start_time),
+            "end_time_unix_nano": unix_nanos(span.end_time),
+            "attributes": attributes,
+        });
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()
+    }
+}
+
+impl fmt::Debug for JsonLines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JsonLines")
+    }
+}
+
+impl SpanExporter for JsonLines {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = batch
+            .iter()
+            .try_for_each(|span| self.write(span))
+            .map_err(|e| TraceError::Other(Box::new(e)));
+        Box::pin(std::future::ready(result))
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
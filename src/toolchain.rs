@@ -0,0 +1,323 @@
+//! Registry of the `cdd-*` language toolchains orchestrated by `cdd-ctl`.
+//!
+//! Every binary used to carry its own hard-coded list of languages; this module is now the
+//! single source of truth for which toolchains exist, which runtimes they can execute under
+//! and how their supervised servers are launched.
+
+use std::collections::HashMap;
+
+use crate::executor::wasm_runner;
+use crate::wasm::WasmLimitsConfig;
+use crate::ProcessConfig;
+
+/// Directory (relative to the working directory) holding the `.wasm` builds of each toolchain.
+pub const WASM_ASSETS_DIR: &str = "cdd-ctl-wasm-sdk/assets/wasm";
+
+/// Generation targets offered by every `from_openapi` implementation.
+const STANDARD_TARGETS: &[&str] = &["to_sdk", "to_sdk_cli", "to_server"];
+
+/// A runtime a toolchain can be executed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Runtime {
+    /// A native `cdd-<lang>` executable found on `PATH`.
+    Native,
+    /// A `cdd-<lang>.wasm` module evaluated by the embedded `wasmtime` engine.
+    Wasm,
+    /// A remote server reached through `external_address`.
+    External,
+}
+
+/// The long-running mode a supervised toolchain server is started in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServeMode {
+    /// Model Context Protocol over stdio (`cdd-<lang> mcp`).
+    Mcp,
+    /// JSON-RPC 2.0 server (`cdd-<lang> serve_json_rpc`).
+    JsonRpc,
+}
+
+impl ServeMode {
+    /// The subcommand passed to the toolchain to start it in this mode.
+    pub fn subcommand(self) -> &'static str {
+        match self {
+            ServeMode::Mcp => "mcp",
+            ServeMode::JsonRpc => "serve_json_rpc",
+        }
+    }
+}
+
+/// Static description of a single `cdd-*` toolchain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    /// Executable / module name, e.g. `cdd-go`.
+    pub name: &'static str,
+    /// Runtimes this toolchain can be executed under.
+    pub runtimes: &'static [Runtime],
+    /// WebAssembly proposals the `.wasm` build requires (e.g. `gc` for Kotlin).
+    pub wasm_features: &'static [&'static str],
+    /// Arguments used when starting the toolchain as an MCP server.
+    pub mcp_args: &'static [&'static str],
+    /// Arguments used when starting the toolchain as a JSON-RPC server.
+    pub rpc_args: &'static [&'static str],
+    /// Generation targets accepted by `from_openapi`.
+    pub targets: &'static [&'static str],
+}
+
+impl Toolchain {
+    /// Language identifier without the `cdd-` prefix, e.g. `go`.
+    pub fn language(&self) -> &'static str {
+        self.name.strip_prefix("cdd-").unwrap_or(self.name)
+    }
+
+    /// Whether the toolchain can be executed under `runtime`.
+    pub fn supports(&self, runtime: Runtime) -> bool {
+        self.runtimes.contains(&runtime)
+    }
+
+    /// Default arguments for the given serve mode.
+    pub fn serve_args(&self, mode: ServeMode) -> &'static [&'static str] {
+        match mode {
+            ServeMode::Mcp => self.mcp_args,
+            ServeMode::JsonRpc => self.rpc_args,
+        }
+    }
+
+    /// Path of the `.wasm` build of this toolchain.
+    pub fn wasm_path(&self) -> String {
+        wasm_module_path(self.name)
+    }
+
+    /// Builds the supervised process definition for this toolchain.
+    ///
+    /// WASM servers see nothing of the host filesystem; jobs needing files are run in a
+    /// sandbox of their own instead (see [`Executor::serve_in`](crate::Executor::serve_in)).
+    /// They run within the `limits` configured for this toolchain, passed as `--wasm-*` flags.
+    ///
+    /// Returns `None` when the toolchain does not support `runtime`, or when `runtime` is
+    /// [`Runtime::External`], which has no sensible default address.
+    pub fn process_config(
+        &self,
+        runtime: Runtime,
+        mode: ServeMode,
+        limits: &WasmLimitsConfig,
+    ) -> Option<ProcessConfig> {
+        if !self.supports(runtime) {
+            return None;
+        }
+        let args: Vec<String> = self
+            .serve_args(mode)
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let (command, args) = match runtime {
+            Runtime::Native => (self.name.to_string(), args),
+            Runtime::Wasm => {
+                let mut wasm_args = vec!["wasm_run".to_string(), self.name.to_string()];
+                wasm_args.extend(limits.for_language(self.name).to_args());
+                wasm_args.push("--".to_string());
+                wasm_args.extend(args);
+                (wasm_runner(), wasm_args)
+            }
+            Runtime::External => return None,
+        };
+        Some(ProcessConfig {
+            command: Some(command),
+            args: Some(args),
+            external_address: None,
+            max_retries: 5,
+            restart_delay_ms: 2000,
+        })
+    }
+}
+
+const NATIVE_WASM_EXTERNAL: &[Runtime] = &[Runtime::Native, Runtime::Wasm, Runtime::External];
+const NATIVE_EXTERNAL: &[Runtime] = &[Runtime::Native, Runtime::External];
+
+const fn toolchain(
+    name: &'static str,
+    runtimes: &'static [Runtime],
+    wasm_features: &'static [&'static str],
+) -> Toolchain {
+    Toolchain {
+        name,
+        runtimes,
+        wasm_features,
+        mcp_args: &["mcp"],
+        rpc_args: &["serve_json_rpc"],
+        targets: STANDARD_TARGETS,
+    }
+}
+
+/// Every toolchain known to `cdd-ctl`, in alphabetical order.
+const BUILTIN: &[Toolchain] = &[
+    toolchain("cdd-c", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-cpp", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-csharp", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-go", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-java", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-kotlin", NATIVE_WASM_EXTERNAL, &["gc"]),
+    toolchain("cdd-php", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-python", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-python-all", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-ruby", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-rust", NATIVE_WASM_EXTERNAL, &[]),
+    // Shell scripts are interpreted natively and have no WASM build.
+    toolchain("cdd-sh", NATIVE_EXTERNAL, &[]),
+    toolchain("cdd-swift", NATIVE_WASM_EXTERNAL, &[]),
+    toolchain("cdd-ts", NATIVE_WASM_EXTERNAL, &[]),
+];
+
+/// Lookup table over the known toolchains.
+#[derive(Debug, Clone, Copy)]
+pub struct ToolchainRegistry {
+    toolchains: &'static [Toolchain],
+}
+
+impl Default for ToolchainRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ToolchainRegistry {
+    /// The registry of toolchains bundled with `cdd-ctl`.
+    pub const fn builtin() -> Self {
+        Self {
+            toolchains: BUILTIN,
+        }
+    }
+
+    /// Iterates over every registered toolchain.
+    pub fn iter(&self) -> impl Iterator<Item = &'static Toolchain> {
+        self.toolchains.iter()
+    }
+
+    /// Iterates over the toolchains supporting `runtime`.
+    pub fn supporting(&self, runtime: Runtime) -> impl Iterator<Item = &'static Toolchain> {
+        self.toolchains.iter().filter(move |t| t.supports(runtime))
+    }
+
+    /// Looks up a toolchain by `cdd-<lang>` name or bare language identifier.
+    pub fn get(&self, name: &str) -> Option<&'static Toolchain> {
+        let name = executable_name(name);
+        self.toolchains.iter().find(|t| t.name == name)
+    }
+
+    /// Builds the default `servers` map for the given runtime and serve mode, WASM servers
+    /// running within `limits`.
+    pub fn process_configs(
+        &self,
+        runtime: Runtime,
+        mode: ServeMode,
+        limits: &WasmLimitsConfig,
+    ) -> HashMap<String, ProcessConfig> {
+        self.toolchains
+            .iter()
+            .filter_map(|t| {
+                t.process_config(runtime, mode, limits)
+                    .map(|config| (t.name.to_string(), config))
+            })
+            .collect()
+    }
+}
+
+/// Normalises a language argument (`go` or `cdd-go`) to its executable name (`cdd-go`).
+pub fn executable_name(language: &str) -> String {
+    if language.starts_with("cdd-") {
+        language.to_string()
+    } else {
+        format!("cdd-{}", language)
+    }
+}
+
+/// Path of the `.wasm` build of the toolchain with the given executable name.
+pub fn wasm_module_path(executable: &str) -> String {
+    format!("{}/{}.wasm", WASM_ASSETS_DIR, executable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::WasmLimits;
+
+    #[test]
+    fn names_and_languages_resolve_to_the_same_toolchain() {
+        let registry = ToolchainRegistry::builtin();
+        let go = registry.get("go").unwrap();
+        assert_eq!(registry.get("cdd-go"), Some(go));
+        assert_eq!(go.language(), "go");
+        assert!(registry.get("cobol").is_none());
+    }
+
+    #[test]
+    fn shell_toolchain_has_no_wasm_build() {
+        let registry = ToolchainRegistry::builtin();
+        assert!(registry
+            .supporting(Runtime::Native)
+            .any(|t| t.name == "cdd-sh"));
+        assert!(registry
+            .supporting(Runtime::Wasm)
+            .all(|t| t.name != "cdd-sh"));
+        let sh = registry.get("sh").unwrap();
+        let limits = WasmLimitsConfig::default();
+        assert!(sh
+            .process_config(Runtime::Wasm, ServeMode::JsonRpc, &limits)
+            .is_none());
+        assert!(sh
+            .process_config(Runtime::External, ServeMode::JsonRpc, &limits)
+            .is_none());
+    }
+
+    #[test]
+    fn kotlin_needs_wasm_gc() {
+        let registry = ToolchainRegistry::builtin();
+        assert_eq!(registry.get("kotlin").unwrap().wasm_features, ["gc"]);
+        assert!(registry
+            .iter()
+            .filter(|t| t.name != "cdd-kotlin")
+            .all(|t| t.wasm_features.is_empty()));
+    }
+
+    #[test]
+    fn wasm_servers_are_run_by_the_current_binary() {
+        let limits = WasmLimitsConfig {
+            default: WasmLimits {
+                timeout_ms: Some(5_000),
+                ..WasmLimits::default()
+            },
+            ..WasmLimitsConfig::default()
+        };
+        let configs = ToolchainRegistry::builtin().process_configs(
+            Runtime::Wasm,
+            ServeMode::JsonRpc,
+            &limits,
+        );
+        assert!(!configs.contains_key("cdd-sh"));
+        let go = &configs["cdd-go"];
+        assert_eq!(go.command.as_deref(), Some(wasm_runner().as_str()));
+        assert_eq!(
+            go.args.as_deref().unwrap(),
+            [
+                "wasm_run",
+                "cdd-go",
+                "--wasm-timeout-ms=5000",
+                "--",
+                "serve_json_rpc"
+            ]
+        );
+        assert_eq!(go.external_address, None);
+    }
+
+    #[test]
+    fn native_servers_run_the_toolchain_itself() {
+        let configs = ToolchainRegistry::builtin().process_configs(
+            Runtime::Native,
+            ServeMode::Mcp,
+            &WasmLimitsConfig::default(),
+        );
+        assert_eq!(configs.len(), BUILTIN.len());
+        let go = &configs["cdd-go"];
+        assert_eq!(go.command.as_deref(), Some("cdd-go"));
+        assert_eq!(go.args.as_deref().unwrap(), ["mcp"]);
+    }
+}
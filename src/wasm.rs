@@ -0,0 +1,544 @@
+//! Embedded `wasmtime` engine used to evaluate the `cdd-*.wasm` toolchains in-process.
+//!
+//! Replaces shelling out to the `wasmtime` CLI: modules are compiled once per engine
+//! configuration and cached (in memory and in the on-disk [`ModuleCache`]), stdout/stderr are
+//! captured into memory, and the directories exposed to the guest are configured explicitly
+//! through [`WasmOptions::preopens`]. Every run is bounded by the fuel, memory and wall-clock
+//! [`WasmLimits`] in [`WasmOptions::limits`].
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use wasmtime::{Config, Engine, Linker, Module, ResourceLimiter, Store, Trap};
+use wasmtime_wasi::p1::{self, WasiP1Ctx};
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+use crate::cache::ModuleCache;
+use crate::executor::{ExecError, LimitKind};
+use crate::metrics::{CacheLayer, Metrics};
+
+/// Upper bound on the stdout/stderr captured from a single run.
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024 * 1024;
+
+/// Interval at which every engine's epoch is advanced; the granularity of `timeout_ms`.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Resource limits applied to a single module execution.
+///
+/// Unset fields are unlimited. Limits are resolved per language from
+/// [`WasmLimitsConfig`] and can only be tightened per request, with [`WasmLimits::within`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasmLimits {
+    /// Units of fuel (roughly, WASM instructions) the module may consume.
+    pub fuel: Option<u64>,
+    /// Maximum size of any linear memory, in bytes.
+    pub max_memory_bytes: Option<usize>,
+    /// Wall-clock deadline for the whole execution, in milliseconds.
+    pub timeout_ms: Option<u64>,
+}
+
+impl WasmLimits {
+    /// Returns `self`, with every unset field taken from `fallback`.
+    pub fn or(self, fallback: WasmLimits) -> WasmLimits {
+        WasmLimits {
+            fuel: self.fuel.or(fallback.fuel),
+            max_memory_bytes: self.max_memory_bytes.or(fallback.max_memory_bytes),
+            timeout_ms: self.timeout_ms.or(fallback.timeout_ms),
+        }
+    }
+
+    /// Returns `self` capped by `limits`: each field is the smaller of the two, or whichever
+    /// is set. A request can lower the configured limits but never raise or lift them.
+    pub fn within(self, limits: WasmLimits) -> WasmLimits {
+        fn min<T: Ord>(requested: Option<T>, cap: Option<T>) -> Option<T> {
+            match (requested, cap) {
+                (Some(requested), Some(cap)) => Some(requested.min(cap)),
+                (requested, cap) => requested.or(cap),
+            }
+        }
+        WasmLimits {
+            fuel: min(self.fuel, limits.fuel),
+            max_memory_bytes: min(self.max_memory_bytes, limits.max_memory_bytes),
+            timeout_ms: min(self.timeout_ms, limits.timeout_ms),
+        }
+    }
+
+    /// The `--wasm-*` flags of `wasm_run` setting the limits that are set.
+    pub fn to_args(self) -> Vec<String> {
+        [
+            ("--wasm-fuel", self.fuel),
+            (
+                "--wasm-max-memory-bytes",
+                self.max_memory_bytes.map(|b| b as u64),
+            ),
+            ("--wasm-timeout-ms", self.timeout_ms),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| value.map(|value| format!("{}={}", flag, value)))
+        .collect()
+    }
+}
+
+/// Default and per-language WASM resource limits (`wasm_limits` in the configuration file).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasmLimitsConfig {
+    /// Limits applied to every language.
+    pub default: WasmLimits,
+    /// Per-language overrides, keyed by `cdd-<lang>` name.
+    pub languages: HashMap<String, WasmLimits>,
+}
+
+impl WasmLimitsConfig {
+    /// Limits for `executable`: its override, falling back to the defaults.
+    pub fn for_language(&self, executable: &str) -> WasmLimits {
+        self.languages
+            .get(executable)
+            .copied()
+            .unwrap_or_default()
+            .or(self.default)
+    }
+
+    /// Applies `overrides` on top of the defaults and every per-language entry.
+    pub fn overridden(mut self, overrides: WasmLimits) -> Self {
+        self.default = overrides.or(self.default);
+        for limits in self.languages.values_mut() {
+            *limits = overrides.or(*limits);
+        }
+        self
+    }
+}
+
+/// A host directory exposed to the guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preopen {
+    /// Directory on the host.
+    pub host: PathBuf,
+    /// Path the directory is mounted at inside the guest.
+    pub guest: String,
+}
+
+impl Preopen {
+    /// Mounts `host` at `guest`.
+    pub fn new(host: impl Into<PathBuf>, guest: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            guest: guest.into(),
+        }
+    }
+
+    /// Parses `HOST[::GUEST]`, mounting at the same path when no guest path is given.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once("::") {
+            Some((host, guest)) => Self::new(host, guest),
+            None => Self::new(spec, spec),
+        }
+    }
+}
+
+/// Per-run configuration of the WASI environment.
+#[derive(Debug, Clone, Default)]
+pub struct WasmOptions {
+    /// Directories exposed to the guest. Nothing on the host is visible unless listed here;
+    /// see [`Sandbox`](crate::sandbox::Sandbox) for an isolated per-job workspace.
+    pub preopens: Vec<Preopen>,
+    /// Environment variables visible to the guest.
+    pub env: Vec<(String, String)>,
+    /// Bytes fed to the guest's stdin.
+    pub stdin: Vec<u8>,
+    /// Connect the guest to the host's stdio instead of capturing it (for long-running servers).
+    pub inherit_stdio: bool,
+    /// Resource limits for this execution.
+    pub limits: WasmLimits,
+}
+
+/// Result of running a module to completion.
+#[derive(Debug, Clone, Default)]
+pub struct WasmOutput {
+    /// Exit code reported through `proc_exit`, or `0` when `_start` returned.
+    pub exit_code: i32,
+    /// Captured stdout (empty when stdio is inherited).
+    pub stdout: Vec<u8>,
+    /// Captured stderr (empty when stdio is inherited).
+    pub stderr: Vec<u8>,
+}
+
+/// Compiles and runs WASI preview 1 modules, caching engines and compiled modules.
+///
+/// Compiled modules are kept in memory for the lifetime of the engine and, when a
+/// [`ModuleCache`] is configured, persisted to disk across processes.
+#[derive(Default)]
+pub struct WasmEngine {
+    engines: Mutex<HashMap<Vec<String>, Engine>>,
+    modules: Mutex<HashMap<(PathBuf, Vec<String>), Module>>,
+    disk_cache: Option<ModuleCache>,
+}
+
+impl WasmEngine {
+    /// Creates an engine with empty in-memory caches and no on-disk cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an engine backed by the given on-disk cache of precompiled modules.
+    pub fn with_disk_cache(disk_cache: Option<ModuleCache>) -> Self {
+        Self {
+            disk_cache,
+            ..Self::default()
+        }
+    }
+
+    /// Process-wide engine shared by every caller, using [`ModuleCache::from_env`].
+    pub fn global() -> &'static WasmEngine {
+        static ENGINE: OnceLock<WasmEngine> = OnceLock::new();
+        ENGINE.get_or_init(|| WasmEngine::with_disk_cache(ModuleCache::from_env()))
+    }
+
+    /// The on-disk cache of precompiled modules, if enabled.
+    pub fn disk_cache(&self) -> Option<&ModuleCache> {
+        self.disk_cache.as_ref()
+    }
+
+    /// Returns the engine configured for `features`, creating it on first use.
+    pub fn engine(&self, features: &[&str]) -> Result<Engine, ExecError> {
+        let key = feature_key(features);
+        let mut engines = self.engines.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(engine) = engines.get(&key) {
+            return Ok(engine.clone());
+        }
+        let engine = Engine::new(&engine_config(features)?).map_err(wasm_error)?;
+        spawn_epoch_ticker(&engine);
+        engines.insert(key, engine.clone());
+        Ok(engine)
+    }
+
+    /// Returns the compiled module at `path`, compiling it on first use.
+    pub fn module(&self, path: &Path, features: &[&str]) -> Result<Module, ExecError> {
+        let key = (path.to_path_buf(), feature_key(features));
+        if let Some(module) = self
+            .modules
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+        {
+            Metrics::global().observe_wasm_cache(CacheLayer::Memory, true);
+            return Ok(module.clone());
+        }
+        Metrics::global().observe_wasm_cache(CacheLayer::Memory, false);
+        let engine = self.engine(features)?;
+        if !path.is_file() {
+            return Err(ExecError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} does not exist", path.display()),
+            )));
+        }
+        // Compile outside the lock: the larger modules take seconds to compile.
+        let module = match &self.disk_cache {
+            Some(cache) => cache.load_or_compile(&engine, path, &key.1)?,
+            None => Module::from_file(&engine, path).map_err(wasm_error)?,
+        };
+        self.modules
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, module.clone());
+        Ok(module)
+    }
+
+    /// Runs the module at `path` with `args` (excluding `argv[0]`) to completion.
+    ///
+    /// This blocks the calling thread; use `tokio::task::spawn_blocking` from async code.
+    pub fn run(
+        &self,
+        path: &Path,
+        features: &[&str],
+        args: &[String],
+        options: &WasmOptions,
+    ) -> Result<WasmOutput, ExecError> {
+        let module = self.module(path, features)?;
+        let engine = module.engine().clone();
+
+        let mut linker: Linker<RunState> = Linker::new(&engine);
+        p1::add_to_linker_sync(&mut linker, |state: &mut RunState| &mut state.wasi)
+            .map_err(wasm_error)?;
+
+        let argv0 = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.arg(&argv0).args(args);
+        for (key, value) in &options.env {
+            builder.env(key, value);
+        }
+        for preopen in &options.preopens {
+            builder
+                .preopened_dir(
+                    &preopen.host,
+                    &preopen.guest,
+                    DirPerms::all(),
+                    FilePerms::all(),
+                )
+                .map_err(|e| {
+                    ExecError::Wasm(format!("preopening {}: {:#}", preopen.host.display(), e))
+                })?;
+        }
+
+        let stdout = MemoryOutputPipe::new(MAX_CAPTURED_OUTPUT);
+        let stderr = MemoryOutputPipe::new(MAX_CAPTURED_OUTPUT);
+        if options.inherit_stdio {
+            builder.inherit_stdio();
+        } else {
+            builder
+                .stdin(MemoryInputPipe::new(options.stdin.clone()))
+                .stdout(stdout.clone())
+                .stderr(stderr.clone());
+        }
+
+        let limits = options.limits;
+        let mut store = Store::new(
+            &engine,
+            RunState {
+                wasi: builder.build_p1(),
+                limiter: MemoryLimiter {
+                    max_memory_bytes: limits.max_memory_bytes,
+                    exceeded: false,
+                },
+            },
+        );
+        store.limiter(|state| &mut state.limiter);
+        store
+            .set_fuel(limits.fuel.unwrap_or(u64::MAX))
+            .map_err(wasm_error)?;
+        // Without a timeout the deadline is pushed out far enough to never be reached.
+        let ticks = limits.timeout_ms.map_or(u64::MAX / 2, |ms| {
+            (ms / EPOCH_TICK.as_millis() as u64).max(1)
+        });
+        store.set_epoch_deadline(ticks);
+        store.epoch_deadline_trap();
+
+        let result = linker
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, "_start"))
+            .and_then(|start| start.call(&mut store, ()));
+
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(e) => {
+                if let Some(exit) = e.downcast_ref::<I32Exit>() {
+                    exit.0
+                } else if let Some(limit) = limit_hit(&e, store.data().limiter.exceeded) {
+                    return Err(ExecError::LimitExceeded {
+                        limit,
+                        stderr: stderr.contents().to_vec(),
+                    });
+                } else {
+                    return Err(wasm_error(e));
+                }
+            }
+        };
+        // A module that cannot grow its memory usually aborts with a non-zero exit code.
+        if exit_code != 0 && store.data().limiter.exceeded {
+            return Err(ExecError::LimitExceeded {
+                limit: LimitKind::Memory,
+                stderr: stderr.contents().to_vec(),
+            });
+        }
+
+        Ok(WasmOutput {
+            exit_code,
+            stdout: stdout.contents().to_vec(),
+            stderr: stderr.contents().to_vec(),
+        })
+    }
+}
+
+/// Per-execution store data.
+struct RunState {
+    wasi: WasiP1Ctx,
+    limiter: MemoryLimiter,
+}
+
+/// Caps linear memory growth, remembering whether the cap was hit.
+struct MemoryLimiter {
+    max_memory_bytes: Option<usize>,
+    exceeded: bool,
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if self.max_memory_bytes.is_some_and(|max| desired > max) {
+            self.exceeded = true;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Classifies a trap caused by one of the [`WasmLimits`].
+fn limit_hit(e: &wasmtime::Error, memory_exceeded: bool) -> Option<LimitKind> {
+    match e.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => Some(LimitKind::Fuel),
+        Some(Trap::Interrupt) => Some(LimitKind::Timeout),
+        _ if memory_exceeded => Some(LimitKind::Memory),
+        _ => None,
+    }
+}
+
+/// Advances `engine`'s epoch every [`EPOCH_TICK`] until the engine is dropped.
+fn spawn_epoch_ticker(engine: &Engine) {
+    let weak = engine.weak();
+    std::thread::Builder::new()
+        .name("wasm-epoch".to_string())
+        .spawn(move || {
+            while let Some(engine) = weak.upgrade() {
+                engine.increment_epoch();
+                drop(engine);
+                std::thread::sleep(EPOCH_TICK);
+            }
+        })
+        .map(drop)
+        .unwrap_or_else(|e| log::warn!("Failed to start WASM epoch ticker: {}", e));
+}
+
+fn feature_key(features: &[&str]) -> Vec<String> {
+    let mut key: Vec<String> = features.iter().map(|f| f.to_string()).collect();
+    key.sort();
+    key.dedup();
+    key
+}
+
+/// Translates `--wasm-features` style names into a [`Config`].
+///
+/// Fuel metering and epoch interruption are always enabled so that [`WasmLimits`] can be
+/// applied per execution without recompiling the module.
+fn engine_config(features: &[&str]) -> Result<Config, ExecError> {
+    let mut config = Config::new();
+    config.consume_fuel(true).epoch_interruption(true);
+    for feature in features {
+        match *feature {
+            "gc" => {
+                config.wasm_function_references(true).wasm_gc(true);
+            }
+            "function-references" => {
+                config.wasm_function_references(true);
+            }
+            "exceptions" => {
+                config.wasm_exceptions(true);
+            }
+            "tail-call" => {
+                config.wasm_tail_call(true);
+            }
+            "multi-memory" => {
+                config.wasm_multi_memory(true);
+            }
+            "memory64" => {
+                config.wasm_memory64(true);
+            }
+            "threads" => {
+                config.wasm_threads(true);
+            }
+            other => {
+                return Err(ExecError::Wasm(format!(
+                    "unsupported WASM feature `{}`",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(config)
+}
+
+fn wasm_error(e: wasmtime::Error) -> ExecError {
+    ExecError::Wasm(format!("{:#}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(
+        fuel: Option<u64>,
+        max_memory_bytes: Option<usize>,
+        timeout_ms: Option<u64>,
+    ) -> WasmLimits {
+        WasmLimits {
+            fuel,
+            max_memory_bytes,
+            timeout_ms,
+        }
+    }
+
+    #[test]
+    fn or_fills_unset_fields() {
+        let merged = limits(Some(1), None, None).or(limits(Some(2), Some(3), None));
+        assert_eq!(merged, limits(Some(1), Some(3), None));
+    }
+
+    #[test]
+    fn within_never_raises_configured_limits() {
+        let configured = limits(Some(1_000), Some(1 << 20), None);
+        let requested = limits(Some(u64::MAX), Some(1 << 10), Some(5_000));
+        assert_eq!(
+            requested.within(configured),
+            limits(Some(1_000), Some(1 << 10), Some(5_000))
+        );
+    }
+
+    #[test]
+    fn within_keeps_configured_limits_when_unset() {
+        let configured = limits(Some(1_000), None, Some(60_000));
+        assert_eq!(WasmLimits::default().within(configured), configured);
+    }
+
+    #[test]
+    fn to_args_renders_only_set_limits() {
+        assert_eq!(
+            limits(Some(1_000), None, Some(5_000)).to_args(),
+            ["--wasm-fuel=1000", "--wasm-timeout-ms=5000"]
+        );
+        assert!(WasmLimits::default().to_args().is_empty());
+    }
+
+    #[test]
+    fn for_language_falls_back_to_default() {
+        let config = WasmLimitsConfig {
+            default: limits(Some(1), Some(2), Some(3)),
+            languages: HashMap::from([("cdd-go".to_string(), limits(None, None, Some(30)))]),
+        };
+        assert_eq!(
+            config.for_language("cdd-go"),
+            limits(Some(1), Some(2), Some(30))
+        );
+        assert_eq!(config.for_language("cdd-rust"), config.default);
+    }
+
+    #[test]
+    fn overridden_applies_to_every_language() {
+        let config = WasmLimitsConfig {
+            default: limits(Some(1), None, None),
+            languages: HashMap::from([("cdd-go".to_string(), limits(Some(2), None, None))]),
+        }
+        .overridden(limits(None, None, Some(10)));
+        assert_eq!(config.default, limits(Some(1), None, Some(10)));
+        assert_eq!(config.languages["cdd-go"], limits(Some(2), None, Some(10)));
+    }
+}